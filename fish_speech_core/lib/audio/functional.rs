@@ -32,3 +32,20 @@ pub fn resample(pcm_data: &Tensor, from_rate: u32, to_rate: u32) -> Result<Tenso
     // Reshape to (num_channels, output_len)
     interpolated.reshape((num_channels, output_len))
 }
+
+/// Scales `pcm` towards `target_rms`, with the gain capped at `max_gain` either way and the
+/// result kept below full scale. Silence is left alone.
+pub fn normalize_loudness(pcm: &mut [f32], target_rms: f32, max_gain: f32) {
+    if pcm.is_empty() {
+        return;
+    }
+    let rms = (pcm.iter().map(|s| s * s).sum::<f32>() / pcm.len() as f32).sqrt();
+    if rms < 1e-6 {
+        return;
+    }
+    let peak = pcm.iter().fold(0.0f32, |m, s| m.max(s.abs()));
+    let gain = (target_rms / rms)
+        .clamp(1.0 / max_gain, max_gain)
+        .min(0.99 / peak);
+    pcm.iter_mut().for_each(|s| *s *= gain);
+}
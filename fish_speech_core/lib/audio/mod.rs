@@ -1,5 +1,6 @@
 pub mod functional;
 pub mod pcm_decode;
+pub mod seam;
 pub mod spectrogram;
 mod stft;
 pub mod wav;
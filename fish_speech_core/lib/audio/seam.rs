@@ -0,0 +1,92 @@
+use candle_core::{D, Result, Tensor};
+use std::f32::consts::PI;
+
+/// Prepends the last `context_frames` frames of `prev` to `codes` (`(.., n_codebooks, seqlen)`),
+/// so the vocoder sees where the previous chunk left off. Returns the frames prepended.
+pub fn with_left_context(
+    prev: Option<&Tensor>,
+    codes: &Tensor,
+    context_frames: usize,
+) -> Result<(Tensor, usize)> {
+    let Some(prev) = prev.filter(|_| context_frames > 0) else {
+        return Ok((codes.clone(), 0));
+    };
+    let prev_len = prev.dim(D::Minus1)?;
+    let n = context_frames.min(prev_len);
+    if n == 0 {
+        return Ok((codes.clone(), 0));
+    }
+    let context = prev.narrow(D::Minus1, prev_len - n, n)?;
+    Ok((Tensor::cat(&[&context, codes], D::Minus1)?, n))
+}
+
+/// Joins separately vocoded chunks into one signal.
+///
+/// Each chunk after the first is expected to start with audio for the previous chunk's last
+/// frames (see [`with_left_context`]). That audio is dropped, except for the part overlapping the
+/// held-back tail of the previous chunk, which is crossfaded with it.
+pub struct PcmJoiner {
+    crossfade_samples: usize,
+    /// End of the previous chunk, held back until the next one arrives to crossfade with
+    tail: Vec<f32>,
+}
+
+impl PcmJoiner {
+    pub fn new(crossfade_samples: usize) -> Self {
+        Self {
+            crossfade_samples,
+            tail: Vec::new(),
+        }
+    }
+
+    pub fn from_ms(crossfade_ms: f32, sample_rate: u32) -> Self {
+        Self::new((crossfade_ms.max(0.0) * sample_rate as f32 / 1000.0).round() as usize)
+    }
+
+    /// Adds a chunk whose first `context_samples` re-render the end of the previous chunk.
+    /// Returns the samples that are final.
+    pub fn push(&mut self, pcm: &[f32], context_samples: usize) -> Vec<f32> {
+        let context_samples = context_samples.min(pcm.len());
+        let overlap = self
+            .crossfade_samples
+            .min(context_samples)
+            .min(self.tail.len());
+        let (context, body) = pcm.split_at(context_samples);
+
+        let mut out = Vec::with_capacity(self.tail.len() + body.len());
+        let kept = self.tail.len() - overlap;
+        out.extend_from_slice(&self.tail[..kept]);
+        let incoming = &context[context.len() - overlap..];
+        for (i, (old, new)) in self.tail[kept..].iter().zip(incoming).enumerate() {
+            // Raised-cosine fade; both sides render the same frames, so they're correlated
+            let w = 0.5 - 0.5 * (PI * (i as f32 + 0.5) / overlap as f32).cos();
+            out.push(old * (1.0 - w) + new * w);
+        }
+
+        let held = self.crossfade_samples.min(body.len());
+        out.extend_from_slice(&body[..body.len() - held]);
+        self.tail = body[body.len() - held..].to_vec();
+        out
+    }
+
+    /// Inserts `n_samples` of silence after the chunks so far. The next chunk's context is then
+    /// dropped entirely rather than crossfaded.
+    pub fn pause(&mut self, n_samples: usize) -> Vec<f32> {
+        if n_samples == 0 {
+            return Vec::new();
+        }
+        let mut out = std::mem::take(&mut self.tail);
+        out.resize(out.len() + n_samples, 0.0);
+        out
+    }
+
+    /// Samples held back for the next seam
+    pub fn pending(&self) -> usize {
+        self.tail.len()
+    }
+
+    /// Flushes the held-back tail of the last chunk
+    pub fn finish(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.tail)
+    }
+}
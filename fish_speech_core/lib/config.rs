@@ -54,4 +54,49 @@ impl WhichLM {
             WhichModel::Fish1_5 => Self::Fish(WhichFishVersion::Fish1_5),
         }
     }
+
+    /// Codec frames per second of generated audio
+    pub fn frame_rate(&self) -> f64 {
+        match self {
+            Self::DualAR => 12.5,
+            _ => 21.535,
+        }
+    }
+}
+
+/// Weight quantization for the LM's linear layers
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhichQuantization {
+    #[value(name = "q8_0")]
+    Q8_0,
+
+    #[value(name = "q4_0")]
+    Q4_0,
+
+    #[value(name = "q4k")]
+    Q4K,
+}
+
+impl WhichQuantization {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Q8_0 => "q8_0",
+            Self::Q4_0 => "q4_0",
+            Self::Q4K => "q4k",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "q8_0" => Some(Self::Q8_0),
+            "q4_0" => Some(Self::Q4_0),
+            "q4k" => Some(Self::Q4K),
+            _ => None,
+        }
+    }
+
+    /// File name of the pre-quantized checkpoint written by the `quantize` binary
+    pub fn gguf_file_name(&self) -> String {
+        format!("model-{}.gguf", self.as_str())
+    }
 }
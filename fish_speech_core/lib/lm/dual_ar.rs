@@ -3,7 +3,7 @@ use super::ops::repeat_kv::repeat_kv;
 use anyhow;
 use candle_core::{D, DType, Device, IndexOp, Result, Tensor};
 use candle_nn::{
-    Embedding, Linear, Module, RmsNorm, VarBuilder, embedding, ops::silu, ops::softmax_last_dim,
+    Embedding, Module, RmsNorm, VarBuilder, embedding, ops::silu, ops::softmax_last_dim,
 };
 use serde::Deserialize;
 use serde_json;
@@ -12,6 +12,8 @@ use std::io::BufReader;
 use std::path::PathBuf;
 use tokenizers::Tokenizer;
 
+use super::lora::{LoraAdapter, LoraLinear};
+use super::quantized::{LinearLayer, LinearLoader};
 use crate::config::{WhichFishVersion, WhichLM};
 
 #[derive(Debug, Clone)]
@@ -116,43 +118,21 @@ impl BaseModelArgs {
 }
 
 pub struct FeedForward {
-    w1: Linear,
-    w2: Linear,
-    w3: Linear,
+    w1: LoraLinear,
+    w2: LoraLinear,
+    w3: LoraLinear,
 }
 
 impl FeedForward {
-    pub fn load(vb: &VarBuilder, config: &BaseModelArgs) -> Result<Self> {
-        let w1 = Linear::new(
-            vb.get(
-                (
-                    config.intermediate_size.unwrap_or(config.dim * 4),
-                    config.dim,
-                ),
-                "w1.weight",
-            )?,
-            None,
-        );
-        let w2 = Linear::new(
-            vb.get(
-                (
-                    config.dim,
-                    config.intermediate_size.unwrap_or(config.dim * 4),
-                ),
-                "w2.weight",
-            )?,
-            None,
-        );
-        let w3 = Linear::new(
-            vb.get(
-                (
-                    config.intermediate_size.unwrap_or(config.dim * 4),
-                    config.dim,
-                ),
-                "w3.weight",
-            )?,
-            None,
-        );
+    pub fn load(
+        vb: &VarBuilder,
+        config: &BaseModelArgs,
+        linear_loader: &LinearLoader,
+    ) -> Result<Self> {
+        let intermediate_size = config.intermediate_size.unwrap_or(config.dim * 4);
+        let w1 = LoraLinear::load(linear_loader, vb, (intermediate_size, config.dim), "w1")?;
+        let w2 = LoraLinear::load(linear_loader, vb, (config.dim, intermediate_size), "w2")?;
+        let w3 = LoraLinear::load(linear_loader, vb, (intermediate_size, config.dim), "w3")?;
         Ok(Self { w1, w2, w3 })
     }
 }
@@ -199,9 +179,13 @@ pub struct Attention {
     head_dim: usize,
     n_local_heads: usize,
     dim: usize,
-    wqkv: Linear,
-    wo: Linear,
+    wqkv: LoraLinear,
+    wo: LoraLinear,
     kv_cache: Option<(Tensor, Tensor)>,
+    /// Keep the attention weights of the last query position on each forward
+    capture_weights: bool,
+    /// (bsz, kv_seqlen), averaged over heads
+    captured: Option<Tensor>,
 }
 
 #[cfg(feature = "flash-attn")]
@@ -216,11 +200,15 @@ fn flash_attn(
 }
 
 impl Attention {
-    pub fn load(vb: &VarBuilder, config: &BaseModelArgs) -> Result<Self> {
+    pub fn load(
+        vb: &VarBuilder,
+        config: &BaseModelArgs,
+        linear_loader: &LinearLoader,
+    ) -> Result<Self> {
         let total_head_dim = (config.n_head + 2 * config.n_local_heads) * config.head_dim;
         // KQV for all heads, but in a batch
-        let wqkv = Linear::new(vb.get((total_head_dim, config.dim), "wqkv.weight")?, None);
-        let wo = Linear::new(vb.get((config.dim, config.dim), "wo.weight")?, None);
+        let wqkv = LoraLinear::load(linear_loader, vb, (total_head_dim, config.dim), "wqkv")?;
+        let wo = LoraLinear::load(linear_loader, vb, (config.dim, config.dim), "wo")?;
 
         let kv_cache = None;
 
@@ -233,6 +221,8 @@ impl Attention {
             wo,
             // TODO configure this, improve cache handling
             kv_cache,
+            capture_weights: false,
+            captured: None,
         })
     }
 
@@ -248,12 +238,11 @@ impl Attention {
         Ok((q_embed, k_embed))
     }
 
-    /// Standard inefficient SDPA
-    fn scaled_dot_product_attention(
+    /// Softmaxed (bsz, n_head, q_seqlen, kv_seqlen) attention weights
+    fn attention_weights(
         &self,
         query: &Tensor,
         key: &Tensor,
-        value: &Tensor,
         softmax_scale: f32,
         attn_mask: Option<&Tensor>,
     ) -> Result<Tensor> {
@@ -273,11 +262,36 @@ impl Attention {
                 masked_fill(&attn_weight, &repeated_mask, f32::NEG_INFINITY)?
             }
         };
-        let attn_weight = softmax_last_dim(&attn_weight)?;
+        softmax_last_dim(&attn_weight)
+    }
+
+    /// Standard inefficient SDPA
+    fn scaled_dot_product_attention(
+        &self,
+        query: &Tensor,
+        key: &Tensor,
+        value: &Tensor,
+        softmax_scale: f32,
+        attn_mask: Option<&Tensor>,
+    ) -> Result<Tensor> {
+        let attn_weight = self.attention_weights(query, key, softmax_scale, attn_mask)?;
         // Ignoring dropout until we implement training
         attn_weight.matmul(&value.contiguous()?)
     }
 
+    /// Starts or stops keeping attention weights; stopping drops any already captured
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture_weights = capture;
+        if !capture {
+            self.captured = None;
+        }
+    }
+
+    /// Weights of the last query position from the latest forward, as (bsz, kv_seqlen) F32
+    pub fn take_captured(&mut self) -> Option<Tensor> {
+        self.captured.take()
+    }
+
     pub fn forward(
         &mut self,
         x: &Tensor,
@@ -370,6 +384,13 @@ impl Attention {
         };
 
         let scale_factor = 1f32 / (self.head_dim as f32).sqrt();
+        if self.capture_weights {
+            // The last position attends to everything before it, so it needs no mask.
+            // Computed separately so flash attention stays on the fast path.
+            let last_query = query_states.narrow(2, seqlen - 1, 1)?;
+            let weights = self.attention_weights(&last_query, &key_states, scale_factor, None)?;
+            self.captured = Some(weights.to_dtype(DType::F32)?.mean(1)?.squeeze(1)?);
+        }
         let mask = if seqlen > 1 { Some(mask) } else { None };
         #[cfg(feature = "flash-attn")]
         let y = {
@@ -425,9 +446,13 @@ pub struct TransformerBlock {
 }
 
 impl TransformerBlock {
-    pub fn load(vb: &VarBuilder, cfg: &BaseModelArgs) -> Result<Self> {
-        let attention = Attention::load(&vb.pp("attention"), cfg)?;
-        let feed_forward = FeedForward::load(&vb.pp("feed_forward"), cfg)?;
+    pub fn load(
+        vb: &VarBuilder,
+        cfg: &BaseModelArgs,
+        linear_loader: &LinearLoader,
+    ) -> Result<Self> {
+        let attention = Attention::load(&vb.pp("attention"), cfg, linear_loader)?;
+        let feed_forward = FeedForward::load(&vb.pp("feed_forward"), cfg, linear_loader)?;
         let ffn_norm = RmsNorm::new(vb.get(cfg.dim, "ffn_norm.weight")?, cfg.norm_eps);
         let attention_norm = RmsNorm::new(vb.get(cfg.dim, "attention_norm.weight")?, cfg.norm_eps);
 
@@ -439,6 +464,17 @@ impl TransformerBlock {
         })
     }
 
+    /// Linear layers that LoRA adapters can target
+    fn lora_linears_mut(&mut self) -> [&mut LoraLinear; 5] {
+        [
+            &mut self.attention.wqkv,
+            &mut self.attention.wo,
+            &mut self.feed_forward.w1,
+            &mut self.feed_forward.w2,
+            &mut self.feed_forward.w3,
+        ]
+    }
+
     pub fn forward(
         &mut self,
         x: &Tensor,
@@ -459,14 +495,15 @@ pub struct DualARTransformer {
     fast_layers: Vec<TransformerBlock>,
     pub fast_embeddings: Embedding,
     layers: Vec<TransformerBlock>,
-    output: Linear,
-    fast_output: Linear,
+    output: LinearLayer,
+    fast_output: LinearLayer,
     norm: RmsNorm,
     fast_norm: RmsNorm,
     freqs_cis: (Tensor, Tensor),
     pub cfg: BaseModelArgs,
     pub token_config: TokenConfig,
     pub model_type: WhichLM,
+    lora_adapters: Vec<String>,
 }
 
 impl DualARTransformer {
@@ -475,6 +512,23 @@ impl DualARTransformer {
         cfg: &BaseModelArgs,
         token_config: &TokenConfig,
         model_type: WhichLM,
+    ) -> Result<Self> {
+        Self::load_with(
+            vb,
+            cfg,
+            token_config,
+            model_type,
+            &LinearLoader::full_precision(),
+        )
+    }
+
+    /// Loads the model, materializing linear layers (possibly quantized) through `linear_loader`
+    pub fn load_with(
+        vb: &VarBuilder,
+        cfg: &BaseModelArgs,
+        token_config: &TokenConfig,
+        model_type: WhichLM,
+        linear_loader: &LinearLoader,
     ) -> Result<Self> {
         let embeddings = embedding(cfg.vocab_size, cfg.dim, vb.pp("embeddings"))?;
         let codebook_embeddings = Embedding::new(
@@ -485,22 +539,19 @@ impl DualARTransformer {
             cfg.dim,
         );
         let layers: Result<Vec<TransformerBlock>> = (0..cfg.n_layer)
-            .map(|l| TransformerBlock::load(&vb.pp(format!("layers.{}", l)), cfg))
+            .map(|l| TransformerBlock::load(&vb.pp(format!("layers.{}", l)), cfg, linear_loader))
             .collect();
         let layers = layers?;
         let norm = RmsNorm::new(vb.get(cfg.dim, "norm.weight")?, cfg.norm_eps);
-        let output = Linear::new(
-            vb.get(
-                (cfg.vocab_size, cfg.dim),
-                if cfg.tie_word_embeddings {
-                    "embeddings.weight"
-                } else {
-                    "output.weight"
-                },
-            )?
-            .contiguous()?,
-            None,
-        );
+        let output = linear_loader.load(
+            vb,
+            (cfg.vocab_size, cfg.dim),
+            if cfg.tie_word_embeddings {
+                "embeddings.weight"
+            } else {
+                "output.weight"
+            },
+        )?;
         let fast_emb_dim = match cfg.depthwise_wte {
             Some(true) => (cfg.num_codebooks - 1) * cfg.codebook_size,
             _ => cfg.codebook_size,
@@ -510,7 +561,9 @@ impl DualARTransformer {
             cfg.dim,
         );
         let fast_layers: Result<Vec<TransformerBlock>> = (0..cfg.n_fast_layer)
-            .map(|l| TransformerBlock::load(&vb.pp(format!("fast_layers.{}", l)), cfg))
+            .map(|l| {
+                TransformerBlock::load(&vb.pp(format!("fast_layers.{}", l)), cfg, linear_loader)
+            })
             .collect();
         let fast_layers = fast_layers?;
         let fast_norm = RmsNorm::new(vb.get(cfg.dim, "fast_norm.weight")?, cfg.norm_eps);
@@ -518,10 +571,13 @@ impl DualARTransformer {
             Some(true) => cfg.codebook_size * cfg.num_codebooks,
             _ => cfg.codebook_size,
         };
-        let fast_output = Linear::new(
-            vb.get((fast_output_size, cfg.dim), "fast_output.weight")?,
-            None,
-        );
+        let fast_output = match cfg.depthwise_output {
+            // Sliced per codebook at runtime, so keep it dense
+            Some(true) => {
+                linear_loader.load_full(vb, (fast_output_size, cfg.dim), "fast_output.weight")?
+            }
+            _ => linear_loader.load(vb, (fast_output_size, cfg.dim), "fast_output.weight")?,
+        };
         let freqs_cis = precompute_freqs_cis(cfg, vb.device(), vb.dtype())?;
 
         Ok(Self {
@@ -538,6 +594,7 @@ impl DualARTransformer {
             freqs_cis,
             token_config: token_config.clone(),
             model_type,
+            lora_adapters: Vec::new(),
         })
     }
 
@@ -590,10 +647,35 @@ impl DualARTransformer {
         input_pos: usize,
         pad_mask: Option<Tensor>,
     ) -> Result<(Tensor, Tensor)> {
+        let x = self.forward_slow(inp, input_pos, pad_mask)?;
+        let seq_len = x.dim(1)?;
+        let x = x.narrow(1, seq_len - 1, 1)?;
+        let slow_out = self.norm.forward(&x)?;
+        let token_logits = self.output.forward(&slow_out)?;
+
+        // Only calculate the logits of last_token
+        Ok((token_logits, x))
+    }
+
+    /// Slow transformer over the whole input; returns pre-norm hidden states at every position
+    fn forward_slow(
+        &mut self,
+        inp: &Tensor,
+        input_pos: usize,
+        pad_mask: Option<Tensor>,
+    ) -> Result<Tensor> {
         // println!("{:?}", inp.to_device(&Device::Cpu)?.to_vec3::<u32>());
         let mut x = self.embed(inp)?;
         // println!("Embedded");
         let (bsz, seq_len, _) = x.dims3()?;
+        if input_pos + seq_len > self.cfg.max_seq_len {
+            candle_core::bail!(
+                "Cannot run {} tokens at position {}: exceeds max_seq_len {}",
+                seq_len,
+                input_pos,
+                self.cfg.max_seq_len
+            );
+        }
 
         let mask = match seq_len {
             1 => self.get_mask_abs(1, 1, x.device())?,
@@ -638,13 +720,105 @@ impl DualARTransformer {
                 ),
             )?;
         }
+        Ok(x)
+    }
 
-        let x = x.narrow(1, seq_len - 1, 1)?;
-        let slow_out = self.norm.forward(&x)?;
-        let token_logits = self.output.forward(&slow_out)?;
+    /// Teacher-forced pass over a prompt followed by `codes` (num_codebooks, T), as the
+    /// generator would have fed them back.
+    ///
+    /// Returns (slow logits (T + 1, vocab), fast logits (T, num_codebooks, codebook_size)).
+    /// Slow logits at `t` predict frame `t`'s semantic token; the last row predicts what follows
+    /// the final frame. The slow KV cache is restored to its previous length afterwards.
+    pub fn forward_teacher_forced(
+        &mut self,
+        prompt: &Tensor,
+        codes: &Tensor,
+    ) -> Result<(Tensor, Tensor)> {
+        let (n_codebooks, n_frames) = codes.dims2()?;
+        if n_codebooks != self.cfg.num_codebooks {
+            candle_core::bail!(
+                "Expected {} codebooks but got {}",
+                self.cfg.num_codebooks,
+                n_codebooks
+            );
+        }
+        if n_frames == 0 {
+            candle_core::bail!("Cannot score an empty code sequence");
+        }
+        let codes = codes.to_dtype(DType::U32)?;
+        let semantic_row = match self.model_type {
+            WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => {
+                (codes.i(0)?.to_dtype(DType::F32)? + self.token_config.semantic_start_id as f64)?
+                    .to_dtype(DType::U32)?
+            }
+            _ => Tensor::full(self.token_config.pad_id, n_frames, codes.device())?,
+        };
+        let frames = Tensor::cat(&[semantic_row.unsqueeze(0)?, codes.clone()], 0)?;
+        let inp = Tensor::cat(&[prompt, &frames], 1)?.unsqueeze(0)?;
+        let prompt_len = prompt.dim(1)?;
+
+        let n_cached = self.curr_kv_size()?;
+        let hidden = self.forward_slow(&inp, n_cached, None);
+        match n_cached {
+            0 => self.clear_slow_layer_caches(),
+            _ => self.clear_slow_caches_until(n_cached)?,
+        }
+        let hidden = hidden?.i((0, prompt_len - 1..))?;
+
+        let slow_logits = self
+            .output
+            .forward(&self.norm.forward(&hidden.unsqueeze(0)?)?)?
+            .squeeze(0)?;
+
+        // Fast transformer: every frame as its own sequence, [hidden, code_0, .., code_{n-2}]
+        let code_embeds = (0..n_codebooks - 1)
+            .map(|idx| {
+                let row = codes.i(idx)?;
+                let row = match self.cfg.depthwise_wte {
+                    Some(true) => (row.to_dtype(DType::F32)?
+                        + (idx * self.cfg.codebook_size) as f64)?
+                        .to_dtype(DType::U32)?,
+                    _ => row,
+                };
+                self.fast_embeddings.forward(&row)?.unsqueeze(1)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let fast_hidden = hidden.i(..n_frames)?.unsqueeze(1)?;
+        let mut x =
+            Tensor::cat(&[&[fast_hidden], code_embeds.as_slice()].concat(), 1)?.contiguous()?;
+        let mask = self
+            .get_mask_abs(n_codebooks, n_codebooks, x.device())?
+            .unsqueeze(0)?
+            .unsqueeze(0)?
+            .expand((n_frames, 1, n_codebooks, n_codebooks))?;
+        let (cos_full, sin_full) = &self.freqs_cis;
+        let freqs_cis = (&cos_full.i(..n_codebooks)?, &sin_full.i(..n_codebooks)?);
+        self.clear_fast_layer_caches();
+        for layer in self.fast_layers.iter_mut() {
+            x = layer.forward(&x, &mask, freqs_cis)?;
+        }
+        self.clear_fast_layer_caches();
+        let fast_out = self.fast_norm.forward(&x)?;
 
-        // Only calculate the logits of last_token
-        Ok((token_logits, x))
+        let fast_logits = match self.cfg.depthwise_output {
+            Some(true) => {
+                let weights = self.fast_output.weight()?;
+                let logits = (0..n_codebooks)
+                    .map(|idx| {
+                        let slice = weights.i((
+                            idx * self.cfg.codebook_size..(idx + 1) * self.cfg.codebook_size,
+                            ..,
+                        ))?;
+                        fast_out
+                            .i((.., idx..idx + 1, ..))?
+                            .broadcast_matmul(&slice.t()?)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Tensor::cat(&logits, 1)?
+            }
+            _ => self.fast_output.forward(&fast_out)?,
+        };
+        Ok((slow_logits, fast_logits))
     }
 
     /// Returns codebook_logits only
@@ -674,7 +848,7 @@ impl DualARTransformer {
 
         match self.cfg.depthwise_output {
             Some(true) => {
-                let weights = self.fast_output.weight();
+                let weights = self.fast_output.weight()?;
                 let slice = weights.i((
                     input_pos * self.cfg.codebook_size..(input_pos + 1) * self.cfg.codebook_size,
                     ..,
@@ -685,6 +859,95 @@ impl DualARTransformer {
         }
     }
 
+    /// Attaches an unmerged LoRA adapter, selectable later by name with `set_lora_adapter`
+    pub fn add_lora_adapter(&mut self, adapter: &LoraAdapter) -> Result<()> {
+        if self.lora_adapters.contains(&adapter.name) {
+            candle_core::bail!("LoRA adapter {} is already loaded", adapter.name);
+        }
+        let n_matched = self
+            .layers
+            .iter_mut()
+            .chain(self.fast_layers.iter_mut())
+            .flat_map(|layer| layer.lora_linears_mut())
+            .map(|linear| linear.add_adapter(adapter))
+            .filter(|matched| *matched)
+            .count();
+        if n_matched != adapter.weights.len() {
+            self.remove_lora_adapter(&adapter.name);
+            candle_core::bail!(
+                "LoRA adapter {} has {} layers but only {} match this model",
+                adapter.name,
+                adapter.weights.len(),
+                n_matched
+            );
+        }
+        self.lora_adapters.push(adapter.name.clone());
+        Ok(())
+    }
+
+    pub fn remove_lora_adapter(&mut self, name: &str) {
+        for layer in self.layers.iter_mut().chain(self.fast_layers.iter_mut()) {
+            for linear in layer.lora_linears_mut() {
+                linear.remove_adapter(name);
+            }
+        }
+        self.lora_adapters.retain(|n| n != name);
+    }
+
+    /// Selects the adapter applied in subsequent forward passes; `None` uses base weights
+    pub fn set_lora_adapter(&mut self, name: Option<&str>) -> Result<()> {
+        if let Some(name) = name
+            && !self.lora_adapters.iter().any(|n| n == name)
+        {
+            candle_core::bail!("Unknown LoRA adapter: {}", name);
+        }
+        for layer in self.layers.iter_mut().chain(self.fast_layers.iter_mut()) {
+            for linear in layer.lora_linears_mut() {
+                linear.set_active(name);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lora_adapters(&self) -> &[String] {
+        &self.lora_adapters
+    }
+
+    /// Captures attention weights from the given slow layers on every forward, for alignment.
+    /// An empty slice turns capture off.
+    pub fn set_attention_capture(&mut self, layers: &[usize]) -> Result<()> {
+        if let Some(&layer) = layers.iter().find(|&&l| l >= self.layers.len()) {
+            candle_core::bail!(
+                "Cannot capture attention from layer {}: model has {} layers",
+                layer,
+                self.layers.len()
+            );
+        }
+        for (i, layer) in self.layers.iter_mut().enumerate() {
+            layer.attention.set_capture(layers.contains(&i));
+        }
+        Ok(())
+    }
+
+    pub fn is_capturing_attention(&self) -> bool {
+        self.layers.iter().any(|l| l.attention.capture_weights)
+    }
+
+    /// Attention of the last position from the latest slow forward, averaged over the captured
+    /// layers: (bsz, kv_seqlen). `None` if capture is off.
+    pub fn take_attention(&mut self) -> Result<Option<Tensor>> {
+        let captured: Vec<Tensor> = self
+            .layers
+            .iter_mut()
+            .filter_map(|l| l.attention.take_captured())
+            .collect();
+        if captured.is_empty() {
+            return Ok(None);
+        }
+        let n = captured.len() as f64;
+        Ok(Some((Tensor::stack(&captured, 0)?.sum(0)? / n)?))
+    }
+
     pub fn clear_fast_layer_caches(&mut self) {
         for layer in self.fast_layers.iter_mut() {
             layer.attention.clear_cache();
@@ -712,6 +975,11 @@ impl DualARTransformer {
         }
     }
 
+    /// Positions left in the context window after the current slow KV cache
+    pub fn remaining_context(&self) -> Result<usize> {
+        Ok(self.cfg.max_seq_len.saturating_sub(self.curr_kv_size()?))
+    }
+
     fn get_mask_abs(&self, size1: usize, size2: usize, device: &Device) -> Result<Tensor> {
         let context = self.cfg.max_seq_len;
         let mask: Vec<_> = (0..size1)
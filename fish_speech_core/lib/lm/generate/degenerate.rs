@@ -0,0 +1,207 @@
+use super::rerank::codebook_entropies;
+use crate::text::clean::estimate_syllables;
+use candle_core::{DType, Result, Tensor};
+use std::collections::HashSet;
+use std::fmt;
+
+/// Thresholds for flagging a generated take as degenerate
+#[derive(Debug, Clone)]
+pub struct DegenerateConfig {
+    /// Longest run of identical frames allowed, in seconds (stuck audio or long silence)
+    pub max_repeated_seconds: f64,
+    /// Frames per n-gram when looking for loops
+    pub ngram_size: usize,
+    /// Largest fraction of frame n-grams that may repeat an earlier one
+    pub max_ngram_repeat_ratio: f32,
+    /// Speaking rate bounds in estimated syllables per second; looser than reranking
+    pub min_syllables_per_second: f32,
+    pub max_syllables_per_second: f32,
+    /// Lowest normalized entropy any codebook may have
+    pub min_codebook_entropy: f32,
+    /// Variance checks need enough frames to mean anything
+    pub min_frames_for_variance: usize,
+}
+
+impl Default for DegenerateConfig {
+    fn default() -> Self {
+        Self {
+            max_repeated_seconds: 1.5,
+            ngram_size: 4,
+            max_ngram_repeat_ratio: 0.3,
+            min_syllables_per_second: 0.75,
+            max_syllables_per_second: 12.0,
+            min_codebook_entropy: 0.25,
+            min_frames_for_variance: 32,
+        }
+    }
+}
+
+/// How many times to regenerate a degenerate chunk, and how to perturb sampling each time
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    /// Added to the sampling temperature on every retry
+    pub temperature_bump: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 1,
+            temperature_bump: 0.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Degeneracy {
+    /// Never emitted <|im_end|>
+    HitTokenLimit {
+        frames: usize,
+    },
+    TooShort {
+        seconds: f64,
+        syllables: f32,
+    },
+    TooLong {
+        seconds: f64,
+        syllables: f32,
+    },
+    /// Same frame repeated for a long stretch
+    RepeatedFrames {
+        run: usize,
+        start: usize,
+    },
+    /// Large share of frame n-grams repeat earlier ones
+    Looping {
+        repeat_ratio: f32,
+    },
+    LowVariance {
+        codebook: usize,
+        entropy: f32,
+    },
+}
+
+impl Degeneracy {
+    /// Short machine-readable name, e.g. for response headers
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::HitTokenLimit { .. } => "hit_token_limit",
+            Self::TooShort { .. } => "too_short",
+            Self::TooLong { .. } => "too_long",
+            Self::RepeatedFrames { .. } => "repeated_frames",
+            Self::Looping { .. } => "looping",
+            Self::LowVariance { .. } => "low_variance",
+        }
+    }
+}
+
+impl fmt::Display for Degeneracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HitTokenLimit { frames } => write!(f, "hit token limit at {} frames", frames),
+            Self::TooShort { seconds, syllables } => {
+                write!(
+                    f,
+                    "{:.2}s is too short for ~{:.0} syllables",
+                    seconds, syllables
+                )
+            }
+            Self::TooLong { seconds, syllables } => {
+                write!(
+                    f,
+                    "{:.2}s is too long for ~{:.0} syllables",
+                    seconds, syllables
+                )
+            }
+            Self::RepeatedFrames { run, start } => {
+                write!(f, "frame repeated {} times from frame {}", run, start)
+            }
+            Self::Looping { repeat_ratio } => {
+                write!(f, "{:.0}% of frame n-grams repeat", repeat_ratio * 100.0)
+            }
+            Self::LowVariance { codebook, entropy } => {
+                write!(f, "codebook {} entropy {:.3}", codebook, entropy)
+            }
+        }
+    }
+}
+
+/// Checks generated `codes` (num_codebooks, T) for signs of a failed generation.
+///
+/// `text` enables the duration check; `max_new_tokens` flags takes that never ended.
+/// Returns every issue found, empty if the take looks fine.
+pub fn detect_degenerate(
+    codes: &Tensor,
+    text: Option<&str>,
+    frame_rate: f64,
+    max_new_tokens: Option<usize>,
+    config: &DegenerateConfig,
+) -> Result<Vec<Degeneracy>> {
+    let rows = codes.to_dtype(DType::U32)?.to_vec2::<u32>()?;
+    let n_frames = rows.first().map(Vec::len).unwrap_or(0);
+    let frames: Vec<Vec<u32>> = (0..n_frames)
+        .map(|t| rows.iter().map(|row| row[t]).collect())
+        .collect();
+    let mut issues = Vec::new();
+
+    if let Some(max_new_tokens) = max_new_tokens
+        && n_frames >= max_new_tokens
+    {
+        issues.push(Degeneracy::HitTokenLimit { frames: n_frames });
+    }
+
+    if let Some(text) = text {
+        let syllables = estimate_syllables(text).max(1.0);
+        let seconds = n_frames as f64 / frame_rate;
+        let rate = syllables / seconds.max(1e-3) as f32;
+        if rate > config.max_syllables_per_second {
+            issues.push(Degeneracy::TooShort { seconds, syllables });
+        } else if rate < config.min_syllables_per_second {
+            issues.push(Degeneracy::TooLong { seconds, syllables });
+        }
+    }
+
+    // Longest run of identical consecutive frames
+    let (mut run, mut start, mut best_run, mut best_start) = (1, 0, 1, 0);
+    for t in 1..n_frames {
+        if frames[t] == frames[t - 1] {
+            run += 1;
+        } else {
+            run = 1;
+            start = t;
+        }
+        if run > best_run {
+            (best_run, best_start) = (run, start);
+        }
+    }
+    if best_run as f64 > config.max_repeated_seconds * frame_rate {
+        issues.push(Degeneracy::RepeatedFrames {
+            run: best_run,
+            start: best_start,
+        });
+    }
+
+    if n_frames >= 2 * config.ngram_size {
+        let ngrams: Vec<&[Vec<u32>]> = frames.windows(config.ngram_size).collect();
+        let unique: HashSet<&[Vec<u32>]> = ngrams.iter().copied().collect();
+        let repeat_ratio = 1.0 - unique.len() as f32 / ngrams.len() as f32;
+        if repeat_ratio > config.max_ngram_repeat_ratio {
+            issues.push(Degeneracy::Looping { repeat_ratio });
+        }
+    }
+
+    if n_frames >= config.min_frames_for_variance {
+        let lowest = codebook_entropies(codes)?
+            .into_iter()
+            .enumerate()
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        if let Some((codebook, entropy)) = lowest
+            && entropy < config.min_codebook_entropy
+        {
+            issues.push(Degeneracy::LowVariance { codebook, entropy });
+        }
+    }
+
+    Ok(issues)
+}
@@ -0,0 +1,137 @@
+use super::single_batch::SingleBatchGenerator;
+use crate::lm::DualARTransformer;
+use crate::lm::sampling::SamplingArgs;
+use candle_core::{D, IndexOp, Result, Tensor};
+use tokenizers::Tokenizer;
+
+/// One step of text + audio generation
+#[derive(Debug, Clone)]
+pub enum GenerationEvent {
+    /// `text` is the newly decoded text; empty while a multi-byte character is incomplete
+    Text { token: u32, text: String },
+    /// (num_codebooks, 1) LM codes for one frame
+    Audio { codes: Tensor },
+    /// `finished` is false if generation ran out of tokens before `<|im_end|>`
+    End { finished: bool },
+}
+
+/// Generates text tokens and audio frames from the full vocab, for DualAR models that produce
+/// inner-monologue text alongside speech
+pub struct InterleavedGenerator<'a> {
+    generator: SingleBatchGenerator<'a>,
+    tokenizer: &'a Tokenizer,
+    im_end_id: u32,
+    text_tokens: Vec<u32>,
+    /// Decoded text already emitted
+    emitted: String,
+    done: bool,
+}
+
+impl<'a> InterleavedGenerator<'a> {
+    pub fn new(
+        model: &'a mut DualARTransformer,
+        tokenizer: &'a Tokenizer,
+        prompt: &Tensor,
+        max_new_tokens: usize,
+        sampling_args: &SamplingArgs,
+    ) -> Result<Self> {
+        let im_end_id = model.token_config.im_end_id;
+        let generator =
+            SingleBatchGenerator::new(model, prompt, max_new_tokens, sampling_args, false)?;
+        Ok(Self {
+            generator,
+            tokenizer,
+            im_end_id,
+            text_tokens: Vec::new(),
+            emitted: String::new(),
+            done: false,
+        })
+    }
+
+    /// Decodes all text so far and returns the part not yet emitted
+    fn decode_delta(&mut self, token: u32) -> Result<String> {
+        self.text_tokens.push(token);
+        let decoded = self
+            .tokenizer
+            .decode(&self.text_tokens, false)
+            .map_err(candle_core::Error::msg)?;
+        // Hold back until a split multi-byte character is complete
+        if decoded.ends_with('\u{FFFD}') {
+            return Ok(String::new());
+        }
+        // Emitted text can't be taken back: if decoding rewrote it, emit nothing this time and
+        // carry on from the new text
+        let delta = decoded
+            .strip_prefix(self.emitted.as_str())
+            .unwrap_or_default()
+            .to_string();
+        self.emitted = decoded;
+        Ok(delta)
+    }
+}
+
+impl Iterator for InterleavedGenerator<'_> {
+    type Item = Result<GenerationEvent>;
+
+    fn next(&mut self) -> Option<Result<GenerationEvent>> {
+        if self.done {
+            return None;
+        }
+        let Some(step) = self.generator.next() else {
+            self.done = true;
+            return Some(Ok(GenerationEvent::End { finished: false }));
+        };
+        let result = (|| {
+            let step = step?;
+            let token = step.tokens[0];
+            if token == self.im_end_id {
+                self.done = true;
+                Ok(GenerationEvent::End { finished: true })
+            } else if step.is_audio {
+                Ok(GenerationEvent::Audio {
+                    codes: step.codes.i((1.., ..))?,
+                })
+            } else {
+                Ok(GenerationEvent::Text {
+                    token,
+                    text: self.decode_delta(token)?,
+                })
+            }
+        })();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+/// Runs interleaved generation to completion, returning the text and any
+/// (num_codebooks, n_frames) codes
+pub fn generate_interleaved(
+    model: &mut DualARTransformer,
+    tokenizer: &Tokenizer,
+    prompt: &Tensor,
+    max_new_tokens: usize,
+    sampling_args: &SamplingArgs,
+) -> Result<(String, Option<Tensor>)> {
+    let generator =
+        InterleavedGenerator::new(model, tokenizer, prompt, max_new_tokens, sampling_args)?;
+    let mut text = String::new();
+    let mut frames = Vec::new();
+    for event in generator {
+        match event? {
+            GenerationEvent::Text { text: delta, .. } => text.push_str(&delta),
+            GenerationEvent::Audio { codes } => frames.push(codes),
+            GenerationEvent::End { finished } => {
+                if !finished {
+                    tracing::warn!("Interleaved generation hit the token limit");
+                }
+            }
+        }
+    }
+    let codes = match frames.is_empty() {
+        true => None,
+        false => Some(Tensor::cat(&frames, D::Minus1)?),
+    };
+    Ok((text, codes))
+}
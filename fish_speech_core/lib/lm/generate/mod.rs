@@ -1,6 +1,18 @@
+pub mod degenerate;
+pub mod interleaved;
+pub mod rerank;
+pub mod score;
+pub mod session;
 pub mod single_batch;
 pub mod static_batch;
 mod utils;
 
+pub use interleaved::{GenerationEvent, InterleavedGenerator, generate_interleaved};
+pub use score::{CodeScores, score_codes};
+pub use session::{
+    CancellationToken, FinishReason, GenerationSession, LogProgress, NoProgress, ProgressReporter,
+    SessionEvent, SessionOutput, SpinnerProgress, generate_with_progress, run_session,
+};
 pub use single_batch::{SingleBatchGenerator, generate_blocking, generate_blocking_with_hidden};
 pub use static_batch::{BatchGenerator, generate_static_batch};
+pub use utils::{clamp_to_context, lm_codes_to_codec};
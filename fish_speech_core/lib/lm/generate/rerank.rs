@@ -0,0 +1,186 @@
+use super::score::{CodeScores, score_codes};
+use super::static_batch::generate_static_batch;
+use crate::lm::DualARTransformer;
+use crate::lm::sampling::SamplingArgs;
+use crate::text::clean::estimate_syllables;
+use candle_core::{D, DType, Result, Tensor};
+
+/// Heuristic weights and bounds for picking the best of several candidate takes
+#[derive(Debug, Clone)]
+pub struct RerankConfig {
+    /// Plausible speaking rate, in estimated syllables per second
+    pub min_syllables_per_second: f32,
+    pub max_syllables_per_second: f32,
+    /// Penalty per unit of log-ratio outside the plausible speaking rate
+    pub duration_weight: f32,
+    /// Mean normalized codebook entropy below which a take is considered stuck or silent
+    pub min_entropy: f32,
+    /// Penalty per unit of entropy below `min_entropy`
+    pub entropy_weight: f32,
+}
+
+impl Default for RerankConfig {
+    fn default() -> Self {
+        Self {
+            min_syllables_per_second: 1.5,
+            max_syllables_per_second: 9.0,
+            duration_weight: 4.0,
+            min_entropy: 0.5,
+            entropy_weight: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CandidateScore {
+    pub scores: CodeScores,
+    pub duration_penalty: f32,
+    pub entropy: f32,
+    pub entropy_penalty: f32,
+    /// Mean per-frame log-likelihood minus penalties; higher is better
+    pub total: f32,
+}
+
+/// Log-distance of the implied speaking rate from the plausible range; 0 when inside it
+pub fn duration_penalty(
+    text: &str,
+    n_frames: usize,
+    frame_rate: f64,
+    config: &RerankConfig,
+) -> f32 {
+    let syllables = estimate_syllables(text).max(1.0);
+    let seconds = (n_frames as f64 / frame_rate).max(1e-3) as f32;
+    let rate = syllables / seconds;
+    if rate < config.min_syllables_per_second {
+        (config.min_syllables_per_second / rate).ln()
+    } else if rate > config.max_syllables_per_second {
+        (rate / config.max_syllables_per_second).ln()
+    } else {
+        0.0
+    }
+}
+
+/// Empirical code entropy of each codebook, normalized to [0, 1].
+///
+/// Loops and stuck silence reuse a handful of codes and score near 0.
+pub fn codebook_entropies(codes: &Tensor) -> Result<Vec<f32>> {
+    let rows = codes.to_dtype(DType::U32)?.to_vec2::<u32>()?;
+    Ok(rows
+        .iter()
+        .filter(|row| row.len() > 1)
+        .map(|row| {
+            let mut sorted = row.clone();
+            sorted.sort_unstable();
+            let n = row.len() as f32;
+            let entropy: f32 = sorted
+                .chunk_by(|a, b| a == b)
+                .map(|run| {
+                    let p = run.len() as f32 / n;
+                    -p * p.ln()
+                })
+                .sum();
+            entropy / n.ln()
+        })
+        .collect())
+}
+
+/// Mean of [`codebook_entropies`]
+pub fn codebook_entropy(codes: &Tensor) -> Result<f32> {
+    let entropies = codebook_entropies(codes)?;
+    if entropies.is_empty() {
+        return Ok(0.0);
+    }
+    Ok(entropies.iter().sum::<f32>() / entropies.len() as f32)
+}
+
+/// Scores one candidate `codes` (num_codebooks, T) for `text`, encoded as `prompt`
+pub fn score_candidate(
+    model: &mut DualARTransformer,
+    prompt: &Tensor,
+    codes: &Tensor,
+    text: &str,
+    config: &RerankConfig,
+) -> Result<CandidateScore> {
+    let scores = score_codes(model, prompt, codes)?;
+    let duration_penalty = duration_penalty(
+        text,
+        scores.n_frames(),
+        model.model_type.frame_rate(),
+        config,
+    );
+    let entropy = codebook_entropy(codes)?;
+    let entropy_penalty = (config.min_entropy - entropy).max(0.0);
+    let total = scores.mean_per_frame()
+        - config.duration_weight * duration_penalty
+        - config.entropy_weight * entropy_penalty;
+    Ok(CandidateScore {
+        scores,
+        duration_penalty,
+        entropy,
+        entropy_penalty,
+        total,
+    })
+}
+
+pub struct BestOf {
+    /// Winning (num_codebooks, T) codes
+    pub codes: Tensor,
+    pub candidates: Vec<CandidateScore>,
+    pub winner: usize,
+}
+
+/// Samples `n` candidates for one chunk in a single batch and keeps the best-scoring take.
+///
+/// `prompt` must carry its full conditioning prefix, since batched generation starts from an
+/// empty KV cache. Candidates that run into `max_new_tokens` only win if all of them do.
+/// Leaves the slow KV cache cleared.
+pub fn generate_best_of(
+    model: &mut DualARTransformer,
+    prompt: &Tensor,
+    text: &str,
+    n: usize,
+    max_new_tokens: usize,
+    sampling_args: &SamplingArgs,
+    config: &RerankConfig,
+) -> Result<BestOf> {
+    if n == 0 {
+        candle_core::bail!("best_of must be at least 1");
+    }
+    let prompts = vec![prompt.clone(); n];
+    let (sequences, _) =
+        generate_static_batch(model, &prompts, max_new_tokens, true, sampling_args.clone())?;
+    model.clear_slow_layer_caches();
+
+    let mut candidates = Vec::with_capacity(n);
+    let mut hit_limit = Vec::with_capacity(n);
+    for codes in sequences.iter() {
+        candidates.push(score_candidate(model, prompt, codes, text, config)?);
+        hit_limit.push(codes.dim(D::Minus1)? >= max_new_tokens);
+    }
+    let eligible: Vec<usize> = match hit_limit.iter().all(|hit| *hit) {
+        true => (0..n).collect(),
+        false => (0..n).filter(|&i| !hit_limit[i]).collect(),
+    };
+    let winner = eligible
+        .into_iter()
+        .max_by(|&a, &b| candidates[a].total.total_cmp(&candidates[b].total))
+        .unwrap_or(0);
+    for (i, candidate) in candidates.iter().enumerate() {
+        tracing::info!(
+            "Candidate {}{}: {} frames, log-likelihood {:.3}/frame, duration penalty {:.3}, entropy {:.3}, score {:.3}",
+            i,
+            if i == winner { " (best)" } else { "" },
+            candidate.scores.n_frames(),
+            candidate.scores.mean_per_frame(),
+            candidate.duration_penalty,
+            candidate.entropy,
+            candidate.total
+        );
+    }
+
+    Ok(BestOf {
+        codes: sequences[winner].clone(),
+        candidates,
+        winner,
+    })
+}
@@ -0,0 +1,109 @@
+use super::utils::constrain_probs_to_audio;
+use crate::config::{WhichFishVersion, WhichLM};
+use crate::lm::DualARTransformer;
+use candle_core::{D, DType, IndexOp, Result, Tensor};
+use candle_nn::ops::log_softmax;
+
+/// Teacher-forced log-likelihoods (natural log) of a code sequence under the LM.
+///
+/// These are raw model likelihoods: no temperature, top-p/top-k or repetition penalty.
+#[derive(Debug, Clone)]
+pub struct CodeScores {
+    /// Per-frame log p of the semantic stream token
+    pub semantic: Vec<f32>,
+    /// (num_codebooks, T) per-frame log p of each fast codebook
+    pub codebooks: Vec<Vec<f32>>,
+    /// Log p of <|im_end|> right after the last frame
+    pub eos: f32,
+}
+
+impl CodeScores {
+    pub fn n_frames(&self) -> usize {
+        self.semantic.len()
+    }
+
+    pub fn semantic_total(&self) -> f32 {
+        self.semantic.iter().sum()
+    }
+
+    pub fn codebook_totals(&self) -> Vec<f32> {
+        self.codebooks.iter().map(|cb| cb.iter().sum()).collect()
+    }
+
+    /// Semantic plus all codebooks, per frame
+    pub fn frame_totals(&self) -> Vec<f32> {
+        (0..self.n_frames())
+            .map(|t| self.semantic[t] + self.codebooks.iter().map(|cb| cb[t]).sum::<f32>())
+            .collect()
+    }
+
+    /// Total log-likelihood of the frames, excluding EOS
+    pub fn total(&self) -> f32 {
+        self.semantic_total() + self.codebook_totals().iter().sum::<f32>()
+    }
+
+    /// Mean per-frame log-likelihood, comparable across takes of different lengths
+    pub fn mean_per_frame(&self) -> f32 {
+        self.total() / self.n_frames().max(1) as f32
+    }
+}
+
+/// Scores `codes` (num_codebooks, T) as a continuation of `prompt` from `PromptEncoder`.
+///
+/// Like generation, this continues from the model's current slow KV cache (e.g. a cached
+/// speaker prompt), which is left as it was.
+pub fn score_codes(
+    model: &mut DualARTransformer,
+    prompt: &Tensor,
+    codes: &Tensor,
+) -> Result<CodeScores> {
+    let codes = codes.to_dtype(DType::U32)?;
+    let n_frames = codes.dim(1)?;
+    let (slow_logits, fast_logits) = model.forward_teacher_forced(prompt, &codes)?;
+    let slow_logits = slow_logits.to_dtype(DType::F32)?;
+    let token_config = &model.token_config;
+
+    let (semantic, eos) = match model.model_type {
+        WhichLM::Fish(WhichFishVersion::Fish1_2) | WhichLM::Fish(WhichFishVersion::Fish1_4) => {
+            // Semantic backbone only chooses between PAD and <|im_end|>
+            let pad = slow_logits.i((.., token_config.pad_id as usize))?;
+            let im_end = slow_logits.i((.., token_config.im_end_id as usize))?;
+            let lsm = log_softmax(&Tensor::stack(&[pad, im_end], 1)?, D::Minus1)?;
+            let semantic = lsm.i((..n_frames, 0))?.to_vec1::<f32>()?;
+            let eos = lsm.i((n_frames, 1))?.to_scalar::<f32>()?;
+            (semantic, eos)
+        }
+        model_type => {
+            // Constrained to [<|im_end|>, <|semantic:0|>, ..], matching sampling
+            let constrained =
+                constrain_probs_to_audio(&slow_logits.unsqueeze(0)?, &model_type, token_config)?
+                    .squeeze(0)?;
+            let lsm = log_softmax(&constrained, D::Minus1)?;
+            let semantic_idx = (codes.i(0..1)?.t()?.to_dtype(DType::F32)? + 1.0)?
+                .to_dtype(DType::U32)?
+                .contiguous()?;
+            let semantic = lsm
+                .i(..n_frames)?
+                .contiguous()?
+                .gather(&semantic_idx, 1)?
+                .squeeze(1)?
+                .to_vec1::<f32>()?;
+            let eos = lsm.i((n_frames, 0))?.to_scalar::<f32>()?;
+            (semantic, eos)
+        }
+    };
+
+    // (T, num_codebooks, codebook_size) -> (T, num_codebooks)
+    let fast_lsm = log_softmax(&fast_logits.to_dtype(DType::F32)?, D::Minus1)?;
+    let codebooks = fast_lsm
+        .gather(&codes.t()?.contiguous()?.unsqueeze(D::Minus1)?, D::Minus1)?
+        .squeeze(D::Minus1)?
+        .t()?
+        .to_vec2::<f32>()?;
+
+    Ok(CodeScores {
+        semantic,
+        codebooks,
+        eos,
+    })
+}
@@ -0,0 +1,367 @@
+use super::single_batch::{SingleBatchGenerator, VQToken};
+use crate::lm::DualARTransformer;
+use crate::lm::sampling::SamplingArgs;
+use candle_core::{IndexOp, Result, Tensor};
+use indicatif::{ProgressBar, ProgressStyle};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Cooperative cancellation, checked between frames. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FinishReason {
+    /// The model sampled `<|im_end|>`
+    EndOfSpeech,
+    TokenLimit,
+    Cancelled,
+}
+
+pub enum SessionEvent {
+    PrefillDone {
+        /// Tokens processed by prefill, excluding the cached prefix
+        prompt_tokens: usize,
+        cached_tokens: usize,
+        elapsed: Duration,
+    },
+    Frame {
+        index: usize,
+        token: VQToken,
+        /// Time to produce this frame
+        elapsed: Duration,
+    },
+    Finished {
+        reason: FinishReason,
+        n_frames: usize,
+        prefill: Duration,
+        decode: Duration,
+        /// The step that sampled `<|im_end|>`, if any
+        end_token: Option<VQToken>,
+    },
+}
+
+/// Observes session events, e.g. to drive a progress bar
+pub trait ProgressReporter: Send {
+    fn on_event(&mut self, event: &SessionEvent);
+}
+
+/// Reports nothing
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {
+    fn on_event(&mut self, _: &SessionEvent) {}
+}
+
+/// Logs prompt processing and decoding throughput through `tracing`
+pub struct LogProgress {
+    frame_rate: f64,
+}
+
+impl LogProgress {
+    pub fn new(frame_rate: f64) -> Self {
+        Self { frame_rate }
+    }
+}
+
+impl ProgressReporter for LogProgress {
+    fn on_event(&mut self, event: &SessionEvent) {
+        match event {
+            SessionEvent::PrefillDone {
+                prompt_tokens,
+                cached_tokens,
+                elapsed,
+            } => {
+                tracing::info!(
+                    "{:.2}ms prompt processing: {} tokens ({} new, {} cached, {:.2} tokens/s)",
+                    elapsed.as_secs_f64() * 1000.0,
+                    prompt_tokens + cached_tokens,
+                    prompt_tokens,
+                    cached_tokens,
+                    *prompt_tokens as f64 / elapsed.as_secs_f64()
+                );
+            }
+            SessionEvent::Finished {
+                reason,
+                n_frames,
+                decode,
+                ..
+            } => {
+                let n_frames = *n_frames as f64;
+                let dt = decode.as_secs_f64();
+                tracing::info!(
+                    "{} tokens generated in {:.3}s ({:.2} tokens/s, {:.3}ms / token, RTF: {:.3}, {:?})",
+                    n_frames,
+                    dt,
+                    n_frames / dt,
+                    // The first frame comes from prefill
+                    (dt * 1e3) / (n_frames - 1f64).max(1.0),
+                    (n_frames / self.frame_rate) / dt,
+                    reason
+                );
+            }
+            SessionEvent::Frame { .. } => {}
+        }
+    }
+}
+
+/// [`LogProgress`] plus a terminal spinner, for CLI use
+pub struct SpinnerProgress {
+    log: LogProgress,
+    spinner: Option<ProgressBar>,
+}
+
+impl SpinnerProgress {
+    pub fn new(frame_rate: f64) -> Self {
+        Self {
+            log: LogProgress::new(frame_rate),
+            spinner: None,
+        }
+    }
+}
+
+impl ProgressReporter for SpinnerProgress {
+    fn on_event(&mut self, event: &SessionEvent) {
+        self.log.on_event(event);
+        match event {
+            SessionEvent::PrefillDone { .. } => {
+                let spinner = ProgressBar::new_spinner();
+                spinner.set_style(
+                    ProgressStyle::default_spinner()
+                        .template(
+                            "{spinner:.green} {msg} [{elapsed_precise}] {per_sec} iterations/s",
+                        )
+                        .unwrap()
+                        .tick_chars("/|\\- "),
+                );
+                spinner.enable_steady_tick(Duration::from_millis(100));
+                self.spinner = Some(spinner);
+            }
+            SessionEvent::Frame { index, .. } => {
+                if let Some(spinner) = &self.spinner {
+                    spinner.inc(1);
+                    spinner.set_message(format!("Tokens: {}", index));
+                }
+            }
+            SessionEvent::Finished { .. } => {
+                if let Some(spinner) = self.spinner.take() {
+                    spinner.finish_and_clear();
+                }
+            }
+        }
+    }
+}
+
+/// Audio-only generation as a stream of timed events
+pub struct GenerationSession<'a> {
+    generator: SingleBatchGenerator<'a>,
+    im_end_id: u32,
+    cancel: CancellationToken,
+    /// First frame, sampled by prefill and emitted after `PrefillDone`
+    pending: Option<VQToken>,
+    /// Prefill sampled `<|im_end|>`, so nothing follows the pending frame
+    ended_at_prefill: bool,
+    n_frames: usize,
+    prefill: Duration,
+    decode_start: Option<Instant>,
+    done: bool,
+}
+
+impl<'a> GenerationSession<'a> {
+    pub fn new(
+        model: &'a mut DualARTransformer,
+        prompt: &Tensor,
+        max_new_tokens: usize,
+        sampling_args: &SamplingArgs,
+        cancel: CancellationToken,
+    ) -> Result<Self> {
+        let im_end_id = model.token_config.im_end_id;
+        let generator =
+            SingleBatchGenerator::new(model, prompt, max_new_tokens, sampling_args, true)?;
+        Ok(Self {
+            generator,
+            im_end_id,
+            cancel,
+            pending: None,
+            ended_at_prefill: false,
+            n_frames: 0,
+            prefill: Duration::ZERO,
+            decode_start: None,
+            done: false,
+        })
+    }
+
+    fn finish(&mut self, reason: FinishReason, end_token: Option<VQToken>) -> SessionEvent {
+        self.done = true;
+        SessionEvent::Finished {
+            reason,
+            n_frames: self.n_frames,
+            prefill: self.prefill,
+            decode: self
+                .decode_start
+                .map(|start| start.elapsed())
+                .unwrap_or_default(),
+            end_token,
+        }
+    }
+
+    fn frame(&mut self, token: VQToken, elapsed: Duration) -> SessionEvent {
+        let index = self.n_frames;
+        self.n_frames += 1;
+        SessionEvent::Frame {
+            index,
+            token,
+            elapsed,
+        }
+    }
+
+    fn step(&mut self) -> Result<SessionEvent> {
+        if let Some(token) = self.pending.take() {
+            return Ok(self.frame(token, self.prefill));
+        }
+        if self.ended_at_prefill {
+            return Ok(self.finish(FinishReason::EndOfSpeech, None));
+        }
+        if self.cancel.is_cancelled() {
+            return Ok(self.finish(FinishReason::Cancelled, None));
+        }
+
+        let is_prefill = self.decode_start.is_none();
+        let cached_tokens = self.generator.input_pos;
+        let start = Instant::now();
+        let Some(token) = self.generator.next() else {
+            if is_prefill {
+                candle_core::bail!(
+                    "Prefill mistakenly thought generation ended. Please check max tokens"
+                );
+            }
+            return Ok(self.finish(FinishReason::TokenLimit, None));
+        };
+        let token = token?;
+        let elapsed = start.elapsed();
+
+        if is_prefill {
+            self.prefill = elapsed;
+            self.decode_start = Some(Instant::now());
+            // The prefill frame is always kept, even if it is <|im_end|>
+            self.ended_at_prefill = token.tokens[0] == self.im_end_id;
+            self.pending = Some(token);
+            return Ok(SessionEvent::PrefillDone {
+                prompt_tokens: self.generator.input_pos - cached_tokens,
+                cached_tokens,
+                elapsed,
+            });
+        }
+        if token.tokens[0] == self.im_end_id {
+            return Ok(self.finish(FinishReason::EndOfSpeech, Some(token)));
+        }
+        Ok(self.frame(token, elapsed))
+    }
+}
+
+impl Iterator for GenerationSession<'_> {
+    type Item = Result<SessionEvent>;
+
+    fn next(&mut self) -> Option<Result<SessionEvent>> {
+        if self.done {
+            return None;
+        }
+        let result = self.step();
+        if result.is_err() {
+            self.done = true;
+        }
+        Some(result)
+    }
+}
+
+pub struct SessionOutput {
+    /// (num_codebooks, n_frames)
+    pub codes: Tensor,
+    /// One per step, including the one that sampled `<|im_end|>`
+    pub hidden_states: Option<Tensor>,
+    /// One row per frame over the KV positions its step attended to; empty unless the model is
+    /// capturing attention (see [`DualARTransformer::set_attention_capture`])
+    pub attention: Vec<Vec<f32>>,
+    pub reason: FinishReason,
+}
+
+/// Drives a session to completion, reporting every event
+pub fn run_session(
+    session: GenerationSession,
+    collect_hidden_states: bool,
+    reporter: &mut dyn ProgressReporter,
+) -> Result<SessionOutput> {
+    let mut frames = Vec::new();
+    let mut hidden_states = Vec::new();
+    let mut attention = Vec::new();
+    let mut reason = FinishReason::TokenLimit;
+    for event in session {
+        let event = event?;
+        reporter.on_event(&event);
+        match event {
+            SessionEvent::Frame { token, .. } => {
+                if let Some(row) = &token.attention {
+                    attention.push(row.to_vec1::<f32>()?);
+                }
+                frames.push(token.codes);
+                if collect_hidden_states {
+                    hidden_states.push(token.hidden_state);
+                }
+            }
+            SessionEvent::Finished {
+                reason: finish_reason,
+                end_token,
+                ..
+            } => {
+                reason = finish_reason;
+                if collect_hidden_states && let Some(token) = end_token {
+                    hidden_states.push(token.hidden_state);
+                }
+            }
+            SessionEvent::PrefillDone { .. } => {}
+        }
+    }
+    if frames.is_empty() {
+        candle_core::bail!("Generation was cancelled before any frames were produced");
+    }
+    let codes = Tensor::cat(&frames, 1)?.i((1.., ..))?;
+    let hidden_states = match collect_hidden_states {
+        true => Tensor::cat(&hidden_states, 0).ok(),
+        false => None,
+    };
+    Ok(SessionOutput {
+        codes,
+        hidden_states,
+        attention,
+        reason,
+    })
+}
+
+/// Blocking generation with pluggable progress reporting and cancellation
+pub fn generate_with_progress(
+    model: &mut DualARTransformer,
+    prompt: &Tensor,
+    max_new_tokens: usize,
+    sampling_args: &SamplingArgs,
+    collect_hidden_states: bool,
+    reporter: &mut dyn ProgressReporter,
+    cancel: CancellationToken,
+) -> Result<SessionOutput> {
+    let session = GenerationSession::new(model, prompt, max_new_tokens, sampling_args, cancel)?;
+    run_session(session, collect_hidden_states, reporter)
+}
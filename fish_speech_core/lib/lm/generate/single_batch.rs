@@ -1,27 +1,40 @@
-use super::utils::{constrain_probs_to_audio, rescale_semantic_tokens};
+use super::session::{
+    CancellationToken, NoProgress, ProgressReporter, SpinnerProgress, generate_with_progress,
+};
+use super::utils::{
+    clamp_to_context, constrain_probs_to_audio, constrained_eos_index, is_audio_token,
+    rescale_semantic_tokens,
+};
 use crate::config::{WhichFishVersion, WhichLM};
 use crate::lm::DualARTransformer;
 use crate::lm::sampling::{
-    SamplingArgs, legacy_softmax_sample, rep_pen::SingleBatchedRepPenProcessor,
+    BatchedLogitsProcessor, EosControl, SamplingArgs, legacy_softmax_sample,
+    processors::{TokenBias, TokenHistory},
 };
 use candle_core::{D, DType, IndexOp, Module, Result, Tensor};
-use candle_transformers::generation::{LogitsProcessor, Sampling};
-use indicatif::{ProgressBar, ProgressStyle};
-use std::time::{Duration, Instant};
 
 pub struct VQToken {
     /// Tensor version of tokens
     pub codes: Tensor,
     pub tokens: Vec<u32>,
     pub hidden_state: Tensor,
+    /// False for text tokens and `<|im_end|>`, whose codebooks are all zero
+    pub is_audio: bool,
+    /// (kv_seqlen,) attention of the step that sampled this token, if the model is capturing it
+    pub attention: Option<Tensor>,
 }
 
 pub struct SingleBatchGenerator<'a> {
     model: &'a mut DualARTransformer,
-    logits_processor: LogitsProcessor,
-    rep_pen_processors: Vec<SingleBatchedRepPenProcessor>,
+    slow_processor: BatchedLogitsProcessor,
+    fast_processor: BatchedLogitsProcessor,
+    slow_history: TokenHistory,
+    /// One per codebook
+    fast_histories: Vec<TokenHistory>,
+    eos: EosControl,
     pub input_pos: usize,
     max_new_tokens: usize,
+    n_generated: usize,
     prompt: Option<Tensor>,
     previous_codes: Option<Vec<u32>>,
     audio_only: bool,
@@ -35,34 +48,32 @@ impl<'a> SingleBatchGenerator<'a> {
         sampling_args: &SamplingArgs,
         audio_only: bool,
     ) -> Result<Self> {
-        let sampling = match sampling_args.temp {
-            0.0 => Sampling::ArgMax,
-            temp => Sampling::TopKThenTopP {
-                temperature: temp,
-                p: sampling_args.top_p,
-                k: sampling_args.top_k,
-            },
-        };
-        let logits_processor = LogitsProcessor::from_sampling(rand::random::<u64>(), sampling);
-        let rep_pen_processors: Vec<SingleBatchedRepPenProcessor> = (0..model.cfg.num_codebooks)
-            .map(|_| {
-                SingleBatchedRepPenProcessor::new(
-                    model.cfg.codebook_size,
-                    16,
-                    sampling_args.repetition_penalty,
-                    model.fast_embeddings.embeddings().dtype(),
-                    model.fast_embeddings.embeddings().device(),
-                )
-            })
-            .collect::<Result<_>>()?;
+        let slow_args = sampling_args.slow_head(16);
+        let fast_args = sampling_args.fast_head(16, model.cfg.depthwise_wte.unwrap_or(false));
+        let slow_history = TokenHistory::new(slow_args.rep_pen_window);
+        let fast_histories = (0..model.cfg.num_codebooks)
+            .map(|_| TokenHistory::new(fast_args.rep_pen_window))
+            .collect();
+        let slow_processor = BatchedLogitsProcessor::new(rand::random::<u64>(), &slow_args);
+        let fast_processor = BatchedLogitsProcessor::new(rand::random::<u64>(), &fast_args);
         let input_pos = model.curr_kv_size()?;
+        let max_new_tokens = clamp_to_context(
+            model.cfg.max_seq_len,
+            input_pos,
+            prompt.dim(D::Minus1)?,
+            max_new_tokens,
+        )?;
 
         Ok(Self {
-            max_new_tokens: max_new_tokens + model.curr_kv_size()?,
+            max_new_tokens,
+            n_generated: 0,
             model,
             prompt: Some(prompt.clone()),
-            logits_processor,
-            rep_pen_processors,
+            slow_processor,
+            fast_processor,
+            slow_history,
+            fast_histories,
+            eos: sampling_args.eos.clone(),
             input_pos,
             audio_only,
             previous_codes: None,
@@ -70,20 +81,33 @@ impl<'a> SingleBatchGenerator<'a> {
     }
 }
 
+impl SingleBatchGenerator<'_> {
+    fn sample_slow(&mut self, logits: &Tensor, bias: Option<TokenBias>) -> Result<u32> {
+        let token = self.slow_processor.sample_biased(
+            logits,
+            Some(std::slice::from_ref(&self.slow_history)),
+            bias,
+        )?[0];
+        self.slow_history.push(token);
+        Ok(token)
+    }
+}
+
 impl<'a> Iterator for SingleBatchGenerator<'a> {
     type Item = Result<VQToken>;
 
     fn next(&mut self) -> Option<Result<VQToken>> {
-        if self.input_pos > self.max_new_tokens {
+        if self.n_generated >= self.max_new_tokens {
             tracing::info!(
-                "Terminating early; input pos: {:?}, max: {:?}",
+                "Terminating early; input pos: {:?}, generated: {:?}, max: {:?}",
                 self.input_pos,
+                self.n_generated,
                 self.max_new_tokens
             );
             return None;
         }
 
-        // Audio only, <|im_end|> was reached last time
+        // <|im_end|> was reached last time
         self.prompt.as_ref()?;
 
         let x = self.prompt.as_ref().unwrap().clone();
@@ -97,6 +121,10 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                 x.clone()
             };
             let (logits, hidden_states) = self.model.forward_generate(&x, self.input_pos, None)?;
+            let attention = match self.model.take_attention()? {
+                Some(weights) => Some(weights.i(0)?),
+                None => None,
+            };
 
             let semantic_token = if self.audio_only {
                 match self.model.model_type {
@@ -119,6 +147,7 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                             eos_prob,
                             self.model.token_config.pad_id,
                             self.model.token_config.im_end_id,
+                            self.eos.eos_bias(self.n_generated),
                         )
                     }
                     _ => {
@@ -129,7 +158,14 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                         )?
                         .flatten_all()?;
 
-                        let shifted_token = self.logits_processor.sample(&slow_logits)?;
+                        let bias = self.eos.is_active().then(|| TokenBias {
+                            token: constrained_eos_index(
+                                &self.model.model_type,
+                                &self.model.token_config,
+                            ),
+                            bias: self.eos.eos_bias(self.n_generated),
+                        });
+                        let shifted_token = self.sample_slow(&slow_logits, bias)?;
                         rescale_semantic_tokens(
                             vec![shifted_token],
                             &self.model.model_type,
@@ -138,18 +174,26 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                     }
                 }
             } else {
-                // Unconstrained generation: accept the huge vocab size and just sample
-                self.logits_processor.sample(&logits)?
+                // Interleaved generation: sample text, audio and <|im_end|> from the full vocab
+                let bias = self.eos.is_active().then(|| TokenBias {
+                    token: self.model.token_config.im_end_id as usize,
+                    bias: self.eos.eos_bias(self.n_generated),
+                });
+                self.sample_slow(&logits, bias)?
             };
+            let is_audio = is_audio_token(
+                semantic_token,
+                &self.model.model_type,
+                &self.model.token_config,
+            );
             let mut codebooks = vec![semantic_token];
             self.model.clear_fast_layer_caches();
 
             // Generate token
             let mut x = hidden_states.clone();
-            // TODO: Skip this and short-circuit when we handle generating text only
             for codebook_idx in 0..self.model.cfg.num_codebooks {
-                // Skip final generation step
-                if self.audio_only && semantic_token == self.model.token_config.im_end_id {
+                // Text tokens and <|im_end|> carry no audio
+                if !is_audio {
                     codebooks.push(0);
                     continue;
                 }
@@ -158,17 +202,14 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                     .forward_generate_fast(&x, codebook_idx)?
                     .flatten_all()?;
 
-                let logits_adj = match (&self.previous_codes, self.model.cfg.depthwise_wte) {
-                    (None, _) => logits.clone(),
-                    // Turning off rep pen for smoltts for now
-                    (_, Some(true)) => logits.clone(),
-                    (Some(t), _) => self.rep_pen_processors[codebook_idx]
-                        .apply(&logits, t[codebook_idx + 1] as usize)?,
-                };
-                let a = self.logits_processor.sample(&logits_adj.flatten_all()?)?;
+                let history = &mut self.fast_histories[codebook_idx];
+                let a = self
+                    .fast_processor
+                    .sample(&logits, Some(std::slice::from_ref(history)))?[0];
+                history.push(a);
                 let a_tensor = Tensor::from_slice(&[a], 1, x.device())?;
                 let a_tensor = if let Some(true) = self.model.cfg.depthwise_wte {
-                    (a_tensor + 0.max(codebook_idx * self.model.cfg.codebook_size) as f64)?
+                    (a_tensor + (codebook_idx * self.model.cfg.codebook_size) as f64)?
                 } else {
                     a_tensor
                 };
@@ -194,9 +235,9 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
             } else {
                 self.input_pos += 1;
             }
+            self.n_generated += 1;
             self.previous_codes = Some(codebooks.clone());
-            self.prompt = if self.audio_only && semantic_token == self.model.token_config.im_end_id
-            {
+            self.prompt = if semantic_token == self.model.token_config.im_end_id {
                 None
             } else {
                 Some(codes_tensor.clone())
@@ -206,6 +247,8 @@ impl<'a> Iterator for SingleBatchGenerator<'a> {
                 tokens: codebooks,
                 codes: codes_tensor,
                 hidden_state: hidden_states,
+                is_audio,
+                attention,
             })
         })();
 
@@ -221,87 +264,20 @@ pub fn generate_blocking_with_hidden(
     collect_hidden_states: bool,
     show_progress: bool,
 ) -> Result<(Tensor, Option<Tensor>)> {
-    // TODO: Handle text output
-    let audio_only = true;
-    let n_cached = model.curr_kv_size()?;
-    let im_end_id = model.token_config.im_end_id;
-    let prompt_size = prompt.dim(D::Minus1)?;
-    let mut generator =
-        SingleBatchGenerator::new(model, prompt, max_new_tokens, sampling_args, audio_only)?;
-
-    let start_pp = Instant::now();
-    let first_vq_token = generator.next().ok_or(candle_core::Error::Msg(
-        "Prefill mistakenly thought generation ended. Please check max tokens".into(),
-    ))??;
-    let dt = start_pp.elapsed();
-    if show_progress {
-        tracing::info!(
-            "{:.2}ms prompt processing: {} tokens ({} new, {} cached, {:.2} tokens/s)",
-            dt.as_secs_f64() * 1000.0,
-            generator.input_pos,
-            generator.input_pos - n_cached,
-            n_cached,
-            prompt_size as f64 / dt.as_secs_f64()
-        );
-    }
-
-    // Set up concatenation batch
-    let mut previous_tokens: Vec<Tensor> = vec![first_vq_token.codes];
-    let mut hidden_states: Vec<Tensor> = vec![first_vq_token.hidden_state];
-
-    let spinner = ProgressBar::new_spinner();
-    spinner.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.green} {msg} [{elapsed_precise}] {per_sec} iterations/s")
-            .unwrap()
-            .tick_chars("/|\\- "),
-    );
-    spinner.enable_steady_tick(Duration::from_millis(100));
-    let start_decode = Instant::now();
-    for (i, maybe_vq_token) in generator.enumerate() {
-        let vq_token = maybe_vq_token?;
-        if vq_token.tokens[0] != im_end_id {
-            previous_tokens.push(vq_token.codes);
-        }
-
-        if collect_hidden_states {
-            hidden_states.push(vq_token.hidden_state);
-        }
-
-        if show_progress {
-            spinner.inc(1);
-            spinner.set_message(format!("Tokens: {}", i));
-        }
-    }
-    let dt = start_decode.elapsed();
-
-    let full_output = Tensor::cat(&previous_tokens, 1)?;
-    let out_tokens = if audio_only {
-        full_output.i((1.., ..))?
-    } else {
-        full_output
-    };
-    let out_len = previous_tokens.len() as f64;
-    let hidden_states = match collect_hidden_states {
-        true => Tensor::cat(&hidden_states, 0).ok(),
-        false => None,
+    let mut reporter: Box<dyn ProgressReporter> = match show_progress {
+        true => Box::new(SpinnerProgress::new(model.model_type.frame_rate())),
+        false => Box::new(NoProgress),
     };
-
-    if show_progress {
-        let frame_rate = match model.model_type {
-            WhichLM::DualAR => 12.5,
-            _ => 21.535,
-        };
-        tracing::info!(
-            "{} tokens generated in {:.3}s ({:.2} tokens/s, {:.3}ms / token, RTF: {:.3})",
-            out_len,
-            dt.as_secs_f64(),
-            out_len / dt.as_secs_f64(),
-            (dt.as_secs_f64() * 1e3) / (out_len - 1f64),
-            (out_len / frame_rate) / dt.as_secs_f64()
-        );
-    }
-    Ok((out_tokens, hidden_states))
+    let output = generate_with_progress(
+        model,
+        prompt,
+        max_new_tokens,
+        sampling_args,
+        collect_hidden_states,
+        reporter.as_mut(),
+        CancellationToken::new(),
+    )?;
+    Ok((output.codes, output.hidden_states))
 }
 
 pub fn generate_blocking(
@@ -1,7 +1,11 @@
-use super::utils::{constrain_probs_to_audio, rescale_semantic_tokens};
-use crate::config::WhichLM;
+use super::utils::{
+    clamp_to_context, constrain_probs_to_audio, constrained_eos_index, rescale_semantic_tokens,
+};
 use crate::lm::DualARTransformer;
-use crate::lm::sampling::{BatchedLogitsProcessor, SamplingArgs, rep_pen::BatchedRepPenProcessor};
+use crate::lm::sampling::{
+    BatchedLogitsProcessor, EosControl, SamplingArgs,
+    processors::{TokenBias, TokenHistory},
+};
 use candle_core::{D, IndexOp, Module, Result, Tensor};
 use indicatif::{ProgressBar, ProgressStyle};
 use std::time::{Duration, Instant};
@@ -18,13 +22,19 @@ pub struct BatchGenerator<'a> {
     model: &'a mut DualARTransformer,
     pub input_pos: usize,
     max_new_tokens: usize,
+    n_generated: usize,
     prompt: Option<Tensor>,
     pad_mask: Option<Tensor>,
     audio_only: bool,
     batch_item_is_dead: Vec<bool>,
     bsz: usize,
-    rep_pen_processors: Vec<BatchedRepPenProcessor>,
-    logits_processor: BatchedLogitsProcessor,
+    slow_processor: BatchedLogitsProcessor,
+    fast_processor: BatchedLogitsProcessor,
+    /// One per batch item
+    slow_histories: Vec<TokenHistory>,
+    /// (codebook, batch item)
+    fast_histories: Vec<Vec<TokenHistory>>,
+    eos: EosControl,
 }
 
 impl<'a> BatchGenerator<'a> {
@@ -38,29 +48,36 @@ impl<'a> BatchGenerator<'a> {
     ) -> Result<Self> {
         let (prompt, pad_mask) = BatchGenerator::pad_prompts(prompts, model)?;
         let bsz = prompts.len();
+        // KV cache is cleared on prefill, so only the padded prompt counts against context
+        let max_new_tokens = clamp_to_context(
+            model.cfg.max_seq_len,
+            0,
+            prompt.dim(D::Minus1)?,
+            max_new_tokens,
+        )?;
+
+        let slow_args = sampling_args.slow_head(12);
+        let fast_args = sampling_args.fast_head(12, model.cfg.depthwise_wte.unwrap_or(false));
 
         Ok(Self {
-            // TODO remove arbitrary constant
-            rep_pen_processors: (0..model.cfg.num_codebooks)
-                .map(|_| {
-                    BatchedRepPenProcessor::new(
-                        model.cfg.codebook_size,
-                        12,
-                        sampling_args.repetition_penalty,
-                        bsz,
-                    )
-                })
-                .collect(),
+            slow_histories: vec![TokenHistory::new(slow_args.rep_pen_window); bsz],
+            fast_histories: vec![
+                vec![TokenHistory::new(fast_args.rep_pen_window); bsz];
+                model.cfg.num_codebooks
+            ],
+            eos: sampling_args.eos.clone(),
+            slow_processor: BatchedLogitsProcessor::new(42, &slow_args),
+            fast_processor: BatchedLogitsProcessor::new(43, &fast_args),
             model,
             prompt: Some(prompt),
             pad_mask: Some(pad_mask),
             // Will clear KV cache before proceeding
             input_pos: 0,
             max_new_tokens,
+            n_generated: 0,
             audio_only,
             batch_item_is_dead: vec![false; prompts.len()],
             bsz: prompts.len(),
-            logits_processor: BatchedLogitsProcessor::new(42, sampling_args),
         })
     }
 
@@ -119,7 +136,7 @@ impl<'a> Iterator for BatchGenerator<'a> {
             // Prefill; we have no guarantees that previous size is correct
             self.model.clear_slow_layer_caches();
         }
-        if self.prompt.is_none() || self.input_pos > self.max_new_tokens {
+        if self.prompt.is_none() || self.n_generated >= self.max_new_tokens {
             // All generations are done
             return None;
         }
@@ -138,7 +155,18 @@ impl<'a> Iterator for BatchGenerator<'a> {
             } else {
                 slow_logits
             };
-            let raw_slow_ids = self.logits_processor.sample(&slow_logits)?;
+            let eos_bias = (self.audio_only && self.eos.is_active()).then(|| TokenBias {
+                token: constrained_eos_index(&self.model.model_type, &self.model.token_config),
+                bias: self.eos.eos_bias(self.n_generated),
+            });
+            let raw_slow_ids = self.slow_processor.sample_biased(
+                &slow_logits,
+                Some(&self.slow_histories),
+                eos_bias,
+            )?;
+            for (history, id) in self.slow_histories.iter_mut().zip(&raw_slow_ids) {
+                history.push(*id);
+            }
             let slow_ids = if self.audio_only {
                 rescale_semantic_tokens(
                     raw_slow_ids,
@@ -184,26 +212,16 @@ impl<'a> Iterator for BatchGenerator<'a> {
                 })
                 .collect();
 
-            for (codebook_idx, rep_pen) in
-                (0..self.model.cfg.num_codebooks).zip(self.rep_pen_processors.iter_mut())
-            {
-                let is_depthwise =
-                    self.model.cfg.depthwise_wte.is_some() && self.model.cfg.depthwise_wte.unwrap();
-                let fast_logits = self
-                    .model
-                    .forward_generate_fast(&x, codebook_idx)?
-                    .to_dtype(candle_core::DType::F32)?;
-                let fast_logits = match self.model.cfg.depthwise_wte {
-                    Some(true) => fast_logits,
-                    _ => rep_pen.apply_mask(&fast_logits)?,
-                };
-
-                let ids = self.logits_processor.sample(&fast_logits)?;
+            let is_depthwise = self.model.cfg.depthwise_wte.unwrap_or(false);
+            for codebook_idx in 0..self.model.cfg.num_codebooks {
+                let fast_logits = self.model.forward_generate_fast(&x, codebook_idx)?;
+                let histories = &mut self.fast_histories[codebook_idx];
+                let ids = self.fast_processor.sample(&fast_logits, Some(histories))?;
+                for (history, id) in histories.iter_mut().zip(&ids) {
+                    history.push(*id);
+                }
                 // Prime hidden state WTE for next round
                 // Unsqueeze seqlen, yeah it's always (bsz, seqlen=1, hidden_dim) but downstream doesn't know that
-                if is_depthwise {
-                    rep_pen.update_mask(ids.clone())?;
-                }
                 let ids_tensor = &Tensor::from_slice(&ids, ids.len(), x.device())?;
                 let ids_tensor = if is_depthwise {
                     let offset = codebook_idx * self.model.cfg.codebook_size;
@@ -265,6 +283,7 @@ impl<'a> Iterator for BatchGenerator<'a> {
             } else {
                 self.input_pos += 1;
             }
+            self.n_generated += 1;
             self.pad_mask = None;
 
             Ok(batch_positions)
@@ -324,7 +343,7 @@ pub fn generate_static_batch(
     for (i, maybe_batch_pos) in generator.enumerate() {
         let vq_token = maybe_batch_pos?;
         // let mut items: Vec<String> = Vec::with_capacity(sequences.len());
-        for (seq, pos) in sequences.iter_mut().zip(vq_token.into_iter()) {
+        for (seq, pos) in sequences.iter_mut().zip(vq_token) {
             if !audio_only || pos.is_active {
                 seq.tokens.push(pos.codes);
                 seq.is_audio_steps.push(pos.is_audio);
@@ -373,10 +392,7 @@ pub fn generate_static_batch(
         .map(|t| if audio_only { t.i((1.., ..)) } else { Ok(t) })
         .collect::<Result<_>>()?;
 
-    let frame_rate = match model.model_type {
-        WhichLM::DualAR => 12.5,
-        _ => 21.535,
-    };
+    let frame_rate = model.model_type.frame_rate();
     tracing::info!(
         "{} tokens generated in {:.3}s ({:.2} tokens/s throughput, {:.3}ms / step, RTF: {:.3})",
         out_len,
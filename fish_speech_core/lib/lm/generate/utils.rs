@@ -32,6 +32,29 @@ pub fn constrain_probs_to_audio(
     }
 }
 
+/// Index of `<|im_end|>` in logits returned by [`constrain_probs_to_audio`]
+pub fn constrained_eos_index(model_type: &WhichLM, token_config: &TokenConfig) -> usize {
+    match model_type {
+        WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => 0,
+        _ => token_config.im_end_id as usize,
+    }
+}
+
+/// Whether a slow-head token starts an audio frame, rather than being text or `<|im_end|>`
+pub fn is_audio_token(token: u32, model_type: &WhichLM, token_config: &TokenConfig) -> bool {
+    match model_type {
+        WhichLM::Fish(WhichFishVersion::Fish1_2) | WhichLM::Fish(WhichFishVersion::Fish1_4) => {
+            token == token_config.pad_id
+        }
+        _ => {
+            token >= token_config.semantic_start_id
+                && token_config
+                    .semantic_end_id
+                    .is_none_or(|end_id| token <= end_id)
+        }
+    }
+}
+
 /// Put back tokens after constrained generation sampling
 pub fn rescale_semantic_tokens(
     tokens: Vec<u32>,
@@ -54,3 +77,44 @@ pub fn rescale_semantic_tokens(
         _ => tokens,
     }
 }
+
+/// Maps generated codes to codec codes: Fish 1.4 and below are offset by one from the codec's
+pub fn lm_codes_to_codec(codes: &Tensor, model_type: &WhichLM) -> Result<Tensor> {
+    match model_type {
+        WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => Ok(codes.clone()),
+        _ => codes.broadcast_sub(&codes.ones_like()?),
+    }
+}
+
+/// Clamps `max_new_tokens` so that the cached prefix, the prompt and the generated frames
+/// all fit within the model's `max_seq_len`.
+///
+/// Errors if the prompt alone leaves no room to generate anything.
+pub fn clamp_to_context(
+    max_seq_len: usize,
+    n_cached: usize,
+    prompt_len: usize,
+    max_new_tokens: usize,
+) -> Result<usize> {
+    let used = n_cached + prompt_len;
+    if used >= max_seq_len {
+        candle_core::bail!(
+            "Prompt does not fit in model context: {} cached + {} prompt tokens >= max_seq_len {}",
+            n_cached,
+            prompt_len,
+            max_seq_len
+        );
+    }
+    let remaining = max_seq_len - used;
+    if remaining < max_new_tokens {
+        tracing::warn!(
+            "Clamping max_new_tokens from {} to {} to fit context ({} cached, {} prompt, {} max)",
+            max_new_tokens,
+            remaining,
+            n_cached,
+            prompt_len,
+            max_seq_len
+        );
+    }
+    Ok(max_new_tokens.min(remaining))
+}
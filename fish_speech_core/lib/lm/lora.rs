@@ -0,0 +1,222 @@
+use super::quantized::{LinearLayer, LinearLoader};
+use candle_core::{DType, Device, Result, Tensor};
+use candle_nn::{Module, VarBuilder};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Linear layers that LoRA adapters may target
+const LORA_TARGETS: [&str; 5] = ["wqkv", "wo", "w1", "w2", "w3"];
+
+/// Subset of a PEFT-style `adapter_config.json`
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoraConfig {
+    pub r: usize,
+    pub lora_alpha: f64,
+}
+
+/// Low-rank update for a single linear layer: `scale * B @ A`
+#[derive(Debug, Clone)]
+pub struct LoraWeights {
+    /// (r, in_dim)
+    pub a: Tensor,
+    /// (out_dim, r)
+    pub b: Tensor,
+    pub scale: f64,
+}
+
+impl LoraWeights {
+    /// Dense (out_dim, in_dim) delta to add to the base weight
+    pub fn delta(&self) -> Result<Tensor> {
+        self.b.matmul(&self.a)? * self.scale
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoraAdapter {
+    pub name: String,
+    /// Keyed by module path, e.g. `layers.0.attention.wqkv`
+    pub weights: HashMap<String, LoraWeights>,
+}
+
+/// Splits `layers.0.attention.wqkv.lora_A.weight` into (`layers.0.attention.wqkv`, `lora_A`)
+fn parse_lora_key(key: &str) -> Option<(&str, &str)> {
+    let key = key.strip_prefix("base_model.model.").unwrap_or(key);
+    let key = key.strip_suffix(".weight").unwrap_or(key);
+    let (module, kind) = key.rsplit_once('.')?;
+    match kind {
+        "lora_A" | "lora_B" => Some((module, kind)),
+        _ => None,
+    }
+}
+
+impl LoraAdapter {
+    /// Loads `lora_A`/`lora_B` pairs from a safetensors file.
+    ///
+    /// Scaling comes from `<adapter>.json` or `adapter_config.json` next to the weights;
+    /// without either, alpha defaults to the rank (scale 1).
+    pub fn load(path: &Path, name: &str, dtype: DType, device: &Device) -> Result<Self> {
+        let tensors = candle_core::safetensors::load(path, device)?;
+        let config_path = [
+            path.with_extension("json"),
+            path.with_file_name("adapter_config.json"),
+        ]
+        .into_iter()
+        .find(|p| p.exists());
+        let config: Option<LoraConfig> = match config_path {
+            Some(config_path) => Some(
+                serde_json::from_reader(std::fs::File::open(&config_path)?)
+                    .map_err(|e| candle_core::Error::Msg(format!("{:?}: {}", config_path, e)))?,
+            ),
+            None => {
+                tracing::warn!("No LoRA config found for {:?}, using alpha = r", path);
+                None
+            }
+        };
+
+        let mut pairs: HashMap<&str, (Option<&Tensor>, Option<&Tensor>)> = HashMap::new();
+        for (key, tensor) in tensors.iter() {
+            let Some((module, kind)) = parse_lora_key(key) else {
+                candle_core::bail!("Unexpected tensor {} in LoRA adapter {:?}", key, path);
+            };
+            if !LORA_TARGETS
+                .iter()
+                .any(|t| module.ends_with(&format!(".{}", t)))
+            {
+                candle_core::bail!(
+                    "LoRA adapter {:?} targets unsupported layer {}",
+                    path,
+                    module
+                );
+            }
+            let entry = pairs.entry(module).or_default();
+            match kind {
+                "lora_A" => entry.0 = Some(tensor),
+                _ => entry.1 = Some(tensor),
+            }
+        }
+
+        let mut weights = HashMap::new();
+        for (module, pair) in pairs {
+            let (Some(a), Some(b)) = pair else {
+                candle_core::bail!("LoRA adapter {:?} is missing A or B for {}", path, module);
+            };
+            let (r, _) = a.dims2()?;
+            if b.dims2()?.1 != r {
+                candle_core::bail!("LoRA rank mismatch for {}: A {:?}, B {:?}", module, a, b);
+            }
+            let scale = match &config {
+                Some(config) => config.lora_alpha / config.r as f64,
+                None => 1.0,
+            };
+            weights.insert(
+                module.to_string(),
+                LoraWeights {
+                    a: a.to_dtype(dtype)?,
+                    b: b.to_dtype(dtype)?,
+                    scale,
+                },
+            );
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            weights,
+        })
+    }
+
+    /// Merges the adapter into base weights keyed `<module>.weight`, returning layers touched
+    pub fn merge_into(&self, base: &mut HashMap<String, Tensor>) -> Result<usize> {
+        for (module, lora) in self.weights.iter() {
+            let key = format!("{}.weight", module);
+            let Some(weight) = base.get(&key) else {
+                candle_core::bail!("Base checkpoint has no weight {} for LoRA", key);
+            };
+            let delta = lora.delta()?.to_dtype(weight.dtype())?;
+            let merged = (weight + delta)?;
+            base.insert(key, merged);
+        }
+        Ok(self.weights.len())
+    }
+}
+
+/// Linear layer with optional unmerged LoRA adapters, of which at most one is active
+pub struct LoraLinear {
+    base: LinearLayer,
+    /// Module path, used to match adapter weights
+    path: String,
+    adapters: HashMap<String, LoraWeights>,
+    active: Option<String>,
+}
+
+impl LoraLinear {
+    pub fn new(base: LinearLayer, path: String) -> Self {
+        Self {
+            base,
+            path,
+            adapters: HashMap::new(),
+            active: None,
+        }
+    }
+
+    /// Loads the base weight `<name>.weight` under `vb`
+    pub fn load(
+        linear_loader: &LinearLoader,
+        vb: &VarBuilder,
+        shape: (usize, usize),
+        name: &str,
+    ) -> Result<Self> {
+        let base = linear_loader.load(vb, shape, &format!("{}.weight", name))?;
+        let path = match vb.prefix() {
+            prefix if prefix.is_empty() => name.to_string(),
+            prefix => format!("{}.{}", prefix, name),
+        };
+        Ok(Self::new(base, path))
+    }
+
+    /// Returns whether the adapter has weights for this layer
+    pub fn add_adapter(&mut self, adapter: &LoraAdapter) -> bool {
+        match adapter.weights.get(&self.path) {
+            Some(weights) => {
+                self.adapters.insert(adapter.name.clone(), weights.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove_adapter(&mut self, name: &str) {
+        self.adapters.remove(name);
+        if self.active.as_deref() == Some(name) {
+            self.active = None;
+        }
+    }
+
+    /// Adapters that do not touch this layer leave it at the base weights
+    pub fn set_active(&mut self, name: Option<&str>) {
+        self.active = name
+            .filter(|name| self.adapters.contains_key(*name))
+            .map(str::to_string);
+    }
+}
+
+impl Module for LoraLinear {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        let out = self.base.forward(xs)?;
+        match self
+            .active
+            .as_ref()
+            .and_then(|name| self.adapters.get(name))
+        {
+            None => Ok(out),
+            Some(lora) => {
+                let low_rank = xs
+                    .to_dtype(lora.a.dtype())?
+                    .broadcast_matmul(&lora.a.t()?)?
+                    .broadcast_matmul(&lora.b.t()?)?;
+                let low_rank = (low_rank * lora.scale)?.to_dtype(out.dtype())?;
+                out + low_rank
+            }
+        }
+    }
+}
@@ -1,6 +1,8 @@
 pub mod dual_ar;
 pub mod generate;
+pub mod lora;
 mod ops;
+pub mod quantized;
 pub mod sampling;
 
 pub use dual_ar::BaseModelArgs;
@@ -0,0 +1,215 @@
+use crate::config::WhichQuantization;
+use crate::lm::dual_ar::BaseModelArgs;
+use candle_core::quantized::{GgmlDType, QMatMul, QTensor, gguf_file};
+use candle_core::{DType, Device, Result, Tensor};
+use candle_nn::{Linear, Module, VarBuilder};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+const QUANTIZATION_METADATA_KEY: &str = "fish_speech.quantization";
+
+impl WhichQuantization {
+    pub fn ggml_dtype(&self) -> GgmlDType {
+        match self {
+            Self::Q8_0 => GgmlDType::Q8_0,
+            Self::Q4_0 => GgmlDType::Q4_0,
+            Self::Q4K => GgmlDType::Q4K,
+        }
+    }
+}
+
+/// Linear projection (no bias) that is either full precision or a quantized matmul
+pub enum LinearLayer {
+    Full(Linear),
+    Quantized(QMatMul),
+}
+
+impl LinearLayer {
+    /// Full-precision weight, for callers that slice the projection directly
+    pub fn weight(&self) -> Result<&Tensor> {
+        match self {
+            Self::Full(linear) => Ok(linear.weight()),
+            Self::Quantized(_) => candle_core::bail!("Cannot slice weights of a quantized layer"),
+        }
+    }
+}
+
+impl Module for LinearLayer {
+    fn forward(&self, xs: &Tensor) -> Result<Tensor> {
+        match self {
+            Self::Full(linear) => linear.forward(xs),
+            Self::Quantized(qmatmul) => {
+                // Quantized kernels only take contiguous F32 activations
+                let dtype = xs.dtype();
+                qmatmul
+                    .forward(&xs.to_dtype(DType::F32)?.contiguous()?)?
+                    .to_dtype(dtype)
+            }
+        }
+    }
+}
+
+/// Decides how linear weights are materialized when loading the LM
+#[derive(Clone, Default)]
+pub struct LinearLoader {
+    quantization: Option<WhichQuantization>,
+    /// Already-quantized weights from a GGUF checkpoint, keyed by full tensor name
+    prequantized: Arc<HashMap<String, Arc<QTensor>>>,
+}
+
+impl LinearLoader {
+    pub fn full_precision() -> Self {
+        Self::default()
+    }
+
+    /// Quantizes full-precision weights as they are loaded
+    pub fn quantize_on_load(quantization: Option<WhichQuantization>) -> Self {
+        Self {
+            quantization,
+            prequantized: Arc::new(HashMap::new()),
+        }
+    }
+
+    pub fn quantization(&self) -> Option<WhichQuantization> {
+        self.quantization
+    }
+
+    pub fn load(&self, vb: &VarBuilder, shape: (usize, usize), name: &str) -> Result<LinearLayer> {
+        let full_name = match vb.prefix() {
+            prefix if prefix.is_empty() => name.to_string(),
+            prefix => format!("{}.{}", prefix, name),
+        };
+        if let Some(qtensor) = self.prequantized.get(&full_name) {
+            if qtensor.shape().dims2()? != shape {
+                candle_core::bail!(
+                    "Quantized weight {} has shape {:?}, expected {:?}",
+                    full_name,
+                    qtensor.shape(),
+                    shape
+                );
+            }
+            return Ok(LinearLayer::Quantized(QMatMul::from_arc(qtensor.clone())?));
+        }
+
+        let weight = vb.get(shape, name)?;
+        match self.quantization {
+            Some(quantization)
+                if shape
+                    .1
+                    .is_multiple_of(quantization.ggml_dtype().block_size()) =>
+            {
+                let qtensor =
+                    QTensor::quantize(&weight.to_dtype(DType::F32)?, quantization.ggml_dtype())?;
+                Ok(LinearLayer::Quantized(QMatMul::from_qtensor(qtensor)?))
+            }
+            _ => Ok(LinearLayer::Full(Linear::new(weight, None))),
+        }
+    }
+
+    /// Always loads in full precision, e.g. for weights that get sliced at runtime
+    pub fn load_full(
+        &self,
+        vb: &VarBuilder,
+        shape: (usize, usize),
+        name: &str,
+    ) -> Result<LinearLayer> {
+        Ok(LinearLayer::Full(Linear::new(vb.get(shape, name)?, None)))
+    }
+}
+
+/// Whether a checkpoint tensor is a linear weight that should be quantized
+pub fn is_quantizable_linear(name: &str, cfg: &BaseModelArgs) -> bool {
+    const LINEAR_SUFFIXES: [&str; 5] = [
+        ".wqkv.weight",
+        ".wo.weight",
+        ".w1.weight",
+        ".w2.weight",
+        ".w3.weight",
+    ];
+    LINEAR_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+        || name == "output.weight"
+        // Depthwise fast output is sliced per codebook, so keep it dense
+        || (name == "fast_output.weight" && cfg.depthwise_output != Some(true))
+}
+
+/// Loads a GGUF checkpoint written by [`quantize_safetensors`].
+///
+/// Returns a `VarBuilder` over the dense tensors (norms, embeddings) and a loader holding
+/// the quantized linear weights.
+pub fn load_gguf(
+    path: &Path,
+    dtype: DType,
+    device: &Device,
+) -> Result<(VarBuilder<'static>, LinearLoader)> {
+    let mut file = std::fs::File::open(path)?;
+    let content = gguf_file::Content::read(&mut file)?;
+    let quantization =
+        match content.metadata.get(QUANTIZATION_METADATA_KEY) {
+            Some(value) => Some(WhichQuantization::from_name(value.to_string()?).ok_or_else(
+                || candle_core::Error::Msg(format!("Unknown quantization in {:?}", path)),
+            )?),
+            None => None,
+        };
+
+    let mut dense = HashMap::new();
+    let mut prequantized = HashMap::new();
+    for (name, info) in content.tensor_infos.iter() {
+        let qtensor = content.tensor(&mut file, name, device)?;
+        match info.ggml_dtype {
+            GgmlDType::F32 | GgmlDType::F16 => {
+                dense.insert(name.clone(), qtensor.dequantize(device)?.to_dtype(dtype)?);
+            }
+            _ => {
+                prequantized.insert(name.clone(), Arc::new(qtensor));
+            }
+        }
+    }
+
+    Ok((
+        VarBuilder::from_tensors(dense, dtype, device),
+        LinearLoader {
+            quantization,
+            prequantized: Arc::new(prequantized),
+        },
+    ))
+}
+
+/// Quantizes the linear layers of a safetensors LM checkpoint and writes it out as GGUF.
+///
+/// Returns (quantized tensors, dense tensors) written.
+pub fn quantize_safetensors(
+    input: &Path,
+    output: &Path,
+    cfg: &BaseModelArgs,
+    quantization: WhichQuantization,
+) -> Result<(usize, usize)> {
+    let tensors = candle_core::safetensors::load(input, &Device::Cpu)?;
+    let ggml_dtype = quantization.ggml_dtype();
+
+    let mut names: Vec<&String> = tensors.keys().collect();
+    names.sort();
+    let mut qtensors = Vec::with_capacity(names.len());
+    let (mut n_quantized, mut n_dense) = (0, 0);
+    for name in names {
+        let tensor = tensors[name].to_dtype(DType::F32)?;
+        let quantize = is_quantizable_linear(name, cfg)
+            && tensor.rank() == 2
+            && tensor.dim(1)?.is_multiple_of(ggml_dtype.block_size());
+        let qtensor = if quantize {
+            n_quantized += 1;
+            QTensor::quantize(&tensor, ggml_dtype)?
+        } else {
+            n_dense += 1;
+            QTensor::quantize(&tensor, GgmlDType::F32)?
+        };
+        qtensors.push((name.as_str(), qtensor));
+    }
+
+    let quantization_value = gguf_file::Value::String(quantization.as_str().to_string());
+    let metadata = [(QUANTIZATION_METADATA_KEY, &quantization_value)];
+    let tensor_refs: Vec<(&str, &QTensor)> = qtensors.iter().map(|(n, t)| (*n, t)).collect();
+    let mut out_file = std::fs::File::create(output)?;
+    gguf_file::write(&mut out_file, &metadata, &tensor_refs)?;
+    Ok((n_quantized, n_dense))
+}
@@ -1,11 +1,22 @@
-pub mod rep_pen;
+pub mod processors;
 use candle_core::{D, DType, Result, Tensor};
-use candle_nn::ops::softmax_last_dim;
-use rand::{Rng, SeedableRng, distributions::Distribution};
+use processors::{LogitsChain, LogitsProcessor, TokenBias, TokenHistory};
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 /// Extremely stripped-down CPU softmax for slow model out
-pub fn legacy_softmax_sample(pad_prob: f32, eos_prob: f32, pad_id: u32, eos_id: u32) -> u32 {
+///
+/// `eos_bias` is added to the EOS logit; `-inf` forces PAD
+pub fn legacy_softmax_sample(
+    pad_prob: f32,
+    eos_prob: f32,
+    pad_id: u32,
+    eos_id: u32,
+    eos_bias: f32,
+) -> u32 {
+    let eos_prob = eos_prob + eos_bias;
     // Compute softmax
     let exp_pad = (pad_prob - pad_prob.max(eos_prob)).exp();
     let exp_eos = (eos_prob - pad_prob.max(eos_prob)).exp();
@@ -31,102 +42,315 @@ pub struct SamplingArgs {
     pub top_p: f64,
     pub top_k: usize,
     pub repetition_penalty: f32,
+    /// 0 disables
+    pub min_p: f64,
+    /// 1 disables
+    pub typical_p: f64,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+    /// Frames of history the penalties look at; each generator has its own default
+    pub rep_pen_window: Option<usize>,
+    /// Semantic head changes. By default it shares temperature and filters, without penalties
+    pub slow: HeadOverrides,
+    /// Codebook head changes. By default it shares everything, except that penalties are off
+    /// for `depthwise_wte` models
+    pub fast: HeadOverrides,
+    /// Audio-only end-of-speech steering
+    pub eos: EosControl,
 }
 
-/// Simplified for my use case.
-/// Drawn heavily from [Candle Transformers](https://docs.rs/candle-transformers/latest/src/candle_transformers/generation/mod.rs.html#18-21)
-///
-/// Thanks again, Kyutai
-pub struct BatchedLogitsProcessor {
-    rng: rand::rngs::StdRng,
-    sampling_args: SamplingArgs,
+impl Default for SamplingArgs {
+    fn default() -> Self {
+        Self {
+            temp: 0.7,
+            top_p: 0.8,
+            top_k: 256,
+            repetition_penalty: 1.2,
+            min_p: 0.0,
+            typical_p: 1.0,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            rep_pen_window: None,
+            slow: HeadOverrides::default(),
+            fast: HeadOverrides::default(),
+            eos: EosControl::default(),
+        }
+    }
 }
 
-impl BatchedLogitsProcessor {
-    pub fn new(seed: u64, sampling_args: SamplingArgs) -> Self {
-        let rng = rand::rngs::StdRng::seed_from_u64(seed);
-        Self { rng, sampling_args }
+/// Steers when audio generation ends by biasing the `<|im_end|>` logit
+#[derive(Clone, Debug, Default)]
+pub struct EosControl {
+    /// `<|im_end|>` can't be sampled before this many frames
+    pub min_frames: usize,
+    /// Frame count the bias ramps toward; if unset, the EOS logit is left alone after `min_frames`
+    pub target_frames: Option<usize>,
+    /// Bias at 0 frames is `-bias`, rising linearly through 0 at the target to `+bias` at twice the target
+    pub bias: f32,
+}
+
+impl EosControl {
+    pub const DEFAULT_BIAS: f32 = 5.0;
+
+    /// Converts durations in seconds to frames at `frame_rate`
+    pub fn from_durations(
+        min_duration: Option<f64>,
+        target_duration: Option<f64>,
+        frame_rate: f64,
+        bias: Option<f32>,
+    ) -> Self {
+        Self {
+            min_frames: min_duration.map_or(0, |d| (d * frame_rate).ceil() as usize),
+            target_frames: target_duration.map(|d| (d * frame_rate).round().max(1.0) as usize),
+            bias: bias.unwrap_or(Self::DEFAULT_BIAS),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.min_frames > 0 || self.target_frames.is_some()
     }
 
-    fn sample_single_top_p_k(
-        rng: &mut rand::rngs::StdRng,
-        mut probs: Vec<f32>,
-        top_k: usize,
-        top_p: f64,
-    ) -> u32 {
-        if top_k >= probs.len() {
-            return sample_topp(rng, &mut probs, top_p as f32);
+    /// Bias for the `<|im_end|>` logit after `n_generated` frames
+    pub fn eos_bias(&self, n_generated: usize) -> f32 {
+        if n_generated < self.min_frames {
+            return f32::NEG_INFINITY;
         }
+        match self.target_frames {
+            Some(target) if target > 0 => {
+                let progress = n_generated as f32 / target as f32 - 1.0;
+                self.bias * progress.clamp(-1.0, 1.0)
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// Twice the target if set (where the bias saturates), else `default`; never below the minimum
+    pub fn max_new_tokens(&self, default: usize) -> usize {
+        let budget = self.target_frames.map_or(default, |target| target * 2);
+        budget.max(self.min_frames + 1)
+    }
+}
 
-        let mut indices: Vec<_> = (0..probs.len()).collect();
-        let (topk_indices, _, _) =
-            indices.select_nth_unstable_by(top_k, |&i, &j| probs[j].total_cmp(&probs[i]));
+/// Fully resolved settings for one sampling head
+#[derive(Clone, Debug)]
+pub struct HeadSamplingArgs {
+    pub temp: f64,
+    pub top_p: f64,
+    pub top_k: usize,
+    pub min_p: f64,
+    pub typical_p: f64,
+    pub repetition_penalty: f32,
+    pub frequency_penalty: f32,
+    pub presence_penalty: f32,
+    pub rep_pen_window: usize,
+}
 
-        let mut topk_probs: Vec<_> = topk_indices.iter().map(|&i| probs[i]).collect();
-        let sum_p = topk_probs.iter().sum::<f32>();
+impl SamplingArgs {
+    fn shared_head(&self, default_window: usize, penalize: bool) -> HeadSamplingArgs {
+        HeadSamplingArgs {
+            temp: self.temp,
+            top_p: self.top_p,
+            top_k: self.top_k,
+            min_p: self.min_p,
+            typical_p: self.typical_p,
+            repetition_penalty: if penalize {
+                self.repetition_penalty
+            } else {
+                1.0
+            },
+            frequency_penalty: if penalize {
+                self.frequency_penalty
+            } else {
+                0.0
+            },
+            presence_penalty: if penalize { self.presence_penalty } else { 0.0 },
+            rep_pen_window: self.rep_pen_window.unwrap_or(default_window),
+        }
+    }
 
-        let index = if top_p <= 0.0 || top_p >= sum_p as f64 {
-            sample_multinomial(rng, &topk_probs)
-        } else {
-            sample_topp(rng, &mut topk_probs, top_p as f32)
-        };
+    /// Raises the temperature of both heads by `delta`, including per-head overrides
+    pub fn bump_temperature(&mut self, delta: f64) {
+        self.temp += delta;
+        for head in [&mut self.slow, &mut self.fast] {
+            if let Some(temp) = head.temp.as_mut() {
+                *temp += delta;
+            }
+        }
+    }
 
-        topk_indices[index as usize] as u32
+    pub fn slow_head(&self, default_window: usize) -> HeadSamplingArgs {
+        self.slow.apply(self.shared_head(default_window, false))
     }
 
-    pub fn sample(&mut self, logits: &Tensor) -> Result<Vec<u32>> {
-        let logits = logits.to_dtype(DType::F32)?;
+    pub fn fast_head(&self, default_window: usize, depthwise_wte: bool) -> HeadSamplingArgs {
+        self.fast
+            .apply(self.shared_head(default_window, !depthwise_wte))
+    }
+}
 
-        if self.sampling_args.temp <= 1e-7 {
-            // Argmax sampling! yay
-            return logits
-                .argmax(D::Minus1)?
-                .to_device(&candle_core::Device::Cpu)?
-                .flatten_all()?
-                .to_vec1::<u32>();
+/// Settings for one head that differ from what it would otherwise use
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HeadOverrides {
+    pub temp: Option<f64>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<usize>,
+    pub min_p: Option<f64>,
+    pub typical_p: Option<f64>,
+    pub repetition_penalty: Option<f32>,
+    pub frequency_penalty: Option<f32>,
+    pub presence_penalty: Option<f32>,
+    pub rep_pen_window: Option<usize>,
+}
+
+impl HeadOverrides {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn apply(&self, head: HeadSamplingArgs) -> HeadSamplingArgs {
+        HeadSamplingArgs {
+            temp: self.temp.unwrap_or(head.temp),
+            top_p: self.top_p.unwrap_or(head.top_p),
+            top_k: self.top_k.unwrap_or(head.top_k),
+            min_p: self.min_p.unwrap_or(head.min_p),
+            typical_p: self.typical_p.unwrap_or(head.typical_p),
+            repetition_penalty: self.repetition_penalty.unwrap_or(head.repetition_penalty),
+            frequency_penalty: self.frequency_penalty.unwrap_or(head.frequency_penalty),
+            presence_penalty: self.presence_penalty.unwrap_or(head.presence_penalty),
+            rep_pen_window: self.rep_pen_window.unwrap_or(head.rep_pen_window),
         }
-        let probs = softmax_last_dim(&(&logits / self.sampling_args.temp)?)?
-            .squeeze(1)?
+    }
+
+    /// These overrides, with `other`'s taking precedence where both are set
+    pub fn merge(&self, other: &Self) -> Self {
+        Self {
+            temp: other.temp.or(self.temp),
+            top_p: other.top_p.or(self.top_p),
+            top_k: other.top_k.or(self.top_k),
+            min_p: other.min_p.or(self.min_p),
+            typical_p: other.typical_p.or(self.typical_p),
+            repetition_penalty: other.repetition_penalty.or(self.repetition_penalty),
+            frequency_penalty: other.frequency_penalty.or(self.frequency_penalty),
+            presence_penalty: other.presence_penalty.or(self.presence_penalty),
+            rep_pen_window: other.rep_pen_window.or(self.rep_pen_window),
+        }
+    }
+
+    /// Sets the setting called `name`, as in [`SamplingArgs`]
+    pub fn set(&mut self, name: &str, value: f64) -> anyhow::Result<()> {
+        let count = || {
+            if value >= 0.0 && value.fract() == 0.0 {
+                Ok(value as usize)
+            } else {
+                anyhow::bail!("{} must be a non-negative integer, not {}", name, value)
+            }
+        };
+        match name {
+            "temp" => self.temp = Some(value),
+            "top_p" => self.top_p = Some(value),
+            "top_k" => self.top_k = Some(count()?),
+            "min_p" => self.min_p = Some(value),
+            "typical_p" => self.typical_p = Some(value),
+            "repetition_penalty" => self.repetition_penalty = Some(value as f32),
+            "frequency_penalty" => self.frequency_penalty = Some(value as f32),
+            "presence_penalty" => self.presence_penalty = Some(value as f32),
+            "rep_pen_window" => self.rep_pen_window = Some(count()?),
+            other => anyhow::bail!("Unknown sampling setting {:?}", other),
+        }
+        Ok(())
+    }
+}
+
+/// Parses `temp=0.5,top_k=30`
+impl FromStr for HeadOverrides {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut overrides = Self::default();
+        for pair in s.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("Expected name=value, not {:?}", pair))?;
+            let value: f64 = value
+                .trim()
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid value for {}: {:?}", name, value))?;
+            overrides.set(name.trim(), value)?;
+        }
+        Ok(overrides)
+    }
+}
+
+/// Samples rows of logits through a [`LogitsChain`].
+/// Originally drawn from [Candle Transformers](https://docs.rs/candle-transformers/latest/src/candle_transformers/generation/mod.rs.html#18-21)
+///
+/// Thanks again, Kyutai
+pub struct BatchedLogitsProcessor {
+    rng: rand::rngs::StdRng,
+    chain: LogitsChain,
+}
+
+impl BatchedLogitsProcessor {
+    pub fn new(seed: u64, head_args: &HeadSamplingArgs) -> Self {
+        Self::from_chain(seed, LogitsChain::from_args(head_args))
+    }
+
+    pub fn from_chain(seed: u64, chain: LogitsChain) -> Self {
+        let rng = rand::rngs::StdRng::seed_from_u64(seed);
+        Self { rng, chain }
+    }
+
+    /// Samples one token per row of `logits` (..., vocab).
+    ///
+    /// `histories` holds one entry per row for the penalties; `None` means no history.
+    pub fn sample(
+        &mut self,
+        logits: &Tensor,
+        histories: Option<&[TokenHistory]>,
+    ) -> Result<Vec<u32>> {
+        self.sample_biased(logits, histories, None)
+    }
+
+    /// As [`Self::sample`], first adding `bias` to every row
+    pub fn sample_biased(
+        &mut self,
+        logits: &Tensor,
+        histories: Option<&[TokenHistory]>,
+        bias: Option<TokenBias>,
+    ) -> Result<Vec<u32>> {
+        let vocab_size = logits.dim(D::Minus1)?;
+        let rows = logits
+            .to_dtype(DType::F32)?
+            .reshape(((), vocab_size))?
             .to_vec2::<f32>()?;
+        if let Some(histories) = histories
+            && histories.len() != rows.len()
+        {
+            candle_core::bail!(
+                "Expected {} token histories but got {}",
+                rows.len(),
+                histories.len()
+            );
+        }
+        let empty = TokenHistory::new(0);
 
         // Split RNG into independent streams
-        let rngs = (0..probs.len())
+        let rngs = (0..rows.len())
             .map(|_| rand::rngs::StdRng::seed_from_u64(self.rng.r#gen()))
             .collect::<Vec<_>>();
 
-        Ok(probs
+        Ok(rows
             .into_par_iter()
             .zip(rngs)
-            .map(|(prob_row, mut rng)| {
-                Self::sample_single_top_p_k(
-                    &mut rng,
-                    prob_row,
-                    self.sampling_args.top_k,
-                    self.sampling_args.top_p,
-                )
+            .enumerate()
+            .map(|(i, (mut row, mut rng))| {
+                let history = histories.map(|h| &h[i]).unwrap_or(&empty);
+                if let Some(bias) = &bias {
+                    bias.process(&mut row, history);
+                }
+                self.chain.sample(row, history, &mut rng)
             })
             .collect())
     }
 }
-
-// Helper functions stripped from original code
-fn sample_multinomial(rng: &mut rand::rngs::StdRng, probs: &[f32]) -> u32 {
-    rand::distributions::WeightedIndex::new(probs)
-        .map(|dist| dist.sample(rng) as u32)
-        .unwrap_or(0)
-}
-
-fn sample_topp(rng: &mut rand::rngs::StdRng, probs: &mut [f32], top_p: f32) -> u32 {
-    let mut indices: Vec<_> = (0..probs.len()).collect();
-    indices.sort_by(|&i, &j| probs[j].total_cmp(&probs[i]));
-
-    let mut cumsum = 0.0;
-    for &idx in &indices {
-        if cumsum >= top_p {
-            probs[idx] = 0.0;
-        }
-        cumsum += probs[idx];
-    }
-
-    sample_multinomial(rng, probs)
-}
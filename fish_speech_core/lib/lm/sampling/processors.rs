@@ -0,0 +1,301 @@
+use super::HeadSamplingArgs;
+use rand::distributions::Distribution;
+use std::collections::{HashMap, VecDeque};
+
+/// Recently sampled tokens of one sequence on one head, for penalties
+#[derive(Debug, Clone)]
+pub struct TokenHistory {
+    window: VecDeque<u32>,
+    counts: HashMap<u32, u32>,
+    max_len: usize,
+}
+
+impl TokenHistory {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            window: VecDeque::with_capacity(max_len),
+            counts: HashMap::new(),
+            max_len,
+        }
+    }
+
+    pub fn push(&mut self, token: u32) {
+        if self.max_len == 0 {
+            return;
+        }
+        self.window.push_front(token);
+        *self.counts.entry(token).or_insert(0) += 1;
+        if self.window.len() > self.max_len
+            && let Some(dropped) = self.window.pop_back()
+            && let Some(count) = self.counts.get_mut(&dropped)
+        {
+            *count -= 1;
+            if *count == 0 {
+                self.counts.remove(&dropped);
+            }
+        }
+    }
+
+    /// (token, occurrences) within the window
+    pub fn counts(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.counts.iter().map(|(&token, &count)| (token, count))
+    }
+
+    pub fn clear(&mut self) {
+        self.window.clear();
+        self.counts.clear();
+    }
+}
+
+/// One in-place step over a row of logits. Filters set excluded tokens to `-inf`.
+pub trait LogitsProcessor: Send + Sync {
+    fn process(&self, logits: &mut [f32], history: &TokenHistory);
+}
+
+/// Divides the logits of tokens seen in the window by the penalty
+pub struct RepetitionPenalty(pub f32);
+
+impl LogitsProcessor for RepetitionPenalty {
+    fn process(&self, logits: &mut [f32], history: &TokenHistory) {
+        for (token, _) in history.counts() {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit /= self.0;
+            }
+        }
+    }
+}
+
+/// Subtracts `penalty * count` for each token seen in the window
+pub struct FrequencyPenalty(pub f32);
+
+impl LogitsProcessor for FrequencyPenalty {
+    fn process(&self, logits: &mut [f32], history: &TokenHistory) {
+        for (token, count) in history.counts() {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit -= self.0 * count as f32;
+            }
+        }
+    }
+}
+
+/// Subtracts a flat penalty from every token seen in the window
+pub struct PresencePenalty(pub f32);
+
+impl LogitsProcessor for PresencePenalty {
+    fn process(&self, logits: &mut [f32], history: &TokenHistory) {
+        for (token, _) in history.counts() {
+            if let Some(logit) = logits.get_mut(token as usize) {
+                *logit -= self.0;
+            }
+        }
+    }
+}
+
+/// Adds a fixed bias to one token's logit
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBias {
+    pub token: usize,
+    pub bias: f32,
+}
+
+impl LogitsProcessor for TokenBias {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        if let Some(logit) = logits.get_mut(self.token) {
+            *logit += self.bias;
+        }
+    }
+}
+
+pub struct Temperature(pub f64);
+
+impl LogitsProcessor for Temperature {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        let inv_temp = (1.0 / self.0) as f32;
+        logits.iter_mut().for_each(|logit| *logit *= inv_temp);
+    }
+}
+
+/// Keeps the `k` most likely tokens
+pub struct TopK(pub usize);
+
+impl LogitsProcessor for TopK {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        if self.0 == 0 || self.0 >= logits.len() {
+            return;
+        }
+        let mut sorted = logits.to_vec();
+        sorted.select_nth_unstable_by(self.0 - 1, |a, b| b.total_cmp(a));
+        let threshold = sorted[self.0 - 1];
+        // Ties at the threshold may keep a few more than k
+        logits
+            .iter_mut()
+            .filter(|logit| **logit < threshold)
+            .for_each(|logit| *logit = f32::NEG_INFINITY);
+    }
+}
+
+/// Keeps the smallest set of tokens whose probability mass reaches `p`
+pub struct TopP(pub f64);
+
+impl LogitsProcessor for TopP {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        if self.0 <= 0.0 || self.0 >= 1.0 {
+            return;
+        }
+        let probs = softmax(logits);
+        let mut indices: Vec<usize> = (0..logits.len()).collect();
+        indices.sort_unstable_by(|&i, &j| probs[j].total_cmp(&probs[i]));
+        let mut cumsum = 0.0;
+        for idx in indices {
+            if cumsum >= self.0 as f32 {
+                logits[idx] = f32::NEG_INFINITY;
+            }
+            cumsum += probs[idx];
+        }
+    }
+}
+
+/// Drops tokens less likely than `p` times the most likely one
+pub struct MinP(pub f64);
+
+impl LogitsProcessor for MinP {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        if self.0 <= 0.0 {
+            return;
+        }
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        // p_i / p_max < min_p  <=>  logit_i - logit_max < ln(min_p)
+        let threshold = max + (self.0 as f32).ln();
+        logits
+            .iter_mut()
+            .filter(|logit| **logit < threshold)
+            .for_each(|logit| *logit = f32::NEG_INFINITY);
+    }
+}
+
+/// Locally typical sampling: keeps the tokens whose surprisal is closest to the
+/// distribution's entropy, until their mass reaches `p`
+pub struct TypicalP(pub f64);
+
+impl LogitsProcessor for TypicalP {
+    fn process(&self, logits: &mut [f32], _: &TokenHistory) {
+        if self.0 <= 0.0 || self.0 >= 1.0 {
+            return;
+        }
+        let probs = softmax(logits);
+        let entropy: f32 = probs
+            .iter()
+            .filter(|p| **p > 0.0)
+            .map(|p| -p * p.ln())
+            .sum();
+        let mut indices: Vec<usize> = (0..logits.len()).filter(|&i| probs[i] > 0.0).collect();
+        let deviation = |i: usize| (-probs[i].ln() - entropy).abs();
+        indices.sort_unstable_by(|&i, &j| deviation(i).total_cmp(&deviation(j)));
+        let mut cumsum = 0.0;
+        let mut keep = vec![false; logits.len()];
+        for idx in indices {
+            keep[idx] = true;
+            cumsum += probs[idx];
+            if cumsum >= self.0 as f32 {
+                break;
+            }
+        }
+        logits
+            .iter_mut()
+            .zip(keep)
+            .filter(|(_, keep)| !keep)
+            .for_each(|(logit, _)| *logit = f32::NEG_INFINITY);
+    }
+}
+
+/// Ordered logits processors followed by multinomial sampling, or argmax at temperature 0
+pub struct LogitsChain {
+    processors: Vec<Box<dyn LogitsProcessor>>,
+    greedy: bool,
+}
+
+impl LogitsChain {
+    pub fn new(greedy: bool) -> Self {
+        Self {
+            processors: Vec::new(),
+            greedy,
+        }
+    }
+
+    pub fn with(mut self, processor: impl LogitsProcessor + 'static) -> Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// Penalties, then temperature, then filters; inactive settings are left out
+    pub fn from_args(args: &HeadSamplingArgs) -> Self {
+        let greedy = args.temp <= 1e-7;
+        let mut chain = Self::new(greedy);
+        if args.repetition_penalty != 1.0 {
+            chain = chain.with(RepetitionPenalty(args.repetition_penalty));
+        }
+        if args.frequency_penalty != 0.0 {
+            chain = chain.with(FrequencyPenalty(args.frequency_penalty));
+        }
+        if args.presence_penalty != 0.0 {
+            chain = chain.with(PresencePenalty(args.presence_penalty));
+        }
+        if greedy {
+            return chain;
+        }
+        if args.temp != 1.0 {
+            chain = chain.with(Temperature(args.temp));
+        }
+        if args.typical_p > 0.0 && args.typical_p < 1.0 {
+            chain = chain.with(TypicalP(args.typical_p));
+        }
+        if args.min_p > 0.0 {
+            chain = chain.with(MinP(args.min_p));
+        }
+        if args.top_k > 0 {
+            chain = chain.with(TopK(args.top_k));
+        }
+        if args.top_p > 0.0 && args.top_p < 1.0 {
+            chain = chain.with(TopP(args.top_p));
+        }
+        chain
+    }
+
+    pub fn process(&self, logits: &mut [f32], history: &TokenHistory) {
+        for processor in self.processors.iter() {
+            processor.process(logits, history);
+        }
+    }
+
+    pub fn sample(
+        &self,
+        mut logits: Vec<f32>,
+        history: &TokenHistory,
+        rng: &mut rand::rngs::StdRng,
+    ) -> u32 {
+        self.process(&mut logits, history);
+        if self.greedy {
+            return argmax(&logits);
+        }
+        let probs = softmax(&logits);
+        rand::distributions::WeightedIndex::new(&probs)
+            .map(|dist| dist.sample(rng) as u32)
+            .unwrap_or_else(|_| argmax(&logits))
+    }
+}
+
+fn argmax(logits: &[f32]) -> u32 {
+    logits
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i as u32)
+        .unwrap_or(0)
+}
+
+fn softmax(logits: &[f32]) -> Vec<f32> {
+    let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = logits.iter().map(|l| (l - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / sum).collect()
+}
@@ -0,0 +1,190 @@
+use super::clean::{is_hanzi, is_kana};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use tokenizers::Tokenizer;
+
+/// A word of the generated text and the frames it was spoken over.
+///
+/// Words come from the text the model read, not the caller's input before normalization.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WordAlignment {
+    pub word: String,
+    pub start_frame: usize,
+    /// Exclusive
+    pub end_frame: usize,
+}
+
+/// Slow layers to capture attention from when none are configured: the middle third, which
+/// tends to track the text more cleanly than the first or last layers
+pub fn default_alignment_layers(n_layers: usize) -> Vec<usize> {
+    let start = n_layers / 3;
+    let end = (2 * n_layers).div_ceil(3).max(start + 1).min(n_layers);
+    (start..end).collect()
+}
+
+/// Positions of the last occurrence of `text_ids` in `prompt_ids`.
+///
+/// Tokenizing the text on its own can differ at the first token from tokenizing it inside the
+/// chat template, so a match of the rest is accepted too, taking the token before it as the first.
+pub fn find_text_tokens(prompt_ids: &[u32], text_ids: &[u32]) -> Option<Range<usize>> {
+    let rfind = |needle: &[u32]| {
+        (!needle.is_empty() && needle.len() <= prompt_ids.len())
+            .then(|| {
+                (0..=prompt_ids.len() - needle.len())
+                    .rev()
+                    .find(|&i| &prompt_ids[i..i + needle.len()] == needle)
+            })
+            .flatten()
+    };
+    if let Some(start) = rfind(text_ids) {
+        return Some(start..start + text_ids.len());
+    }
+    let start = rfind(&text_ids[1.min(text_ids.len())..])?.checked_sub(1)?;
+    Some(start..start + text_ids.len())
+}
+
+/// Monotonic alignment of frames to text tokens from (n_frames, n_tokens) attention.
+///
+/// Each frame is assigned one token; from frame to frame the token stays the same or moves
+/// forward by one or two (a skipped token is usually punctuation). Returns the frames of each
+/// token; skipped tokens get an empty range where they would have been.
+pub fn monotonic_alignment(attention: &[Vec<f32>]) -> Vec<Range<usize>> {
+    let n_tokens = attention.first().map_or(0, |row| row.len());
+    if n_tokens == 0 {
+        return Vec::new();
+    }
+    let n_frames = attention.len();
+    let score = |row: &[f32], j: usize| {
+        let total: f32 = row.iter().sum();
+        (row[j] / total.max(f32::EPSILON)).max(1e-8).ln()
+    };
+
+    // Best path score ending at each token, and where each step came from
+    let mut cost = vec![f32::NEG_INFINITY; n_tokens];
+    let mut back = vec![vec![0usize; n_tokens]; n_frames];
+    for j in 0..n_tokens.min(2) {
+        cost[j] = score(&attention[0], j);
+        back[0][j] = j;
+    }
+    for (i, row) in attention.iter().enumerate().skip(1) {
+        let mut next = vec![f32::NEG_INFINITY; n_tokens];
+        for j in 0..n_tokens {
+            let (prev, best) = (j.saturating_sub(2)..=j)
+                .map(|p| (p, cost[p]))
+                .fold((j, f32::NEG_INFINITY), |a, b| if b.1 > a.1 { b } else { a });
+            if best.is_finite() {
+                next[j] = best + score(row, j);
+                back[i][j] = prev;
+            }
+        }
+        cost = next;
+    }
+
+    // Prefer paths that reach the end of the text
+    let best_in = |range: Range<usize>| {
+        range
+            .filter(|&j| cost[j].is_finite())
+            .max_by(|&a, &b| cost[a].total_cmp(&cost[b]))
+    };
+    let mut j = best_in(n_tokens.saturating_sub(2)..n_tokens)
+        .or_else(|| best_in(0..n_tokens))
+        .unwrap_or(0);
+    let mut path = vec![0; n_frames];
+    for i in (0..n_frames).rev() {
+        path[i] = j;
+        j = back[i][j];
+    }
+
+    let mut spans: Vec<Range<usize>> = Vec::with_capacity(n_tokens);
+    for token in 0..n_tokens {
+        let start = path.partition_point(|&t| t < token);
+        let end = path.partition_point(|&t| t <= token);
+        spans.push(start..end);
+    }
+    spans
+}
+
+/// Groups token frame spans into words of `text`, given each token's byte offsets into it.
+///
+/// Words are whitespace-separated, except that CJK characters are words of their own.
+pub fn align_words(
+    text: &str,
+    offsets: &[(usize, usize)],
+    token_frames: &[Range<usize>],
+) -> Vec<WordAlignment> {
+    let mut words = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        let own_word = is_hanzi(c) || is_kana(c);
+        if c.is_whitespace() || own_word {
+            if let Some(s) = start.take() {
+                words.push(s..i);
+            }
+            if own_word {
+                words.push(i..i + c.len_utf8());
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(s) = start {
+        words.push(s..text.len());
+    }
+
+    let mut aligned: Vec<WordAlignment> = Vec::with_capacity(words.len());
+    for word in words {
+        // A token spanning several words (e.g. two CJK characters) is shared out by bytes
+        let frames = offsets
+            .iter()
+            .zip(token_frames)
+            // Zero-width tokens (special or merged ones) have no bytes to share frames out by
+            .filter(|((s, e), _)| s < e && *s < word.end && *e > word.start)
+            .map(|(&(s, e), frames)| {
+                let at = |byte: usize| frames.start + frames.len() * (byte - s) / (e - s);
+                at(s.max(word.start))..at(e.min(word.end))
+            })
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+        // Words no token covers (or only skipped ones) start where the last word ended
+        let prev_end = aligned.last().map_or(0, |w| w.end_frame);
+        let frames = frames.unwrap_or(prev_end..prev_end);
+        aligned.push(WordAlignment {
+            word: text[word].to_string(),
+            start_frame: frames.start.max(prev_end),
+            end_frame: frames.end.max(prev_end),
+        });
+    }
+    aligned
+}
+
+/// Aligns the words of `text` to the frames generated for it.
+///
+/// `prompt_ids` are the prompt's text-row tokens, which started at KV position `prompt_pos`, and
+/// `attention` has one row per generated frame over the KV positions it attended to.
+pub fn word_alignment(
+    tokenizer: &Tokenizer,
+    text: &str,
+    prompt_ids: &[u32],
+    prompt_pos: usize,
+    attention: &[Vec<f32>],
+) -> Result<Vec<WordAlignment>> {
+    let encoding = tokenizer
+        .encode(text, false)
+        .map_err(|e| anyhow::anyhow!("Could not tokenize: {:?}", e))?;
+    let span = find_text_tokens(prompt_ids, encoding.get_ids())
+        .context("Chunk text not found in its prompt")?;
+    let columns = prompt_pos + span.start..prompt_pos + span.end;
+    let text_attention: Vec<Vec<f32>> = attention
+        .iter()
+        .map(|row| {
+            row.get(columns.clone())
+                .map(<[f32]>::to_vec)
+                .context("Attention row doesn't cover the chunk text")
+        })
+        .collect::<Result<_>>()?;
+    Ok(align_words(
+        text,
+        encoding.get_offsets(),
+        &monotonic_alignment(&text_attention),
+    ))
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Where a chunk of text was spoken in the generated audio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkTiming {
+    /// The chunk as it was generated: after normalization, text rules and the lexicon, so `23`
+    /// in the input may be `twenty three` here
+    pub text: String,
+    pub start_sample: usize,
+    /// Exclusive; pauses after the chunk aren't included
+    pub end_sample: usize,
+    /// Semantic tokens (frames) generated for the chunk
+    pub tokens: usize,
+    /// Word-level timings of `text`, when requested
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordTiming>,
+}
+
+/// Where a word was spoken in the generated audio
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WordTiming {
+    pub word: String,
+    pub start_sample: usize,
+    /// Exclusive
+    pub end_sample: usize,
+}
+
+/// SubRip cues, one per chunk
+pub fn to_srt(timings: &[ChunkTiming], sample_rate: u32) -> String {
+    let mut out = String::new();
+    for (i, timing) in timings.iter().enumerate() {
+        let _ = write!(
+            out,
+            "{}\n{} --> {}\n{}\n\n",
+            i + 1,
+            timestamp(timing.start_sample, sample_rate, ','),
+            timestamp(timing.end_sample, sample_rate, ','),
+            timing.text
+        );
+    }
+    out
+}
+
+/// WebVTT cues, one per chunk. Chunks with word timings get a timestamp tag before each word
+/// after the first, for karaoke-style highlighting.
+pub fn to_vtt(timings: &[ChunkTiming], sample_rate: u32) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for timing in timings {
+        let text = match timing.words.as_slice() {
+            [] => escape_cue_text(&timing.text),
+            [first, rest @ ..] => {
+                let mut text = escape_cue_text(&first.word);
+                for word in rest {
+                    let _ = write!(
+                        text,
+                        " <{}>{}",
+                        timestamp(word.start_sample, sample_rate, '.'),
+                        escape_cue_text(&word.word)
+                    );
+                }
+                text
+            }
+        };
+        let _ = write!(
+            out,
+            "{} --> {}\n{}\n\n",
+            timestamp(timing.start_sample, sample_rate, '.'),
+            timestamp(timing.end_sample, sample_rate, '.'),
+            // A blank line or "-->" would end the cue early
+            text.replace("-->", "->").replace("\n\n", "\n")
+        );
+    }
+    out
+}
+
+/// WebVTT cue text would read `&` and `<` as the start of an entity or tag
+fn escape_cue_text(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;")
+}
+
+/// `HH:MM:SS<sep>mmm`
+fn timestamp(samples: usize, sample_rate: u32, millis_sep: char) -> String {
+    let total_ms = (samples as u64 * 1000 + sample_rate as u64 / 2) / sample_rate as u64;
+    let (hours, rest) = (total_ms / 3_600_000, total_ms % 3_600_000);
+    let (minutes, rest) = (rest / 60_000, rest % 60_000);
+    let (seconds, millis) = (rest / 1000, rest % 1000);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        hours, minutes, seconds, millis_sep, millis
+    )
+}
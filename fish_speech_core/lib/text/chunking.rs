@@ -0,0 +1,149 @@
+use super::clean::{detect_script, estimate_syllables, get_commas};
+use tokenizers::Tokenizer;
+
+/// Typical speaking rate, for estimating how many audio frames a chunk will take
+const SYLLABLES_PER_SECOND: f32 = 4.5;
+
+/// How text between breaks is cut into chunks for generation
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkingMode {
+    /// Character thresholds per script
+    #[default]
+    Characters,
+    /// Budgets in tokenizer tokens and estimated audio frames
+    Tokens,
+    /// One chunk per paragraph or `[pause]`-separated segment
+    Off,
+}
+
+/// Limits on a chunk in [`ChunkingMode::Tokens`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBudget {
+    pub max_tokens: usize,
+    pub max_frames: usize,
+    /// Estimated audio frames per syllable of text
+    pub frames_per_syllable: f32,
+}
+
+impl TokenBudget {
+    /// A budget for a codec producing `frame_rate` frames per second
+    pub fn new(max_tokens: usize, max_frames: usize, frame_rate: f64) -> Self {
+        Self {
+            max_tokens,
+            max_frames,
+            frames_per_syllable: frame_rate as f32 / SYLLABLES_PER_SECOND,
+        }
+    }
+}
+
+/// A [`ChunkingMode`] with what it needs to measure chunks
+#[derive(Clone, Copy, Default)]
+pub enum Chunker<'a> {
+    #[default]
+    Characters,
+    Tokens {
+        tokenizer: &'a Tokenizer,
+        budget: TokenBudget,
+    },
+    Off,
+}
+
+impl<'a> Chunker<'a> {
+    pub fn new(mode: ChunkingMode, tokenizer: &'a Tokenizer, budget: TokenBudget) -> Self {
+        match mode {
+            ChunkingMode::Characters => Self::Characters,
+            ChunkingMode::Tokens => Self::Tokens { tokenizer, budget },
+            ChunkingMode::Off => Self::Off,
+        }
+    }
+}
+
+/// Packs `sentences` into chunks within `budget`.
+///
+/// The first sentence is always a chunk of its own, so audio starts as soon as possible. Later
+/// ones are combined while they fit and share a script. Sentences over budget are split on
+/// commas, then between words (or characters, for text without spaces).
+pub(crate) fn chunk_by_tokens(
+    sentences: &[&str],
+    tokenizer: &Tokenizer,
+    budget: &TokenBudget,
+) -> Vec<String> {
+    let fits = |text: &str| {
+        let tokens = tokenizer
+            .encode(text, false)
+            .map_or(text.len(), |encoding| encoding.len());
+        let frames = estimate_syllables(text) * budget.frames_per_syllable;
+        tokens <= budget.max_tokens && frames <= budget.max_frames as f32
+    };
+    let Some((first, rest)) = sentences.split_first() else {
+        return vec![];
+    };
+
+    let mut chunks = split_to_fit(first, &fits);
+    let mut current = String::new();
+    for sentence in rest {
+        let sentence = sentence.trim();
+        if !fits(sentence) {
+            if !current.is_empty() {
+                chunks.push(std::mem::take(&mut current));
+            }
+            chunks.extend(split_to_fit(sentence, &fits));
+            continue;
+        }
+        if !current.is_empty()
+            && (detect_script(&current) != detect_script(sentence)
+                || !fits(&format!("{} {}", current, sentence)))
+        {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(sentence);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// `sentence` as one chunk if it fits, else packed from comma-separated pieces, then words
+fn split_to_fit(sentence: &str, fits: &impl Fn(&str) -> bool) -> Vec<String> {
+    let sentence = sentence.trim();
+    if fits(sentence) {
+        return vec![sentence.to_string()];
+    }
+    let pieces = sentence
+        .split_inclusive(get_commas(&detect_script(sentence)))
+        .flat_map(|piece| match fits(piece.trim()) {
+            true => vec![piece],
+            false if piece.contains(char::is_whitespace) => {
+                piece.split_inclusive(char::is_whitespace).collect()
+            }
+            false => piece
+                .char_indices()
+                .map(|(i, c)| &piece[i..i + c.len_utf8()])
+                .collect(),
+        });
+    pack(pieces, fits)
+}
+
+/// Concatenates consecutive `pieces` while the result fits. A piece that doesn't fit on its own
+/// becomes a chunk anyway.
+fn pack<'a>(pieces: impl Iterator<Item = &'a str>, fits: &impl Fn(&str) -> bool) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for piece in pieces {
+        let candidate = format!("{}{}", current, piece);
+        if !current.trim().is_empty() && !fits(candidate.trim()) {
+            chunks.push(current.trim().to_string());
+            current = piece.to_string();
+        } else {
+            current = candidate;
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim().to_string());
+    }
+    chunks
+}
@@ -1,146 +1,220 @@
-use std::collections::HashMap;
-use std::sync::OnceLock;
-
-static SYMBOL_MAP: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
-
-fn get_symbol_map() -> &'static HashMap<&'static str, &'static str> {
-    SYMBOL_MAP.get_or_init(|| {
-        let mut m = HashMap::new();
-        // Core cleanups that are very unlikely to harm
-        m.insert("“", "\"");
-        m.insert("”", "\"");
-        m.insert("‘", "'");
-        m.insert("’", "'");
-        m.insert("…", "...");
-        m.insert("«", "\""); // French opening quote
-        m.insert("»", "\""); // French closing quote
-        m.insert(" « ", "\""); // French quotes with spaces
-        m.insert(" » ", "\"");
-
-        // Remove zero-width spaces and other invisible formatters
-        m.insert("\u{200B}", ""); // zero width space
-        m.insert("\u{200C}", ""); // zero width non-joiner
-        m.insert("\u{200D}", ""); // zero width joiner
-        m.insert("\u{FEFF}", ""); // zero width no-break space
-
-        // Japanese-specific punctuation normalization
-        m.insert("。", "."); // Japanese period
-        m.insert("、", ", "); // Comma - note the space after!
-        m.insert("！", "!"); // Japanese exclamation
-        m.insert("？", "?"); // Japanese question mark
-        m.insert("「", "\""); // Japanese opening quote
-        m.insert("」", "\""); // Japanese closing quote
-        m.insert("『", "\""); // Japanese opening double quote
-        m.insert("』", "\""); // Japanese closing double quote
-        m.insert("・", ""); // Nakaguro (middle dot)
-        m.insert("：", ","); // Japanese colon to comma
-        m.insert("；", ","); // Japanese semicolon to comma
-        m.insert("（", ""); // Japanese opening parenthesis
-        m.insert("）", ""); // Japanese closing parenthesis
-        m.insert("【", ""); // Japanese opening bracket
-        m.insert("】", ""); // Japanese closing bracket
-        m
-    })
-}
+use super::chunking::{Chunker, chunk_by_tokens};
+use super::pipeline::TextPipeline;
+use super::segment::Segmenter;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum Script {
-    Chinese,  // Primarily hanzi
-    Japanese, // Mix of kanji and kana
-    Korean,   // Primarily hangul
-    Latin,    // Everything else
+pub(crate) enum Script {
+    Chinese,    // Primarily hanzi
+    Japanese,   // Mix of kanji and kana
+    Korean,     // Primarily hangul
+    Thai,       // No spaces between words, none of our punctuation
+    Arabic,     // Right-to-left, with its own comma and question mark
+    Hebrew,     // Right-to-left
+    Devanagari, // Hindi, Marathi, Nepali, ...
+    Cyrillic,
+    Greek,
+    Latin, // Everything else
 }
 
+const SCRIPTS: [Script; 10] = [
+    Script::Chinese,
+    Script::Japanese,
+    Script::Korean,
+    Script::Thai,
+    Script::Arabic,
+    Script::Hebrew,
+    Script::Devanagari,
+    Script::Cyrillic,
+    Script::Greek,
+    Script::Latin,
+];
+
 fn get_thresholds(script: &Script) -> (usize, usize) {
     match script {
         // (combine_threshold, split_threshold)
-        Script::Chinese => (30, 100),  // Chinese is dense
-        Script::Japanese => (45, 150), // Japanese has particles
-        Script::Korean => (40, 120),   // Similar to Japanese
-        Script::Latin => (150, 400),   // Much longer before we panic
+        Script::Chinese => (30, 100),     // Chinese is dense
+        Script::Japanese => (45, 150),    // Japanese has particles
+        Script::Korean => (40, 120),      // Similar to Japanese
+        Script::Thai => (80, 250),        // Several characters per syllable, tone marks
+        Script::Arabic => (120, 350),     // Short vowels go unwritten
+        Script::Hebrew => (110, 320),     // Likewise
+        Script::Devanagari => (130, 380), // Vowel signs count as characters
+        Script::Cyrillic | Script::Greek | Script::Latin => (150, 400), // Much longer before we panic
     }
 }
 
-fn clean_text(text: &str) -> String {
-    let mut result = text.trim().to_string();
+/// Characters that end a sentence. Thai has none: spaces between Thai text end sentences.
+pub(crate) fn get_terminators(script: &Script) -> &'static [char] {
+    match script {
+        Script::Chinese | Script::Japanese => &['。', '！', '？', '.', '!', '?', '…'],
+        Script::Thai => &[],
+        Script::Arabic => &['.', '!', '؟', '۔', '?'],
+        Script::Devanagari => &['।', '॥', '.', '!', '?'],
+        // The Greek question mark; ';' only counts after Greek letters
+        Script::Greek => &['.', '!', '\u{037E}', '…'],
+        Script::Korean | Script::Hebrew | Script::Cyrillic | Script::Latin => &['.', '!', '?', '…'],
+    }
+}
 
-    // Apply symbol mapping
-    for (from, to) in get_symbol_map().iter() {
-        result = result.replace(from, to);
+/// Characters a sentence too long for one chunk is split after
+pub(crate) fn get_commas(script: &Script) -> &'static [char] {
+    match script {
+        Script::Thai => &[' '],
+        Script::Arabic => &['،', '؛', ','],
+        Script::Greek => &[',', '·', '\u{0387}'],
+        _ => &[',', '，', '、'],
     }
+}
 
-    // Strip emoji
-    result = result
-        .chars()
-        .filter(|&c| !('\u{1F300}'..='\u{1F9FF}').contains(&c))
-        .collect();
-
-    // Normalize dash-like things to em-dash
-    result = result
-        .replace(" - ", "—")
-        .replace("--", "—")
-        .replace(" – ", "—");
-
-    // Normalize multiple punctuation
-    result = result
-        .replace("....", ".")
-        .replace("...", ".")
-        .replace("..", ".")
-        .replace(",,", ",");
-
-    // Ensure single spaces between words and after punctuation
-    result.split_whitespace().collect::<Vec<_>>().join(" ")
+/// Whether `c` ends a sentence in any script
+pub(crate) fn is_terminator(c: char) -> bool {
+    SCRIPTS
+        .iter()
+        .any(|script| get_terminators(script).contains(&c))
 }
 
-fn is_hanzi(c: char) -> bool {
-    ('\u{4E00}'..='\u{9FFF}').contains(&c) // Basic CJK unified
+pub(crate) fn is_hanzi(c: char) -> bool {
+    ('\u{4E00}'..='\u{9FFF}').contains(&c) ||   // Basic CJK unified
+    ('\u{3400}'..='\u{4DBF}').contains(&c) ||   // Extension A
+    ('\u{20000}'..='\u{323AF}').contains(&c) || // Extensions B to H
+    ('\u{F900}'..='\u{FAFF}').contains(&c) ||   // Compatibility ideographs
+    ('\u{2F800}'..='\u{2FA1F}').contains(&c) // Compatibility supplement
 }
 
-fn is_kana(c: char) -> bool {
+pub(crate) fn is_kana(c: char) -> bool {
     ('\u{3040}'..='\u{309F}').contains(&c) ||  // Hiragana
     ('\u{30A0}'..='\u{30FF}').contains(&c) // Katakana
 }
 
-fn is_hangul(c: char) -> bool {
+pub(crate) fn is_hangul(c: char) -> bool {
     ('\u{AC00}'..='\u{D7AF}').contains(&c) // Hangul syllables
 }
 
-fn detect_script(text: &str) -> Script {
+pub(crate) fn is_thai(c: char) -> bool {
+    ('\u{0E00}'..='\u{0E7F}').contains(&c)
+}
+
+fn is_arabic(c: char) -> bool {
+    ('\u{0600}'..='\u{06FF}').contains(&c) ||  // Arabic
+    ('\u{0750}'..='\u{077F}').contains(&c) ||  // Supplement
+    ('\u{08A0}'..='\u{08FF}').contains(&c) ||  // Extended-A
+    ('\u{FB50}'..='\u{FDFF}').contains(&c) ||  // Presentation forms A
+    ('\u{FE70}'..='\u{FEFF}').contains(&c) // Presentation forms B
+}
+
+fn is_hebrew(c: char) -> bool {
+    ('\u{0590}'..='\u{05FF}').contains(&c) || ('\u{FB1D}'..='\u{FB4F}').contains(&c)
+}
+
+fn is_devanagari(c: char) -> bool {
+    ('\u{0900}'..='\u{097F}').contains(&c) || ('\u{A8E0}'..='\u{A8FF}').contains(&c)
+}
+
+fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{052F}').contains(&c)
+}
+
+pub(crate) fn is_greek(c: char) -> bool {
+    ('\u{0370}'..='\u{03FF}').contains(&c) || ('\u{1F00}'..='\u{1FFF}').contains(&c)
+}
+
+pub(crate) fn detect_script(text: &str) -> Script {
     let chars: Vec<_> = text.chars().collect();
     if chars.is_empty() {
         return Script::Latin;
     }
 
     let total = chars.len() as f32;
-    let hanzi = chars.iter().filter(|c| is_hanzi(**c)).count() as f32;
-    let kana = chars.iter().filter(|c| is_kana(**c)).count() as f32;
-    let hangul = chars.iter().filter(|c| is_hangul(**c)).count() as f32;
+    let count = |f: fn(char) -> bool| chars.iter().filter(|c| f(**c)).count() as f32;
+    let hanzi = count(is_hanzi);
+    let kana = count(is_kana);
+    let hangul = count(is_hangul);
 
     if hanzi / total > 0.5 && kana / total < 0.1 {
-        Script::Chinese
+        return Script::Chinese;
     } else if kana / total > 0.2 || (hanzi / total > 0.2 && kana / total > 0.1) {
-        Script::Japanese
+        return Script::Japanese;
     } else if hangul / total > 0.3 {
-        Script::Korean
+        return Script::Korean;
+    }
+
+    // Otherwise the most common of the other alphabets, if it's a good share of the text
+    let (script, n) = [
+        (Script::Thai, count(is_thai)),
+        (Script::Arabic, count(is_arabic)),
+        (Script::Hebrew, count(is_hebrew)),
+        (Script::Devanagari, count(is_devanagari)),
+        (Script::Cyrillic, count(is_cyrillic)),
+        (Script::Greek, count(is_greek)),
+    ]
+    .into_iter()
+    .max_by(|a, b| a.1.total_cmp(&b.1))
+    .unwrap();
+    if n / total > 0.3 {
+        script
     } else {
         Script::Latin
     }
 }
 
+/// Rough syllable count: one per CJK character, one per three letters or digits otherwise
+pub fn estimate_syllables(text: &str) -> f32 {
+    let (cjk, other) = text.chars().fold((0usize, 0usize), |(cjk, other), c| {
+        if is_hanzi(c) || is_kana(c) || is_hangul(c) {
+            (cjk + 1, other)
+        } else if c.is_alphanumeric() {
+            (cjk, other + 1)
+        } else {
+            (cjk, other)
+        }
+    });
+    cjk as f32 + other as f32 / 3.0
+}
+
 pub fn preprocess_text(text: &str) -> Vec<String> {
-    let text = clean_text(text);
-    let script = detect_script(&text);
+    preprocess_text_with(text, &Segmenter::default())
+}
+
+/// [`preprocess_text`] with the sentence segmenter for another language
+pub fn preprocess_text_with(text: &str, segmenter: &Segmenter) -> Vec<String> {
+    preprocess_text_chunked(
+        text,
+        &TextPipeline::default(),
+        segmenter,
+        &Chunker::Characters,
+    )
+}
+
+/// [`preprocess_text_with`], normalizing text with `pipeline` and measuring chunks as `chunker`
+/// does
+pub fn preprocess_text_chunked(
+    text: &str,
+    pipeline: &TextPipeline,
+    segmenter: &Segmenter,
+    chunker: &Chunker,
+) -> Vec<String> {
+    let text = pipeline.normalize(text);
 
     // Split on major sentence boundaries first
-    let sentences: Vec<&str> = text
-        .split_inclusive(&['.', '!', '?'][..])
-        .filter(|s| !s.trim().is_empty())
-        .collect();
+    let sentences = segmenter.split(&text);
 
     if sentences.is_empty() {
         return vec![];
     }
 
+    let chunks = match chunker {
+        Chunker::Characters => chunk_by_characters(&text, &sentences),
+        Chunker::Tokens { tokenizer, budget } => chunk_by_tokens(&sentences, tokenizer, budget),
+        Chunker::Off => vec![text.clone()],
+    };
+    tracing::info!("Split into {} chunks with progressive sizing", chunks.len());
+    tracing::info!("Chunks:\n{:?}", chunks);
+    chunks
+}
+
+fn chunk_by_characters(text: &str, sentences: &[&str]) -> Vec<String> {
+    let script = detect_script(text);
+
     let mut chunks = Vec::new();
 
     // First chunk gets base thresholds
@@ -158,7 +232,7 @@ pub fn preprocess_text(text: &str) -> Vec<String> {
         chunks.push(first.to_string());
     } else {
         // If first sentence is huge, reluctantly split on commas
-        for piece in first.split_inclusive(&[',', '，', '、'][..]) {
+        for piece in first.split_inclusive(get_commas(&detect_script(first))) {
             if !piece.trim().is_empty() {
                 chunks.push(piece.trim().to_string());
             }
@@ -190,7 +264,7 @@ pub fn preprocess_text(text: &str) -> Vec<String> {
             }
 
             // Split the long sentence
-            for piece in sentence.split_inclusive(&[',', '，', '、'][..]) {
+            for piece in sentence.split_inclusive(get_commas(&detect_script(sentence))) {
                 if !piece.trim().is_empty() {
                     chunks.push(piece.trim().to_string());
                     chunk_index += 1;
@@ -229,9 +303,6 @@ pub fn preprocess_text(text: &str) -> Vec<String> {
     if !current.is_empty() {
         chunks.push(current.trim().to_string());
     }
-
-    tracing::info!("Split into {} chunks with progressive sizing", chunks.len());
-    tracing::info!("Chunks:\n{:?}", chunks);
     chunks
 }
 
@@ -246,12 +317,21 @@ mod tests {
         assert_eq!(detect_script("我爱北京天安门"), Script::Chinese);
         assert_eq!(detect_script("안녕하세요"), Script::Korean);
         assert_eq!(detect_script("漢字とひらがな"), Script::Japanese);
+        assert_eq!(detect_script("𠀀𠀁𠀂 㐀㐁"), Script::Chinese);
+        assert_eq!(detect_script("สวัสดีครับ"), Script::Thai);
+        assert_eq!(detect_script("مرحبا بالعالم"), Script::Arabic);
+        assert_eq!(detect_script("שלום עולם"), Script::Hebrew);
+        assert_eq!(detect_script("नमस्ते दुनिया"), Script::Devanagari);
+        assert_eq!(detect_script("Привет, мир"), Script::Cyrillic);
+        assert_eq!(detect_script("Γεια σου κόσμε"), Script::Greek);
+        assert_eq!(detect_script("Hello, Привет"), Script::Cyrillic);
+        assert_eq!(detect_script("Welcome to Москва today"), Script::Latin);
     }
 
     #[test]
     fn test_text_cleaning() {
         let text = "Hello 👋 World! Testing—some « quotes » and。。。ellipses...";
-        let cleaned = clean_text(text);
+        let cleaned = TextPipeline::default().normalize(text);
         assert!(!cleaned.contains('👋'));
         assert!(cleaned.contains('—'));
         assert!(cleaned.contains('"'));
@@ -0,0 +1,163 @@
+//! Pronunciation lexicon: respellings for brand names, acronyms and codes the model misreads
+use super::clean::{is_hanzi, is_kana, is_thai};
+use super::pipeline::TextNormalizer;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// One respelling
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LexiconEntry {
+    pub word: String,
+    pub replacement: String,
+    /// Whether case must match. By default, words with capitals (`SQL`, `iOS`) match exactly and
+    /// lowercase ones (`nginx`) match in any case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case_sensitive: Option<bool>,
+}
+
+impl LexiconEntry {
+    pub fn new(word: impl Into<String>, replacement: impl Into<String>) -> Self {
+        Self {
+            word: word.into(),
+            replacement: replacement.into(),
+            case_sensitive: None,
+        }
+    }
+
+    fn is_case_sensitive(&self) -> bool {
+        self.case_sensitive
+            .unwrap_or_else(|| self.word.chars().any(char::is_uppercase))
+    }
+}
+
+/// Lexicon entries as written in JSON: `{"word": "replacement"}`, or a list of
+/// [`LexiconEntry`] objects
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LexiconSpec {
+    Words(BTreeMap<String, String>),
+    Entries(Vec<LexiconEntry>),
+}
+
+impl LexiconSpec {
+    pub fn into_entries(self) -> Vec<LexiconEntry> {
+        match self {
+            Self::Words(words) => words
+                .into_iter()
+                .map(|(word, replacement)| LexiconEntry::new(word, replacement))
+                .collect(),
+            Self::Entries(entries) => entries,
+        }
+    }
+}
+
+/// Replaces whole words with their respellings, longest first, in a single pass so replacements
+/// aren't rewritten again
+#[derive(Debug, Clone, Default)]
+pub struct Lexicon {
+    /// Sorted by decreasing length of `word`
+    entries: Vec<LexiconEntry>,
+}
+
+impl Lexicon {
+    pub fn new(entries: impl IntoIterator<Item = LexiconEntry>) -> Self {
+        Self::default().extend(entries)
+    }
+
+    /// Loads a JSON [`LexiconSpec`]
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open lexicon {}", path.display()))?;
+        let spec: LexiconSpec = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse lexicon {}", path.display()))?;
+        Ok(Self::new(spec.into_entries()))
+    }
+
+    /// Adds `entries`, replacing existing ones for the same word
+    pub fn extend(mut self, entries: impl IntoIterator<Item = LexiconEntry>) -> Self {
+        for entry in entries {
+            if entry.word.is_empty() {
+                continue;
+            }
+            self.entries.retain(|e| e.word != entry.word);
+            self.entries.push(entry);
+        }
+        self.entries
+            .sort_by_key(|e| std::cmp::Reverse(e.word.chars().count()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[LexiconEntry] {
+        &self.entries
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        if self.entries.is_empty() {
+            return text.to_string();
+        }
+        let mut out = String::with_capacity(text.len());
+        let mut prev: Option<char> = None;
+        let mut i = 0;
+        'outer: while i < text.len() {
+            for entry in &self.entries {
+                if let Some(end) = match_at(text, i, entry)
+                    && bounded(prev, entry.word.chars().next())
+                    && bounded(text[end..].chars().next(), entry.word.chars().last())
+                {
+                    out.push_str(&entry.replacement);
+                    prev = text[..end].chars().last();
+                    i = end;
+                    continue 'outer;
+                }
+            }
+            let c = text[i..].chars().next().unwrap();
+            out.push(c);
+            prev = Some(c);
+            i += c.len_utf8();
+        }
+        out
+    }
+}
+
+impl TextNormalizer for Lexicon {
+    fn name(&self) -> &str {
+        "lexicon"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        self.apply(text)
+    }
+}
+
+/// End of `entry.word` if it occurs at byte `start` of `text`
+fn match_at(text: &str, start: usize, entry: &LexiconEntry) -> Option<usize> {
+    let rest = &text[start..];
+    if entry.is_case_sensitive() {
+        return rest
+            .starts_with(&entry.word)
+            .then(|| start + entry.word.len());
+    }
+    let mut chars = rest.char_indices();
+    for expected in entry.word.chars() {
+        let (_, c) = chars.next()?;
+        if !c.to_lowercase().eq(expected.to_lowercase()) {
+            return None;
+        }
+    }
+    Some(start + chars.next().map_or(rest.len(), |(i, _)| i))
+}
+
+/// Whether `neighbour` leaves a word edge at `edge` whole: letters and digits can't continue a
+/// word that ends in one, but `C++` or `.NET` need no boundary on their symbol side. Chinese,
+/// Japanese and Thai are written without spaces, so their characters never need one.
+fn bounded(neighbour: Option<char>, edge: Option<char>) -> bool {
+    let is_word =
+        |c: char| (c.is_alphanumeric() || c == '_') && !(is_hanzi(c) || is_kana(c) || is_thai(c));
+    !(neighbour.is_some_and(is_word) && edge.is_some_and(is_word))
+}
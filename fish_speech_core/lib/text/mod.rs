@@ -1,2 +1,11 @@
+pub mod alignment;
+pub mod captions;
+pub mod chunking;
 pub mod clean;
+pub mod lexicon;
+pub mod normalize;
+pub mod pause;
+pub mod pipeline;
 pub mod prompt;
+pub mod segment;
+pub mod ssml;
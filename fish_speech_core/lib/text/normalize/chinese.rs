@@ -0,0 +1,194 @@
+use super::outside_markers;
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+const DIGITS: [char; 10] = ['零', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+/// Measure words after which 2 is read 两
+const MEASURE_WORDS: &str = "个只本次位条件张天人名种台辆家元块毛角岁倍周份";
+
+/// Reads Arabic numerals in Chinese text as hanzi (数字转汉字): numbers, decimals, percentages,
+/// dates, times, currency, units and phone numbers
+pub fn normalize_chinese(text: &str) -> String {
+    outside_markers(text, normalize_span)
+}
+
+struct Rules {
+    phone: Regex,
+    date: Regex,
+    time: Regex,
+    currency: Regex,
+    percent: Regex,
+    unit: Regex,
+    number: Regex,
+}
+
+fn rules() -> &'static Rules {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+    RULES.get_or_init(|| Rules {
+        phone: Regex::new(r"0\d{2,3}-\d{7,8}|\d{11,}").unwrap(),
+        date: Regex::new(r"(\d{4})年|(\d{4})-(\d{1,2})-(\d{1,2})").unwrap(),
+        time: Regex::new(r"(\d{1,2})[:：](\d{2})").unwrap(),
+        currency: Regex::new(r"([¥￥$€£])\s?(\d[\d,]*(?:\.\d+)?)").unwrap(),
+        percent: Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?[%％]").unwrap(),
+        unit: Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?(km|cm|mm|kg|mg|ml|m|g|L|°C|℃)([^A-Za-z]|$)")
+            .unwrap(),
+        number: Regex::new(&format!(
+            r"(^|[^0-9A-Za-z.])(-)?(\d{{1,3}}(?:,\d{{3}})+|\d+)(\.\d+)?([{}]?)",
+            MEASURE_WORDS
+        ))
+        .unwrap(),
+    })
+}
+
+fn normalize_span(text: &str) -> String {
+    let rules = rules();
+    let text = rules
+        .phone
+        .replace_all(text, |c: &Captures| digit_reading(&c[0]));
+    let text = rules
+        .date
+        .replace_all(&text, |c: &Captures| match c.get(1) {
+            Some(year) => format!("{}年", digit_reading(year.as_str())),
+            None => match (c[3].parse::<u64>(), c[4].parse::<u64>()) {
+                (Ok(m @ 1..=12), Ok(d @ 1..=31)) => format!(
+                    "{}年{}月{}日",
+                    digit_reading(&c[2]),
+                    chinese_number(m),
+                    chinese_number(d)
+                ),
+                _ => c[0].to_string(),
+            },
+        });
+    let text = rules.time.replace_all(&text, |c: &Captures| {
+        match (c[1].parse::<u64>(), c[2].parse::<u64>()) {
+            (Ok(h @ 0..=24), Ok(0)) => format!("{}点", chinese_number(h)),
+            (Ok(h @ 0..=24), Ok(m @ 1..=9)) => {
+                format!("{}点零{}分", chinese_number(h), DIGITS[m as usize])
+            }
+            (Ok(h @ 0..=24), Ok(m @ 10..=59)) => {
+                format!("{}点{}分", chinese_number(h), chinese_number(m))
+            }
+            _ => c[0].to_string(),
+        }
+    });
+    let text = rules.currency.replace_all(&text, |c: &Captures| {
+        let unit = match &c[1] {
+            "$" => "美元",
+            "€" => "欧元",
+            "£" => "英镑",
+            _ => "元",
+        };
+        format!("{}{}", decimal_reading(&c[2]), unit)
+    });
+    let text = rules.percent.replace_all(&text, |c: &Captures| {
+        format!("百分之{}", decimal_reading(&c[1]))
+    });
+    let text = rules.unit.replace_all(&text, |c: &Captures| {
+        let number = decimal_reading(&c[1]);
+        let reading = match &c[2] {
+            "°C" | "℃" => return format!("摄氏{}度{}", number, &c[3]),
+            "km" => "公里",
+            "cm" => "厘米",
+            "mm" => "毫米",
+            "kg" => "公斤",
+            "mg" => "毫克",
+            "ml" => "毫升",
+            "m" => "米",
+            "g" => "克",
+            _ => "升",
+        };
+        format!("{}{}{}", number, reading, &c[3])
+    });
+    let text = rules.number.replace_all(&text, |c: &Captures| {
+        let sign = if c.get(2).is_some() { "负" } else { "" };
+        let measure = &c[5];
+        let reading = match (&c[3], c.get(4)) {
+            // 第二名 is an ordinal, 两名 a count
+            ("2", None) if !measure.is_empty() && !c[1].ends_with('第') => "两".to_string(),
+            (int, fraction) => {
+                decimal_reading(&format!("{}{}", int, fraction.map_or("", |f| f.as_str())))
+            }
+        };
+        format!("{}{}{}{}", &c[1], sign, reading, measure)
+    });
+    text.into_owned()
+}
+
+/// Hanzi for `n`, e.g. 20_305 -> 两万零三百零五. Numbers from 10^16 up are read digit by digit.
+pub fn chinese_number(n: u64) -> String {
+    if n == 0 {
+        return DIGITS[0].to_string();
+    }
+    if n >= 10_000_000_000_000_000 {
+        return digit_reading(&n.to_string());
+    }
+    const GROUPS: [&str; 4] = ["", "万", "亿", "万亿"];
+    let groups: Vec<u64> = (0..4).map(|i| n / 10_000u64.pow(i) % 10_000).collect();
+    let mut out = String::new();
+    // A zero is read once for any run of zeros between nonzero digits
+    let mut pending_zero = false;
+    for (i, &group) in groups.iter().enumerate().rev() {
+        if group == 0 {
+            pending_zero = !out.is_empty();
+            continue;
+        }
+        if !out.is_empty() && (pending_zero || group < 1000) {
+            out.push(DIGITS[0]);
+        }
+        pending_zero = false;
+        group_reading(group, out.is_empty(), i > 0, &mut out);
+        out.push_str(GROUPS[i]);
+    }
+    out
+}
+
+/// A group of up to four digits. `first` if it leads the number; `scaled` if 万 or 亿 follows.
+fn group_reading(group: u64, first: bool, scaled: bool, out: &mut String) {
+    const PLACES: [&str; 4] = ["千", "百", "十", ""];
+    let digits = [group / 1000, group / 100 % 10, group / 10 % 10, group % 10];
+    let mut started = false;
+    let mut pending_zero = false;
+    for (place, &d) in digits.iter().enumerate() {
+        if d == 0 {
+            pending_zero = started;
+            continue;
+        }
+        if pending_zero {
+            out.push(DIGITS[0]);
+            pending_zero = false;
+        }
+        let leading = first && !started;
+        match (place, d) {
+            // 十五, not 一十五, at the start of a number
+            (2, 1) if leading => {}
+            (0, 2) => out.push('两'),
+            (1, 2) | (3, 2) if leading && (place == 1 || scaled) => out.push('两'),
+            _ => out.push(DIGITS[d as usize]),
+        }
+        out.push_str(PLACES[place]);
+        started = true;
+    }
+}
+
+/// "3.14" -> 三点一四
+fn decimal_reading(number: &str) -> String {
+    let number = number.replace(',', "");
+    let (int, fraction) = number.split_once('.').unwrap_or((&number, ""));
+    let int = match int.parse::<u64>() {
+        Ok(n) => chinese_number(n),
+        Err(_) => digit_reading(int),
+    };
+    match fraction {
+        "" => int,
+        f => format!("{}点{}", int, digit_reading(f)),
+    }
+}
+
+/// Digit by digit, as for years and phone numbers
+fn digit_reading(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| DIGITS[d as usize])
+        .collect()
+}
@@ -0,0 +1,344 @@
+use super::outside_markers;
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+/// Spells out numbers, dates, times, currency, percentages, phone numbers and common units in
+/// English text, so they're read predictably (and `3.5` no longer ends a sentence).
+///
+/// `[pause]`-style markers are left as they are.
+pub fn normalize_english(text: &str) -> String {
+    outside_markers(text, normalize_span)
+}
+
+struct Rules {
+    phone: Regex,
+    date_mdy: Regex,
+    date_iso: Regex,
+    time: Regex,
+    currency: Regex,
+    percent: Regex,
+    ordinal: Regex,
+    unit: Regex,
+    number: Regex,
+}
+
+fn rules() -> &'static Rules {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+    RULES.get_or_init(|| Rules {
+        phone: Regex::new(r"(?:\+?\b1[-. ])?(?:\((\d{3})\)\s?|\b(\d{3})[-. ])(\d{3})[-. ](\d{4})\b")
+            .unwrap(),
+        date_mdy: Regex::new(r"\b(\d{1,2})/(\d{1,2})/(\d{4})\b").unwrap(),
+        date_iso: Regex::new(r"\b(\d{4})-(\d{2})-(\d{2})\b").unwrap(),
+        time: Regex::new(r"(?i)\b(\d{1,2}):(\d{2})(?:\s?([ap])\.?m\b\.?)?").unwrap(),
+        currency: Regex::new(
+            r"(?i)([$£€¥])\s?(\d[\d,]*(?:\.\d+)?)(?:\s?(k|m|bn?|t|thousand|million|billion|trillion)\b)?",
+        )
+        .unwrap(),
+        percent: Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?%").unwrap(),
+        ordinal: Regex::new(r"(?i)\b(\d[\d,]*)(st|nd|rd|th)\b").unwrap(),
+        unit: Regex::new(
+            r"\b(\d[\d,]*(?:\.\d+)?)(?:\s?(km/h|km|cm|mm|kg|mg|lbs?|oz|mi|ft|mph|kph|ml|GB|MB|KB|TB|°C|°F)|(m|g|L))\b",
+        )
+        .unwrap(),
+        number: Regex::new(r"(^|[^\w.,])(-)?(\d{1,3}(?:,\d{3})+|\d+)(\.\d+)?").unwrap(),
+    })
+}
+
+fn normalize_span(text: &str) -> String {
+    let rules = rules();
+    let text = rules.phone.replace_all(text, |c: &Captures| {
+        let area = c.get(1).or(c.get(2)).unwrap().as_str();
+        [area, &c[3], &c[4]]
+            .iter()
+            .map(|group| digit_words(group))
+            .collect::<Vec<_>>()
+            .join(", ")
+    });
+    let text = rules.date_mdy.replace_all(&text, |c: &Captures| {
+        date_words(&c[3], &c[1], &c[2]).unwrap_or_else(|| c[0].to_string())
+    });
+    let text = rules.date_iso.replace_all(&text, |c: &Captures| {
+        date_words(&c[1], &c[2], &c[3]).unwrap_or_else(|| c[0].to_string())
+    });
+    let text = rules.time.replace_all(&text, |c: &Captures| {
+        time_words(c).unwrap_or_else(|| c[0].to_string())
+    });
+    let text = rules.currency.replace_all(&text, currency_words);
+    let text = rules.percent.replace_all(&text, |c: &Captures| {
+        format!("{} percent", number_words(&c[1]))
+    });
+    let text = rules.ordinal.replace_all(&text, |c: &Captures| {
+        match c[1].replace(',', "").parse::<u64>() {
+            Ok(n) => ordinal_words(n),
+            Err(_) => c[0].to_string(),
+        }
+    });
+    let text = rules.unit.replace_all(&text, |c: &Captures| {
+        let unit = c.get(2).or(c.get(3)).unwrap().as_str();
+        let singular = c[1] == *"1";
+        format!("{} {}", number_words(&c[1]), unit_words(unit, singular))
+    });
+    let text = rules.number.replace_all(&text, |c: &Captures| {
+        let digits = c[3].replace(',', "");
+        let fraction = c.get(4).map_or("", |f| f.as_str());
+        // Bare four-digit numbers in this range are nearly always years
+        let words = match digits.parse::<u64>() {
+            Ok(n) if fraction.is_empty() && !c[3].contains(',') && (1100..2100).contains(&n) => {
+                year_words(n)
+            }
+            _ => number_words(&format!("{}{}", digits, fraction)),
+        };
+        let sign = if c.get(2).is_some() { "minus " } else { "" };
+        format!("{}{}{}", &c[1], sign, words)
+    });
+    text.into_owned()
+}
+
+const ONES: [&str; 20] = [
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+/// English words for `n`, e.g. 1204 -> "one thousand two hundred four"
+pub fn cardinal_words(n: u64) -> String {
+    fn below_thousand(n: u64, words: &mut Vec<String>) {
+        if n >= 100 {
+            words.push(ONES[(n / 100) as usize].to_string());
+            words.push("hundred".to_string());
+        }
+        let rest = n % 100;
+        if rest >= 20 {
+            match rest % 10 {
+                0 => words.push(TENS[(rest / 10) as usize].to_string()),
+                ones => words.push(format!(
+                    "{}-{}",
+                    TENS[(rest / 10) as usize],
+                    ONES[ones as usize]
+                )),
+            }
+        } else if rest > 0 {
+            words.push(ONES[rest as usize].to_string());
+        }
+    }
+
+    if n == 0 {
+        return ONES[0].to_string();
+    }
+    const SCALES: [(u64, &str); 6] = [
+        (1_000_000_000_000_000_000, "quintillion"),
+        (1_000_000_000_000_000, "quadrillion"),
+        (1_000_000_000_000, "trillion"),
+        (1_000_000_000, "billion"),
+        (1_000_000, "million"),
+        (1_000, "thousand"),
+    ];
+    let mut words = Vec::new();
+    let mut rest = n;
+    for (scale, name) in SCALES {
+        if rest >= scale {
+            below_thousand(rest / scale, &mut words);
+            words.push(name.to_string());
+            rest %= scale;
+        }
+    }
+    below_thousand(rest, &mut words);
+    words.join(" ")
+}
+
+/// e.g. 21 -> "twenty-first"
+pub fn ordinal_words(n: u64) -> String {
+    let cardinal = cardinal_words(n);
+    let split = cardinal.rfind([' ', '-']).map_or(0, |i| i + 1);
+    let (head, last) = cardinal.split_at(split);
+    let last = match last {
+        "one" => "first".to_string(),
+        "two" => "second".to_string(),
+        "three" => "third".to_string(),
+        "five" => "fifth".to_string(),
+        "eight" => "eighth".to_string(),
+        "nine" => "ninth".to_string(),
+        "twelve" => "twelfth".to_string(),
+        tens if tens.ends_with('y') => format!("{}ieth", &tens[..tens.len() - 1]),
+        other => format!("{}th", other),
+    };
+    format!("{}{}", head, last)
+}
+
+/// e.g. 1999 -> "nineteen ninety-nine", 2005 -> "two thousand five"
+pub fn year_words(n: u64) -> String {
+    let (century, rest) = (n / 100, n % 100);
+    match (century, rest) {
+        (_, 0) if n.is_multiple_of(1000) => cardinal_words(n),
+        (20, r) if r < 10 => cardinal_words(n),
+        (c, 0) => format!("{} hundred", cardinal_words(c)),
+        (c, r) if r < 10 => format!("{} oh {}", cardinal_words(c), ONES[r as usize]),
+        (c, r) => format!("{} {}", cardinal_words(c), cardinal_words(r)),
+    }
+}
+
+/// Integers and decimals, e.g. "1,234.05" -> "one thousand two hundred thirty-four point zero five"
+fn number_words(number: &str) -> String {
+    let number = number.replace(',', "");
+    let (int, fraction) = number.split_once('.').unwrap_or((&number, ""));
+    let int_words = match int.parse::<u64>() {
+        Ok(n) => cardinal_words(n),
+        // Too long for a u64: read digit by digit
+        Err(_) => digit_words(int),
+    };
+    match fraction {
+        "" => int_words,
+        f => format!("{} point {}", int_words, digit_words(f)),
+    }
+}
+
+fn digit_words(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| ONES[d as usize])
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// "December fifth, twenty twenty-four", or `None` if the fields aren't a valid date
+fn date_words(year: &str, month: &str, day: &str) -> Option<String> {
+    let (year, month, day): (u64, usize, u64) =
+        (year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    Some(format!(
+        "{} {}, {}",
+        MONTHS[month - 1],
+        ordinal_words(day),
+        year_words(year)
+    ))
+}
+
+fn time_words(c: &Captures) -> Option<String> {
+    let hours: u64 = c[1].parse().ok()?;
+    let minutes: u64 = c[2].parse().ok()?;
+    let meridiem = c.get(3).map(|m| m.as_str().to_lowercase());
+    if minutes >= 60 || hours > 24 || (meridiem.is_some() && !(1..=12).contains(&hours)) {
+        return None;
+    }
+    let mut words = cardinal_words(hours);
+    match minutes {
+        0 if meridiem.is_none() => words.push_str(" hundred"),
+        0 => {}
+        m if m < 10 => words.push_str(&format!(" oh {}", ONES[m as usize])),
+        m => words.push_str(&format!(" {}", cardinal_words(m))),
+    }
+    if let Some(m) = meridiem {
+        words.push_str(&format!(" {} m", m));
+    }
+    Some(words)
+}
+
+fn currency_words(c: &Captures) -> String {
+    let (major, minor) = match &c[1] {
+        "$" => ("dollar", Some("cent")),
+        "£" => ("pound", Some("penny")),
+        "€" => ("euro", Some("cent")),
+        _ => ("yen", None),
+    };
+    let plural = |word: &str, n: u64| match (word, n) {
+        (_, 1) | ("yen", _) => word.to_string(),
+        ("penny", _) => "pence".to_string(),
+        _ => format!("{}s", word),
+    };
+    let amount = c[2].replace(',', "");
+    if let Some(scale) = c.get(3) {
+        let scale = match scale.as_str().to_lowercase().as_str() {
+            "k" | "thousand" => "thousand",
+            "m" | "million" => "million",
+            "b" | "bn" | "billion" => "billion",
+            _ => "trillion",
+        };
+        return format!("{} {} {}", number_words(&amount), scale, plural(major, 0));
+    }
+    let (int, fraction) = amount.split_once('.').unwrap_or((&amount, ""));
+    let units: u64 = int.parse().unwrap_or(0);
+    let major_words = format!("{} {}", number_words(int), plural(major, units));
+    match (minor, fraction.len()) {
+        (Some(minor), 2) => {
+            let cents: u64 = fraction.parse().unwrap_or(0);
+            match (units, cents) {
+                (_, 0) => major_words,
+                (0, c) => format!("{} {}", cardinal_words(c), plural(minor, c)),
+                (_, c) => format!(
+                    "{} and {} {}",
+                    major_words,
+                    cardinal_words(c),
+                    plural(minor, c)
+                ),
+            }
+        }
+        (_, 0) => major_words,
+        _ => format!("{} {}", number_words(&amount), plural(major, 0)),
+    }
+}
+
+fn unit_words(unit: &str, singular: bool) -> String {
+    let (one, many) = match unit {
+        "km/h" | "kph" => ("kilometer per hour", "kilometers per hour"),
+        "km" => ("kilometer", "kilometers"),
+        "cm" => ("centimeter", "centimeters"),
+        "mm" => ("millimeter", "millimeters"),
+        "m" => ("meter", "meters"),
+        "kg" => ("kilogram", "kilograms"),
+        "mg" => ("milligram", "milligrams"),
+        "g" => ("gram", "grams"),
+        "lb" | "lbs" => ("pound", "pounds"),
+        "oz" => ("ounce", "ounces"),
+        "mi" => ("mile", "miles"),
+        "ft" => ("foot", "feet"),
+        "mph" => ("mile per hour", "miles per hour"),
+        "ml" => ("milliliter", "milliliters"),
+        "L" => ("liter", "liters"),
+        "GB" => ("gigabyte", "gigabytes"),
+        "MB" => ("megabyte", "megabytes"),
+        "KB" => ("kilobyte", "kilobytes"),
+        "TB" => ("terabyte", "terabytes"),
+        "°C" => ("degree Celsius", "degrees Celsius"),
+        "°F" => ("degree Fahrenheit", "degrees Fahrenheit"),
+        other => (other, other),
+    };
+    if singular { one } else { many }.to_string()
+}
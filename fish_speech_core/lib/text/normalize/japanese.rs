@@ -0,0 +1,166 @@
+use super::outside_markers;
+use regex::{Captures, Regex};
+use std::sync::OnceLock;
+
+const DIGITS: [char; 10] = ['〇', '一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+/// Reads Arabic numerals in Japanese text as kanji: numbers, decimals, percentages, times,
+/// currency, units and phone numbers. Dates need nothing more, since 年, 月 and 日 follow plain
+/// numbers.
+pub fn normalize_japanese(text: &str) -> String {
+    outside_markers(text, normalize_span)
+}
+
+struct Rules {
+    phone: Regex,
+    date: Regex,
+    time: Regex,
+    currency: Regex,
+    percent: Regex,
+    unit: Regex,
+    number: Regex,
+}
+
+fn rules() -> &'static Rules {
+    static RULES: OnceLock<Rules> = OnceLock::new();
+    RULES.get_or_init(|| Rules {
+        phone: Regex::new(r"0\d{1,4}-\d{1,4}-\d{4}|\d{10,}").unwrap(),
+        date: Regex::new(r"(\d{4})[-/](\d{1,2})[-/](\d{1,2})").unwrap(),
+        time: Regex::new(r"(\d{1,2})[:：](\d{2})").unwrap(),
+        currency: Regex::new(r"([¥￥$€£])\s?(\d[\d,]*(?:\.\d+)?)").unwrap(),
+        percent: Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?[%％]").unwrap(),
+        unit: Regex::new(r"(\d[\d,]*(?:\.\d+)?)\s?(km|cm|mm|kg|mg|ml|m|g|L|°C|℃)([^A-Za-z]|$)")
+            .unwrap(),
+        number: Regex::new(r"(^|[^0-9A-Za-z.])(-)?(\d{1,3}(?:,\d{3})+|\d+)(\.\d+)?").unwrap(),
+    })
+}
+
+fn normalize_span(text: &str) -> String {
+    let rules = rules();
+    let text = rules.phone.replace_all(text, |c: &Captures| {
+        c[0].split('-')
+            .map(digit_reading)
+            .collect::<Vec<_>>()
+            .join("の")
+    });
+    let text = rules.date.replace_all(&text, |c: &Captures| {
+        match (
+            c[1].parse::<u64>(),
+            c[2].parse::<u64>(),
+            c[3].parse::<u64>(),
+        ) {
+            (Ok(y), Ok(m @ 1..=12), Ok(d @ 1..=31)) => format!(
+                "{}年{}月{}日",
+                japanese_number(y),
+                japanese_number(m),
+                japanese_number(d)
+            ),
+            _ => c[0].to_string(),
+        }
+    });
+    let text = rules.time.replace_all(&text, |c: &Captures| {
+        match (c[1].parse::<u64>(), c[2].parse::<u64>()) {
+            (Ok(h @ 0..=24), Ok(0)) => format!("{}時", japanese_number(h)),
+            (Ok(h @ 0..=24), Ok(m @ 1..=59)) => {
+                format!("{}時{}分", japanese_number(h), japanese_number(m))
+            }
+            _ => c[0].to_string(),
+        }
+    });
+    let text = rules.currency.replace_all(&text, |c: &Captures| {
+        let unit = match &c[1] {
+            "$" => "ドル",
+            "€" => "ユーロ",
+            "£" => "ポンド",
+            _ => "円",
+        };
+        format!("{}{}", decimal_reading(&c[2]), unit)
+    });
+    let text = rules.percent.replace_all(&text, |c: &Captures| {
+        format!("{}パーセント", decimal_reading(&c[1]))
+    });
+    let text = rules.unit.replace_all(&text, |c: &Captures| {
+        let reading = match &c[2] {
+            "°C" | "℃" => "度",
+            "km" => "キロメートル",
+            "cm" => "センチメートル",
+            "mm" => "ミリメートル",
+            "kg" => "キログラム",
+            "mg" => "ミリグラム",
+            "ml" => "ミリリットル",
+            "m" => "メートル",
+            "g" => "グラム",
+            _ => "リットル",
+        };
+        format!("{}{}{}", decimal_reading(&c[1]), reading, &c[3])
+    });
+    let text = rules.number.replace_all(&text, |c: &Captures| {
+        let sign = if c.get(2).is_some() {
+            "マイナス"
+        } else {
+            ""
+        };
+        let fraction = c.get(4).map_or("", |f| f.as_str());
+        format!(
+            "{}{}{}",
+            &c[1],
+            sign,
+            decimal_reading(&format!("{}{}", &c[3], fraction))
+        )
+    });
+    text.into_owned()
+}
+
+/// Kanji for `n`, e.g. 20_305 -> 二万三百五. Numbers from 10^16 up are read digit by digit.
+pub fn japanese_number(n: u64) -> String {
+    if n == 0 {
+        return "零".to_string();
+    }
+    if n >= 10_000_000_000_000_000 {
+        return digit_reading(&n.to_string());
+    }
+    const GROUPS: [&str; 4] = ["", "万", "億", "兆"];
+    const PLACES: [&str; 4] = ["千", "百", "十", ""];
+    let mut out = String::new();
+    for i in (0..4).rev() {
+        let group = n / 10_000u64.pow(i as u32) % 10_000;
+        if group == 0 {
+            continue;
+        }
+        let digits = [group / 1000, group / 100 % 10, group / 10 % 10, group % 10];
+        for (place, &d) in digits.iter().enumerate() {
+            match d {
+                0 => continue,
+                // 千, 百 and 十 stand alone: 千五百, not 一千五百
+                1 if place < 3 => {}
+                _ => out.push(DIGITS[d as usize]),
+            }
+            out.push_str(PLACES[place]);
+        }
+        out.push_str(GROUPS[i]);
+    }
+    out
+}
+
+/// "3.14" -> 三点一四
+fn decimal_reading(number: &str) -> String {
+    let number = number.replace(',', "");
+    let (int, fraction) = number.split_once('.').unwrap_or((&number, ""));
+    let int = match int.parse::<u64>() {
+        Ok(n) => japanese_number(n),
+        Err(_) => digit_reading(int),
+    };
+    match fraction {
+        "" => int,
+        f => format!("{}点{}", int, digit_reading(f)),
+    }
+}
+
+/// Digit by digit, as for phone numbers
+fn digit_reading(digits: &str) -> String {
+    digits
+        .chars()
+        .filter_map(|c| c.to_digit(10))
+        .map(|d| DIGITS[d as usize])
+        .collect()
+}
@@ -0,0 +1,74 @@
+//! Rule-based readings of numbers, dates, currency and units, so the model doesn't have to guess
+mod chinese;
+mod english;
+mod japanese;
+
+pub use chinese::{chinese_number, normalize_chinese};
+pub use english::{cardinal_words, normalize_english, ordinal_words, year_words};
+pub use japanese::{japanese_number, normalize_japanese};
+
+use super::clean::{Script, detect_script};
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// Normalizes each sentence with the reader for its script: Chinese, Japanese or English.
+/// Other scripts are left as they are.
+pub fn normalize_text(text: &str) -> String {
+    normalize_text_in(text, "en")
+}
+
+/// [`normalize_text`] for text in `language`. English is the only Latin-script reader, so Latin
+/// sentences are left as they are for `de`, `fr` and `es`, and read as English otherwise.
+pub fn normalize_text_in(text: &str, language: &str) -> String {
+    let read_latin = !matches!(language, "de" | "fr" | "es");
+    sentences(text)
+        .map(|sentence| match detect_script(sentence) {
+            Script::Chinese => normalize_chinese(sentence),
+            Script::Japanese => normalize_japanese(sentence),
+            Script::Latin if read_latin => normalize_english(sentence),
+            _ => sentence.to_string(),
+        })
+        .collect()
+}
+
+/// Splits after CJK terminators, newlines, and `.!?` followed by whitespace (so decimals stay
+/// whole), keeping the separators
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut chars = rest.char_indices().peekable();
+        let mut end = rest.len();
+        while let Some((i, c)) = chars.next() {
+            let next = chars.peek().map(|&(_, n)| n);
+            let boundary = matches!(c, '。' | '！' | '？' | '\n')
+                || (matches!(c, '.' | '!' | '?') && next.is_some_and(char::is_whitespace));
+            if boundary && !next.is_some_and(|n| matches!(n, '。' | '！' | '？')) {
+                // Trailing whitespace stays with the sentence it follows
+                end = i + c.len_utf8();
+                end += rest[end..].len() - rest[end..].trim_start().len();
+                break;
+            }
+        }
+        let (sentence, tail) = rest.split_at(end);
+        rest = tail;
+        Some(sentence)
+    })
+}
+
+/// Applies `f` to the text between `[pause]`-style markers, leaving the markers as they are
+fn outside_markers(text: &str, f: impl Fn(&str) -> String) -> String {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    let marker = MARKER.get_or_init(|| Regex::new(r"\[[^\]\n]*\]").unwrap());
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for m in marker.find_iter(text) {
+        out.push_str(&f(&text[last..m.start()]));
+        out.push_str(m.as_str());
+        last = m.end();
+    }
+    out.push_str(&f(&text[last..]));
+    out
+}
@@ -0,0 +1,185 @@
+use super::chunking::Chunker;
+use super::clean::{is_terminator, preprocess_text_chunked};
+use super::pipeline::TextPipeline;
+use super::segment::Segmenter;
+use regex::Regex;
+use std::sync::OnceLock;
+
+/// What follows a chunk of text, which decides the silence inserted after it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChunkBreak {
+    /// Mid-sentence (a long sentence split on commas), or the end of the input
+    None,
+    /// Chunk ends with `.`, `!` or `?`
+    Sentence,
+    /// Blank line in the input
+    Paragraph,
+    /// Explicit `[pause]` / `[pause:500ms]` marker, with its duration in milliseconds if given
+    Marker(Option<f32>),
+}
+
+impl ChunkBreak {
+    /// Combines two breaks with no text between them. Markers win and add up.
+    fn merge(self, next: ChunkBreak) -> ChunkBreak {
+        match (self, next) {
+            (ChunkBreak::Marker(Some(a)), ChunkBreak::Marker(Some(b))) => {
+                ChunkBreak::Marker(Some(a + b))
+            }
+            (_, ChunkBreak::Marker(_)) => next,
+            (ChunkBreak::Marker(_), _) => self,
+            (_, ChunkBreak::Paragraph) => next,
+            _ => self,
+        }
+    }
+}
+
+/// Longest pause inserted after any break, however long the marker or config asks for
+pub const MAX_PAUSE_MS: f32 = 10_000.0;
+
+/// Silence after each kind of break
+#[derive(Debug, Clone, PartialEq)]
+pub struct PauseConfig {
+    pub sentence_ms: f32,
+    pub paragraph_ms: f32,
+    /// For markers without a duration
+    pub marker_ms: f32,
+}
+
+impl Default for PauseConfig {
+    fn default() -> Self {
+        Self {
+            sentence_ms: 100.0,
+            paragraph_ms: 600.0,
+            marker_ms: 500.0,
+        }
+    }
+}
+
+impl PauseConfig {
+    pub fn pause_ms(&self, chunk_break: ChunkBreak) -> f32 {
+        match chunk_break {
+            ChunkBreak::None => 0.0,
+            ChunkBreak::Sentence => self.sentence_ms,
+            ChunkBreak::Paragraph => self.paragraph_ms,
+            ChunkBreak::Marker(ms) => ms.unwrap_or(self.marker_ms),
+        }
+    }
+
+    /// Samples of silence after `chunk_break`, at most [`MAX_PAUSE_MS`]
+    pub fn pause_samples(&self, chunk_break: ChunkBreak, sample_rate: u32) -> usize {
+        let ms = self.pause_ms(chunk_break);
+        // NaN clamps to 0 through `as`
+        (ms.clamp(0.0, MAX_PAUSE_MS) * sample_rate as f32 / 1000.0).round() as usize
+    }
+}
+
+/// A chunk ready for generation, and what follows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub text: String,
+    pub after: ChunkBreak,
+}
+
+fn marker_regex() -> &'static Regex {
+    static MARKER: OnceLock<Regex> = OnceLock::new();
+    MARKER.get_or_init(|| {
+        Regex::new(r"(?i)\[(?:pause|break)(?:\s*[:=]\s*(\d+(?:\.\d+)?)\s*(ms|s)?)?\s*\]|\n\s*\n")
+            .unwrap()
+    })
+}
+
+/// Like [`preprocess_text`](super::clean::preprocess_text), but keeps paragraph breaks and `[pause]` markers (optionally
+/// `[pause:1.5s]` or `[pause:500ms]`) as breaks between chunks instead of dropping them.
+///
+/// Chunks never span a paragraph or marker. Breaks before any text are ignored.
+pub fn preprocess_text_with_breaks(text: &str) -> Vec<TextChunk> {
+    preprocess_text_with_breaks_chunked(
+        text,
+        &TextPipeline::default(),
+        &Segmenter::default(),
+        &Chunker::Characters,
+    )
+}
+
+/// [`preprocess_text_with_breaks`], normalizing each segment with `pipeline`, splitting sentences
+/// with `segmenter` and measuring chunks as `chunker` does
+pub fn preprocess_text_with_breaks_chunked(
+    text: &str,
+    pipeline: &TextPipeline,
+    segmenter: &Segmenter,
+    chunker: &Chunker,
+) -> Vec<TextChunk> {
+    let mut chunks: Vec<TextChunk> = Vec::new();
+    let mut segment_start = 0;
+    for caps in marker_regex().captures_iter(text) {
+        let whole = caps.get(0).unwrap();
+        let after = if whole.as_str().starts_with('[') {
+            ChunkBreak::Marker(
+                caps.get(1)
+                    .and_then(|n| n.as_str().parse::<f32>().ok())
+                    .map(
+                        |n| match caps.get(2).map(|u| u.as_str().to_lowercase()).as_deref() {
+                            Some("s") => n * 1000.0,
+                            _ => n,
+                        },
+                    ),
+            )
+        } else {
+            ChunkBreak::Paragraph
+        };
+        push_segment(
+            &text[segment_start..whole.start()],
+            after,
+            pipeline,
+            segmenter,
+            chunker,
+            &mut chunks,
+        );
+        segment_start = whole.end();
+    }
+    push_segment(
+        &text[segment_start..],
+        ChunkBreak::None,
+        pipeline,
+        segmenter,
+        chunker,
+        &mut chunks,
+    );
+
+    // Nothing to pause for after the last chunk
+    if let Some(last) = chunks.last_mut() {
+        last.after = ChunkBreak::None;
+    }
+    chunks
+}
+
+/// Chunks `segment` onto `chunks`, followed by `after`
+pub(crate) fn push_segment(
+    segment: &str,
+    after: ChunkBreak,
+    pipeline: &TextPipeline,
+    segmenter: &Segmenter,
+    chunker: &Chunker,
+    chunks: &mut Vec<TextChunk>,
+) {
+    let texts = preprocess_text_chunked(segment, pipeline, segmenter, chunker);
+    if texts.is_empty() {
+        if let Some(last) = chunks.last_mut() {
+            last.after = last.after.merge(after);
+        }
+        return;
+    }
+    let n = texts.len();
+    for (i, text) in texts.into_iter().enumerate() {
+        let own = if text
+            .trim_end_matches(['"', '\'', ')'])
+            .ends_with(is_terminator)
+        {
+            ChunkBreak::Sentence
+        } else {
+            ChunkBreak::None
+        };
+        let after = if i + 1 == n { own.merge(after) } else { own };
+        chunks.push(TextChunk { text, after });
+    }
+}
@@ -0,0 +1,313 @@
+//! Ordered text normalization, applied to each segment of input before it's chunked
+use super::normalize::normalize_text_in;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Arc;
+
+/// One step of a [`TextPipeline`]
+pub trait TextNormalizer: Send + Sync {
+    /// Identifies the step in [`TextPipeline::from_steps`] and [`TextPipeline::insert_before`]
+    fn name(&self) -> &str;
+    fn normalize(&self, text: &str) -> String;
+}
+
+/// Replacements applied in order, so longer patterns listed first win over their substrings
+const SYMBOLS: &[(&str, &str)] = &[
+    // Core cleanups that are very unlikely to harm
+    ("“", "\""),
+    ("”", "\""),
+    ("‘", "'"),
+    ("’", "'"),
+    ("…", "..."),
+    (" « ", " \""), // French quotes, with spaces inside
+    (" » ", "\" "),
+    ("«", "\""), // French opening quote
+    ("»", "\""), // French closing quote
+    // Remove zero-width spaces and other invisible formatters
+    ("\u{200B}", ""), // zero width space
+    ("\u{200C}", ""), // zero width non-joiner
+    ("\u{200D}", ""), // zero width joiner
+    ("\u{FEFF}", ""), // zero width no-break space
+    ("\u{200E}", ""), // left-to-right mark
+    ("\u{200F}", ""), // right-to-left mark
+    ("\u{061C}", ""), // Arabic letter mark
+    // Japanese-specific punctuation normalization
+    ("。", "."),  // Japanese period
+    ("、", ", "), // Comma - note the space after!
+    ("！", "!"),  // Japanese exclamation
+    ("？", "?"),  // Japanese question mark
+    ("「", "\""), // Japanese opening quote
+    ("」", "\""), // Japanese closing quote
+    ("『", "\""), // Japanese opening double quote
+    ("』", "\""), // Japanese closing double quote
+    ("・", ""),   // Nakaguro (middle dot)
+    ("：", ","),  // Japanese colon to comma
+    ("；", ","),  // Japanese semicolon to comma
+    ("（", ""),   // Japanese opening parenthesis
+    ("）", ""),   // Japanese closing parenthesis
+    ("【", ""),   // Japanese opening bracket
+    ("】", ""),   // Japanese closing bracket
+];
+
+/// Maps typographic quotes, invisible formatters and full-width punctuation to plain ASCII
+#[derive(Debug, Clone)]
+pub struct SymbolMap {
+    replacements: Vec<(String, String)>,
+}
+
+impl Default for SymbolMap {
+    fn default() -> Self {
+        Self {
+            replacements: SYMBOLS
+                .iter()
+                .map(|&(from, to)| (from.to_string(), to.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl SymbolMap {
+    /// Adds a replacement, applied after the existing ones
+    pub fn with(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.replacements.push((from.into(), to.into()));
+        self
+    }
+}
+
+impl TextNormalizer for SymbolMap {
+    fn name(&self) -> &str {
+        "symbols"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        let mut text = text.trim().to_string();
+        for (from, to) in &self.replacements {
+            text = text.replace(from.as_str(), to);
+        }
+        text
+    }
+}
+
+/// Drops emoji and pictographs, which the model can't read
+#[derive(Debug, Clone, Default)]
+pub struct StripEmoji;
+
+impl TextNormalizer for StripEmoji {
+    fn name(&self) -> &str {
+        "emoji"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        text.chars()
+            .filter(|&c| !('\u{1F300}'..='\u{1F9FF}').contains(&c))
+            .collect()
+    }
+}
+
+/// Reads numbers, dates, currency and units as words, by script. See [`normalize_text_in`].
+#[derive(Debug, Clone)]
+pub struct ScriptRules {
+    language: String,
+}
+
+impl Default for ScriptRules {
+    fn default() -> Self {
+        Self::new("en")
+    }
+}
+
+impl ScriptRules {
+    /// Reads Latin-script text as `language`; only English is read, see [`normalize_text_in`]
+    pub fn new(language: &str) -> Self {
+        Self {
+            language: language.to_string(),
+        }
+    }
+}
+
+impl TextNormalizer for ScriptRules {
+    fn name(&self) -> &str {
+        "numbers"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        normalize_text_in(text, &self.language)
+    }
+}
+
+/// Unifies dashes, collapses repeated punctuation and whitespace
+#[derive(Debug, Clone, Default)]
+pub struct Punctuation;
+
+impl TextNormalizer for Punctuation {
+    fn name(&self) -> &str {
+        "punctuation"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        // Normalize dash-like things to em-dash
+        let text = text
+            .replace(" - ", "—")
+            .replace("--", "—")
+            .replace(" – ", "—");
+
+        // Normalize multiple punctuation
+        let text = text
+            .replace("....", ".")
+            .replace("...", ".")
+            .replace("..", ".")
+            .replace(",,", ",");
+
+        // Ensure single spaces between words and after punctuation
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// A user-supplied replacement, with `$1`-style references to the pattern's groups
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegexRule {
+    pub pattern: String,
+    pub replacement: String,
+}
+
+/// [`RegexRule`]s applied in order
+#[derive(Debug, Clone, Default)]
+pub struct RegexRules {
+    rules: Vec<(Regex, String)>,
+}
+
+impl RegexRules {
+    pub fn new(rules: &[RegexRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .with_context(|| format!("Invalid text rule pattern {:?}", rule.pattern))
+                    .map(|regex| (regex, rule.replacement.clone()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    /// Loads a JSON list of `{"pattern": ..., "replacement": ...}` rules
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open text rules {}", path.display()))?;
+        let rules: Vec<RegexRule> = serde_json::from_reader(file)
+            .with_context(|| format!("Failed to parse text rules {}", path.display()))?;
+        Self::new(&rules)
+    }
+}
+
+impl TextNormalizer for RegexRules {
+    fn name(&self) -> &str {
+        "rules"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (regex, replacement) in &self.rules {
+            text = regex.replace_all(&text, replacement.as_str()).into_owned();
+        }
+        text
+    }
+}
+
+/// Normalizers applied one after another.
+///
+/// The default pipeline is `symbols`, `emoji`, `punctuation`, which is all the cleanup input
+/// gets unless a request asks for more.
+#[derive(Clone)]
+pub struct TextPipeline {
+    steps: Vec<Arc<dyn TextNormalizer>>,
+}
+
+impl Default for TextPipeline {
+    fn default() -> Self {
+        Self::new()
+            .with(SymbolMap::default())
+            .with(StripEmoji)
+            .with(Punctuation)
+    }
+}
+
+impl std::fmt::Debug for TextPipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.step_names()).finish()
+    }
+}
+
+impl TextPipeline {
+    /// A pipeline that leaves text as it is
+    pub fn new() -> Self {
+        Self { steps: Vec::new() }
+    }
+
+    /// A pipeline of built-in steps, in the given order: `symbols`, `emoji`, `numbers` or
+    /// `punctuation`
+    pub fn from_steps<S: AsRef<str>>(names: &[S]) -> Result<Self> {
+        let mut pipeline = Self::new();
+        for name in names {
+            pipeline = match name.as_ref() {
+                "symbols" => pipeline.with(SymbolMap::default()),
+                "emoji" => pipeline.with(StripEmoji),
+                "numbers" => pipeline.with(ScriptRules::default()),
+                "punctuation" => pipeline.with(Punctuation),
+                other => bail!(
+                    "Unknown text step {:?}; expected symbols, emoji, numbers or punctuation",
+                    other
+                ),
+            };
+        }
+        Ok(pipeline)
+    }
+
+    /// Appends `step`
+    pub fn with(mut self, step: impl TextNormalizer + 'static) -> Self {
+        self.steps.push(Arc::new(step));
+        self
+    }
+
+    /// Adds `step` before all the others
+    pub fn prepend(mut self, step: Arc<dyn TextNormalizer>) -> Self {
+        self.steps.insert(0, step);
+        self
+    }
+
+    /// Adds `step` before the first step called `name`, or at the end if there is none
+    pub fn insert_before(mut self, name: &str, step: Arc<dyn TextNormalizer>) -> Self {
+        let index = self
+            .steps
+            .iter()
+            .position(|s| s.name() == name)
+            .unwrap_or(self.steps.len());
+        self.steps.insert(index, step);
+        self
+    }
+
+    /// Reads numbers as `language` in any `numbers` step (see [`ScriptRules::new`])
+    pub fn with_language(mut self, language: &str) -> Self {
+        for step in self.steps.iter_mut().filter(|s| s.name() == "numbers") {
+            *step = Arc::new(ScriptRules::new(language));
+        }
+        self
+    }
+
+    /// Removes steps called `name`
+    pub fn without(mut self, name: &str) -> Self {
+        self.steps.retain(|s| s.name() != name);
+        self
+    }
+
+    pub fn step_names(&self) -> Vec<&str> {
+        self.steps.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn normalize(&self, text: &str) -> String {
+        self.steps
+            .iter()
+            .fold(text.to_string(), |text, step| step.normalize(&text))
+    }
+}
@@ -1,5 +1,6 @@
 use crate::config::{WhichFishVersion, WhichLM};
-use candle_core::{DType, Device, Error, IndexOp, Result, Tensor};
+use candle_core::{D, DType, Device, Error, IndexOp, Result, Tensor};
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use tokenizers::Tokenizer;
 
@@ -105,9 +106,16 @@ impl<'a> PromptEncoder<'a> {
         Tensor::cat(&[user_prompt, assistant_prompt], 1)
     }
 
+    /// A finished exchange: the user's text, then the (num_codebooks, seqlen) codec codes spoken for it
+    pub fn encode_turn(&self, text: &str, codes: &Tensor) -> Result<Tensor> {
+        let user = self.encode_text("user", Some(text))?;
+        let assistant = self.encode_vq(Some(codes))?;
+        Tensor::cat(&[user, assistant], 1)
+    }
+
     /// Returns (num_conditioning_tokens, encoded sequence)
     pub fn encode_sequence(
-        self,
+        &self,
         chunks: Vec<String>,
         sysprompt_text: Option<String>,
         cached_speaker: Option<Tensor>,
@@ -158,6 +166,79 @@ impl<'a> PromptEncoder<'a> {
     }
 }
 
+/// The last few finished turns (see [`PromptEncoder::encode_turn`]), replayed ahead of each new
+/// chunk so long-form generation keeps the pace and intonation of what came before
+#[derive(Debug, Clone)]
+pub struct ChunkHistory {
+    max_turns: usize,
+    turns: VecDeque<Tensor>,
+}
+
+impl ChunkHistory {
+    /// `max_turns` of 0 disables history
+    pub fn new(max_turns: usize) -> Self {
+        Self {
+            max_turns,
+            turns: VecDeque::with_capacity(max_turns),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_turns > 0
+    }
+
+    pub fn n_turns(&self) -> usize {
+        self.turns.len()
+    }
+
+    pub fn n_tokens(&self) -> usize {
+        self.turns
+            .iter()
+            .map(|t| t.dim(D::Minus1).unwrap_or(0))
+            .sum()
+    }
+
+    /// Adds a turn, dropping the oldest beyond `max_turns`
+    pub fn push(&mut self, turn: Tensor) {
+        if !self.is_enabled() {
+            return;
+        }
+        self.turns.push_back(turn);
+        while self.turns.len() > self.max_turns {
+            self.turns.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.turns.clear();
+    }
+
+    /// Prepends the retained turns to `prompt`, evicting the oldest until the cached prefix,
+    /// history, prompt and `reserve` generated frames all fit within `max_seq_len`
+    pub fn prepend(
+        &mut self,
+        prompt: &Tensor,
+        n_cached: usize,
+        max_seq_len: usize,
+        reserve: usize,
+    ) -> Result<Tensor> {
+        let fixed = n_cached + prompt.dim(D::Minus1)? + reserve;
+        while !self.turns.is_empty() && fixed + self.n_tokens() > max_seq_len {
+            self.turns.pop_front();
+            tracing::info!(
+                "Evicted oldest turn from chunk history to fit context; {} left",
+                self.turns.len()
+            );
+        }
+        if self.turns.is_empty() {
+            return Ok(prompt.clone());
+        }
+        let mut parts: Vec<&Tensor> = self.turns.iter().collect();
+        parts.push(prompt);
+        Tensor::cat(&parts, D::Minus1)
+    }
+}
+
 pub fn load_prompt_text(
     prompt_path: &PathBuf,
     device: &Device,
@@ -0,0 +1,165 @@
+use super::clean::{is_greek, is_hangul, is_hanzi, is_kana, is_terminator, is_thai};
+use std::collections::HashSet;
+
+const ENGLISH: &[&str] = &[
+    "mr", "mrs", "ms", "mx", "dr", "prof", "rev", "hon", "sr", "jr", "st", "vs", "etc", "e.g",
+    "i.e", "cf", "approx", "dept", "est", "fig", "inc", "ltd", "corp", "co", "mt", "vol", "jan",
+    "feb", "apr", "jun", "jul", "aug", "sep", "sept", "oct", "nov", "dec",
+];
+const GERMAN: &[&str] = &[
+    "z.b", "bzw", "usw", "ca", "dr", "prof", "nr", "str", "hr", "fr", "vgl", "evtl", "ggf", "inkl",
+    "u.a", "d.h", "s.o", "bspw",
+];
+const FRENCH: &[&str] = &[
+    "m", "mme", "mlle", "dr", "pr", "st", "ste", "etc", "cf", "p.ex", "env", "av", "bd",
+];
+const SPANISH: &[&str] = &[
+    "sr", "sra", "srta", "dr", "dra", "ud", "uds", "etc", "pág", "p.ej", "av", "núm",
+];
+
+/// Splits text into sentences without breaking at abbreviations, initials, decimals, URLs or
+/// emails, or inside quotes closed after the terminal punctuation
+#[derive(Debug, Clone)]
+pub struct Segmenter {
+    /// Lowercase, without the final period
+    abbreviations: HashSet<String>,
+}
+
+impl Default for Segmenter {
+    fn default() -> Self {
+        Self::new(ENGLISH.iter().copied())
+    }
+}
+
+impl Segmenter {
+    /// A segmenter that doesn't end sentences after `abbreviations`, given with or without their
+    /// final period (`"Dr"`, `"e.g."`)
+    pub fn new<S: AsRef<str>>(abbreviations: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            abbreviations: abbreviations
+                .into_iter()
+                .map(|a| a.as_ref().trim_end_matches('.').to_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Built-in abbreviations for `en`, `de`, `fr` or `es`
+    pub fn for_language(language: &str) -> Option<Self> {
+        let abbreviations = match language {
+            "en" => ENGLISH,
+            "de" => GERMAN,
+            "fr" => FRENCH,
+            "es" => SPANISH,
+            _ => return None,
+        };
+        Some(Self::new(abbreviations.iter().copied()))
+    }
+
+    pub fn with_abbreviations<S: AsRef<str>>(mut self, extra: impl IntoIterator<Item = S>) -> Self {
+        self.abbreviations.extend(Self::new(extra).abbreviations);
+        self
+    }
+
+    /// Sentences of `text`, trimmed, each with its terminal punctuation and closing quotes
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let chars: Vec<(usize, char)> = text.char_indices().collect();
+        let byte_at = |i: usize| chars.get(i).map_or(text.len(), |&(b, _)| b);
+        let mut sentences = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < chars.len() {
+            // Thai has no sentence punctuation: a space between Thai words ends a sentence
+            if chars[i].1.is_whitespace() && i > 0 && is_thai(chars[i - 1].1) {
+                let run_start = i;
+                while i < chars.len() && chars[i].1.is_whitespace() {
+                    i += 1;
+                }
+                if i < chars.len() && is_thai(chars[i].1) {
+                    let sentence = text[start..byte_at(run_start)].trim();
+                    if !sentence.is_empty() {
+                        sentences.push(sentence);
+                    }
+                    start = byte_at(i);
+                }
+                continue;
+            }
+            if !is_terminal(&chars, i) {
+                i += 1;
+                continue;
+            }
+            let run_start = i;
+            while i < chars.len() && is_terminal(&chars, i) {
+                i += 1;
+            }
+            let run: String = chars[run_start..i].iter().map(|&(_, c)| c).collect();
+            while i < chars.len() && is_closer(chars[i].1) {
+                i += 1;
+            }
+            let end = byte_at(i);
+            let word_start = text[start..byte_at(run_start)]
+                .rfind(char::is_whitespace)
+                .map_or(start, |ws| start + ws + 1);
+            let word = &text[word_start..byte_at(run_start)];
+            if self.is_boundary(&run, word, &text[end..]) {
+                let sentence = text[start..end].trim();
+                if !sentence.is_empty() {
+                    sentences.push(sentence);
+                }
+                start = end;
+            }
+        }
+        let rest = text[start..].trim();
+        if !rest.is_empty() {
+            sentences.push(rest);
+        }
+        sentences
+    }
+
+    /// Whether `run` of terminal punctuation after `word` ends a sentence, given the text `after` it
+    fn is_boundary(&self, run: &str, word: &str, after: &str) -> bool {
+        if run.contains(['。', '！', '？', '।', '॥', '۔']) {
+            return true;
+        }
+        // Decimals, URLs and emails continue straight after the period
+        match after.chars().next() {
+            None => return true,
+            Some(c) if c.is_whitespace() || is_cjk(c) => {}
+            Some(_) => return false,
+        }
+        // "Wait... what?", "“Why?” she asked."
+        if after.trim_start().starts_with(char::is_lowercase) {
+            return false;
+        }
+        if run != "." {
+            return true;
+        }
+        let word = word.trim_start_matches(['"', '\'', '(', '[', '“', '‘', '«']);
+        !(self.abbreviations.contains(&word.to_lowercase()) || is_initials(word))
+    }
+}
+
+/// "J", "U.S", "R.R": single capitals, possibly dotted together
+fn is_initials(word: &str) -> bool {
+    !word.is_empty()
+        && word
+            .split('.')
+            .all(|part| part.chars().count() == 1 && part.chars().all(char::is_uppercase))
+}
+
+/// Whether the `i`th of `chars` ends a sentence in some script. A semicolon is the Greek question
+/// mark after Greek letters.
+fn is_terminal(chars: &[(usize, char)], i: usize) -> bool {
+    let c = chars[i].1;
+    is_terminator(c) || (c == ';' && i > 0 && is_greek(chars[i - 1].1))
+}
+
+fn is_closer(c: char) -> bool {
+    matches!(
+        c,
+        '"' | '\'' | ')' | ']' | '}' | '”' | '’' | '»' | '」' | '』' | '）'
+    )
+}
+
+fn is_cjk(c: char) -> bool {
+    is_hanzi(c) || is_kana(c) || is_hangul(c)
+}
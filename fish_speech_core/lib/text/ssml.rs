@@ -0,0 +1,331 @@
+use super::chunking::Chunker;
+use super::normalize::cardinal_words;
+use super::pause::{ChunkBreak, MAX_PAUSE_MS, TextChunk, push_segment};
+use super::pipeline::TextPipeline;
+use super::segment::Segmenter;
+use anyhow::{Context, Result, bail};
+use roxmltree::{Document, Node};
+
+/// One step of an SSML document, in reading order
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsmlSegment {
+    /// Text to speak, with `<say-as>` and `<sub>` already substituted
+    Text(String),
+    /// `<break>` or the end of a `<p>`
+    Break(ChunkBreak),
+    /// Switch to the named voice; `None` returns to the request's voice
+    Voice(Option<String>),
+}
+
+/// Consecutive chunks spoken in one voice
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsmlRun {
+    /// `None` for the request's voice
+    pub voice: Option<String>,
+    pub chunks: Vec<TextChunk>,
+}
+
+/// Whether `input` looks like an SSML document rather than plain text
+pub fn is_ssml(input: &str) -> bool {
+    let input = input.trim_start();
+    let input = match input.strip_prefix("<?xml") {
+        Some(rest) => rest.split_once("?>").map_or("", |(_, r)| r).trim_start(),
+        None => input,
+    };
+    input.starts_with("<speak")
+}
+
+/// Parses a `<speak>` document into segments.
+///
+/// Supports `<break time strength>`, `<p>`, `<s>`, `<say-as interpret-as="characters|cardinal|date">`,
+/// `<sub alias>` and `<voice name>`. Other elements are read for their text only.
+pub fn parse_ssml(input: &str) -> Result<Vec<SsmlSegment>> {
+    let doc = Document::parse(input).context("Invalid SSML")?;
+    let root = doc.root_element();
+    if root.tag_name().name() != "speak" {
+        bail!(
+            "SSML root element must be <speak>, not <{}>",
+            root.tag_name().name()
+        );
+    }
+    let mut parser = Parser {
+        segments: Vec::new(),
+        voices: Vec::new(),
+    };
+    parser.children(root)?;
+    Ok(parser.segments)
+}
+
+/// Chunks parsed SSML for generation, grouping consecutive chunks by voice
+pub fn chunk_ssml(
+    segments: &[SsmlSegment],
+    pipeline: &TextPipeline,
+    segmenter: &Segmenter,
+    chunker: &Chunker,
+) -> Vec<SsmlRun> {
+    let mut chunks: Vec<TextChunk> = Vec::new();
+    let mut voices: Vec<Option<String>> = Vec::new();
+    let mut voice: Option<String> = None;
+    let mut text = String::new();
+    for segment in segments {
+        match segment {
+            SsmlSegment::Text(t) => text.push_str(t),
+            SsmlSegment::Break(chunk_break) => {
+                push_segment(
+                    &text,
+                    *chunk_break,
+                    pipeline,
+                    segmenter,
+                    chunker,
+                    &mut chunks,
+                );
+                voices.resize(chunks.len(), voice.clone());
+                text.clear();
+            }
+            SsmlSegment::Voice(next) => {
+                push_segment(
+                    &text,
+                    ChunkBreak::None,
+                    pipeline,
+                    segmenter,
+                    chunker,
+                    &mut chunks,
+                );
+                voices.resize(chunks.len(), voice.clone());
+                text.clear();
+                voice = next.clone();
+            }
+        }
+    }
+    push_segment(
+        &text,
+        ChunkBreak::None,
+        pipeline,
+        segmenter,
+        chunker,
+        &mut chunks,
+    );
+    voices.resize(chunks.len(), voice);
+    if let Some(last) = chunks.last_mut() {
+        last.after = ChunkBreak::None;
+    }
+
+    let mut runs: Vec<SsmlRun> = Vec::new();
+    for (chunk, voice) in chunks.into_iter().zip(voices) {
+        match runs.last_mut() {
+            Some(run) if run.voice == voice => run.chunks.push(chunk),
+            _ => runs.push(SsmlRun {
+                voice,
+                chunks: vec![chunk],
+            }),
+        }
+    }
+    runs
+}
+
+struct Parser {
+    segments: Vec<SsmlSegment>,
+    /// Enclosing `<voice>` names
+    voices: Vec<String>,
+}
+
+impl Parser {
+    fn children(&mut self, node: Node) -> Result<()> {
+        for child in node.children() {
+            if child.is_text() {
+                self.text(child.text().unwrap_or_default());
+            } else if child.is_element() {
+                self.element(child)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        if let Some(SsmlSegment::Text(last)) = self.segments.last_mut() {
+            last.push_str(text);
+        } else {
+            self.segments.push(SsmlSegment::Text(text.to_string()));
+        }
+    }
+
+    fn element(&mut self, node: Node) -> Result<()> {
+        match node.tag_name().name() {
+            "break" => {
+                let ms = match (node.attribute("time"), node.attribute("strength")) {
+                    (Some(time), _) => Some(parse_time(time)?),
+                    (None, Some(strength)) => Some(strength_ms(strength)?),
+                    (None, None) => None,
+                };
+                self.segments
+                    .push(SsmlSegment::Break(ChunkBreak::Marker(ms)));
+            }
+            "p" | "paragraph" => {
+                self.children(node)?;
+                self.segments
+                    .push(SsmlSegment::Break(ChunkBreak::Paragraph));
+            }
+            "s" | "sentence" => {
+                self.text(" ");
+                self.children(node)?;
+                if let Some(SsmlSegment::Text(last)) = self.segments.last_mut() {
+                    let trimmed = last.trim_end();
+                    if !trimmed.ends_with(['.', '!', '?']) && !trimmed.is_empty() {
+                        last.truncate(trimmed.len());
+                        last.push('.');
+                    }
+                }
+                self.text(" ");
+            }
+            "sub" => {
+                let alias = node
+                    .attribute("alias")
+                    .context("<sub> requires an alias attribute")?;
+                self.text(alias);
+            }
+            "say-as" => {
+                let text: String = node
+                    .descendants()
+                    .filter(|n| n.is_text())
+                    .filter_map(|n| n.text())
+                    .collect();
+                let interpret_as = node
+                    .attribute("interpret-as")
+                    .context("<say-as> requires an interpret-as attribute")?;
+                let spoken = say_as(interpret_as, node.attribute("format"), text.trim())?;
+                self.text(&spoken);
+            }
+            "voice" => {
+                let name = node
+                    .attribute("name")
+                    .context("<voice> requires a name attribute")?;
+                self.voices.push(name.to_string());
+                self.segments
+                    .push(SsmlSegment::Voice(Some(name.to_string())));
+                self.children(node)?;
+                self.voices.pop();
+                self.segments
+                    .push(SsmlSegment::Voice(self.voices.last().cloned()));
+            }
+            // <prosody>, <emphasis>, <lang>, <mark> and unknown elements: keep only their text
+            _ => self.children(node)?,
+        }
+        Ok(())
+    }
+}
+
+/// `500ms`, `1.5s` or a bare number of milliseconds, up to [`MAX_PAUSE_MS`]
+fn parse_time(time: &str) -> Result<f32> {
+    let time = time.trim();
+    let (number, scale) = if let Some(ms) = time.strip_suffix("ms") {
+        (ms, 1.0)
+    } else if let Some(s) = time.strip_suffix('s') {
+        (s, 1000.0)
+    } else {
+        (time, 1.0)
+    };
+    match number.trim().parse::<f32>().map(|n| n * scale) {
+        Ok(ms) if (0.0..=MAX_PAUSE_MS).contains(&ms) => Ok(ms),
+        Ok(ms) if ms > MAX_PAUSE_MS => bail!(
+            "Break time {:?} is longer than the {} ms maximum",
+            time,
+            MAX_PAUSE_MS
+        ),
+        _ => bail!("Invalid break time {:?}", time),
+    }
+}
+
+fn strength_ms(strength: &str) -> Result<f32> {
+    Ok(match strength {
+        "none" => 0.0,
+        "x-weak" => 125.0,
+        "weak" => 250.0,
+        "medium" => 400.0,
+        "strong" => 750.0,
+        "x-strong" => 1200.0,
+        _ => bail!("Invalid break strength {:?}", strength),
+    })
+}
+
+fn say_as(interpret_as: &str, format: Option<&str>, text: &str) -> Result<String> {
+    match interpret_as {
+        "characters" | "spell-out" => Ok(text
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.to_string())
+            .collect::<Vec<_>>()
+            .join(" ")),
+        "cardinal" | "number" => {
+            let digits: String = text.chars().filter(|c| !matches!(c, ',' | '_')).collect();
+            let (negative, digits) = match digits.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, digits.as_str()),
+            };
+            let n: u64 = digits
+                .parse()
+                .with_context(|| format!("Invalid cardinal {:?}", text))?;
+            let words = cardinal_words(n);
+            Ok(if negative {
+                format!("minus {}", words)
+            } else {
+                words
+            })
+        }
+        "date" => say_date(text, format.unwrap_or("ymd")),
+        // Unsupported interpretations are read as written
+        _ => Ok(text.to_string()),
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Reads `2024-03-15`, `03/15/2024` and similar, with field order from `format` (`ymd`, `mdy`,
+/// `dmy`, `md`, `dm`, `ym`, ...)
+fn say_date(text: &str, format: &str) -> Result<String> {
+    let fields: Vec<&str> = text
+        .split(['-', '/', '.'])
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .collect();
+    if fields.len() != format.len() {
+        bail!("Date {:?} doesn't match format {:?}", text, format);
+    }
+    let (mut year, mut month, mut day) = (None, None, None);
+    for (field, kind) in fields.iter().zip(format.chars()) {
+        let value: u32 = field
+            .parse()
+            .with_context(|| format!("Invalid date {:?}", text))?;
+        match kind {
+            'y' => year = Some(value),
+            'm' => month = Some(value),
+            'd' => day = Some(value),
+            _ => bail!("Invalid date format {:?}", format),
+        }
+    }
+    let month = month
+        .map(|m| match m {
+            1..=12 => Ok(MONTHS[m as usize - 1]),
+            _ => bail!("Invalid month in date {:?}", text),
+        })
+        .transpose()?;
+    Ok(match (month, day, year) {
+        (Some(m), Some(d), Some(y)) => format!("{} {}, {}", m, d, y),
+        (Some(m), Some(d), None) => format!("{} {}", m, d),
+        (Some(m), None, Some(y)) => format!("{} {}", m, y),
+        (Some(m), None, None) => m.to_string(),
+        _ => text.to_string(),
+    })
+}
@@ -3,13 +3,14 @@ use candle_core::{D, DType, Device, IndexOp, Result, Tensor};
 use candle_nn::VarBuilder;
 use clap::Parser;
 use fish_speech_core::config::{WhichFishVersion, WhichLM, WhichModel};
-use fish_speech_core::lm::generate::generate_blocking;
+use fish_speech_core::lm::generate::{generate_blocking, lm_codes_to_codec};
 use fish_speech_core::lm::sampling::SamplingArgs;
 use fish_speech_core::lm::{
     DualARTransformer,
     dual_ar::{BaseModelArgs, TokenConfig},
 };
-use fish_speech_core::text::prompt::{PromptEncoder, load_prompt_text};
+use fish_speech_core::text::clean::preprocess_text;
+use fish_speech_core::text::prompt::{ChunkHistory, PromptEncoder, load_prompt_text};
 use std::path::PathBuf;
 use tokenizers::Tokenizer;
 
@@ -25,6 +26,12 @@ fn generate_long(
         top_p: args.top_p,
         top_k: args.top_k,
         repetition_penalty: args.repetition_penalty,
+        min_p: args.min_p,
+        typical_p: args.typical_p,
+        frequency_penalty: args.frequency_penalty,
+        presence_penalty: args.presence_penalty,
+        rep_pen_window: args.rep_pen_window,
+        ..Default::default()
     };
 
     if args.prompt_tokens.len() != args.prompt_text.len() {
@@ -65,8 +72,6 @@ fn generate_long(
         _ => Tensor::cat(&conditioning_prompts?, D::Minus1)?,
     };
 
-    // TODO: use splitting code from the server
-    let text_to_generate = prompt_encoder.encode_text("user", Some(&args.text))?;
     tracing::info!("Text: {:?}", &args.text);
     let assistant_preprompt = prompt_encoder.encode_vq(None)?;
 
@@ -74,31 +79,64 @@ fn generate_long(
         "Speaker conditioning size: {:?}",
         final_conditioning.shape()
     );
-    let final_prompt = Tensor::cat(
-        &[final_conditioning, text_to_generate, assistant_preprompt],
-        D::Minus1,
-    )?;
-
-    tracing::info!("Loaded prompt with shape {:?}", final_prompt.shape());
-    // For debugging
-    let speaker_tokens = final_prompt
-        .i((0, ..))?
-        .flatten_all()?
-        .to_device(&Device::Cpu)?
-        .to_vec1::<u32>()?;
-    tracing::info!("Input tokens:\n{:?}", &speaker_tokens);
-    tracing::info!(
-        "Input prompt:\n{}",
-        tokenizer.decode(&speaker_tokens, false).unwrap()
-    );
+    let n_conditioning_tokens = final_conditioning.dim(D::Minus1)?;
+    let mut history = ChunkHistory::new(args.context_turns);
+    let mut all_codes = Vec::new();
+    // Without history, keep generating the whole text as a single prompt
+    let chunks = match args.context_turns {
+        0 => vec![args.text.clone()],
+        _ => preprocess_text(&args.text),
+    };
+    for (i, chunk) in chunks.iter().enumerate() {
+        let text_to_generate = prompt_encoder.encode_text("user", Some(chunk))?;
+        let chunk_prompt = Tensor::cat(&[&text_to_generate, &assistant_preprompt], D::Minus1)?;
+        // Conditioning stays in the KV cache after the first chunk
+        let n_cached = if i == 0 { 0 } else { n_conditioning_tokens };
+        let chunk_prompt = history.prepend(
+            &chunk_prompt,
+            n_cached,
+            model.cfg.max_seq_len,
+            args.max_new_tokens,
+        )?;
+        let final_prompt = match i {
+            0 => Tensor::cat(&[&final_conditioning, &chunk_prompt], D::Minus1)?,
+            _ => chunk_prompt,
+        };
+
+        tracing::info!(
+            "Chunk {} of {}: loaded prompt with shape {:?} ({} previous turns)",
+            i + 1,
+            chunks.len(),
+            final_prompt.shape(),
+            history.n_turns()
+        );
+        // For debugging
+        let speaker_tokens = final_prompt
+            .i((0, ..))?
+            .flatten_all()?
+            .to_device(&Device::Cpu)?
+            .to_vec1::<u32>()?;
+        tracing::info!("Input tokens:\n{:?}", &speaker_tokens);
+        tracing::info!(
+            "Input prompt:\n{}",
+            tokenizer.decode(&speaker_tokens, false).unwrap()
+        );
 
-    let res = generate_blocking(
-        model,
-        &final_prompt,
-        args.max_new_tokens,
-        &sampling_args,
-        true,
-    )?;
+        let res = generate_blocking(
+            model,
+            &final_prompt,
+            args.max_new_tokens,
+            &sampling_args,
+            true,
+        )?;
+        model.clear_slow_caches_until(n_conditioning_tokens)?;
+        if history.is_enabled() {
+            let codec_codes = lm_codes_to_codec(&res, &model_type)?;
+            history.push(prompt_encoder.encode_turn(chunk, &codec_codes)?);
+        }
+        all_codes.push(res);
+    }
+    let res = Tensor::cat(&all_codes, D::Minus1)?;
     res.write_npy(&args.out_path)?;
 
     Ok(())
@@ -127,6 +165,31 @@ struct Args {
     #[arg(long, default_value_t = 1.2)]
     repetition_penalty: f32,
 
+    /// Min-p sampling threshold, relative to the most likely token. 0 disables
+    #[arg(long, default_value_t = 0.0)]
+    min_p: f64,
+
+    /// Typical sampling mass. 1 disables
+    #[arg(long, default_value_t = 1.0)]
+    typical_p: f64,
+
+    /// Penalty per occurrence of a codebook token within the window
+    #[arg(long, default_value_t = 0.0)]
+    frequency_penalty: f32,
+
+    /// Flat penalty for codebook tokens seen within the window
+    #[arg(long, default_value_t = 0.0)]
+    presence_penalty: f32,
+
+    /// Frames of history the penalties look at (default 16)
+    #[arg(long)]
+    rep_pen_window: Option<usize>,
+
+    /// Previous chunks (text and generated audio) kept in context for the next one.
+    /// 0 disables history and generates the whole text as one prompt
+    #[arg(long, default_value_t = 0)]
+    context_turns: usize,
+
     /// Text to process (required)
     #[arg(long)]
     text: String,
@@ -0,0 +1,59 @@
+use anyhow::Result;
+use candle_core::{DType, Device};
+use clap::Parser;
+use fish_speech_core::lm::lora::LoraAdapter;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+#[command(
+    author = "Jacob Keisling <jacob@keisling.me>",
+    version = "0.1",
+    about = "Merges a LoRA adapter into Fish Speech LM weights"
+)]
+struct Args {
+    /// Checkpoint directory containing model.safetensors (or model.pth for 1.2)
+    #[arg(long, default_value = "checkpoints/fish-speech-1.5")]
+    checkpoint: PathBuf,
+
+    /// LoRA adapter weights (safetensors, PEFT-style lora_A / lora_B)
+    #[arg(short, long)]
+    adapter: PathBuf,
+
+    /// Output path. Defaults to model-merged.safetensors in the checkpoint directory
+    #[arg(short, long)]
+    output_path: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let device = Device::Cpu;
+
+    let safetensors_path = args.checkpoint.join("model.safetensors");
+    let mut weights: HashMap<_, _> = if safetensors_path.exists() {
+        candle_core::safetensors::load(&safetensors_path, &device)?
+    } else {
+        candle_core::pickle::read_all(args.checkpoint.join("model.pth"))?
+            .into_iter()
+            .collect()
+    };
+    let output_path = args
+        .output_path
+        .unwrap_or_else(|| args.checkpoint.join("model-merged.safetensors"));
+
+    println!("Merging {:?} into {:?}", args.adapter, args.checkpoint);
+    let start = Instant::now();
+    // Merge in F32 so the low-rank update isn't rounded away; merge_into casts back to the base dtype
+    let adapter = LoraAdapter::load(&args.adapter, "merge", DType::F32, &device)?;
+    let n_merged = adapter.merge_into(&mut weights)?;
+    candle_core::safetensors::save(&weights, &output_path)?;
+    println!(
+        "Merged {} layers to {:?} in {:.2}s",
+        n_merged,
+        output_path,
+        start.elapsed().as_secs_f64()
+    );
+
+    Ok(())
+}
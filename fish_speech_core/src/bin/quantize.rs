@@ -0,0 +1,58 @@
+use anyhow::Result;
+use clap::Parser;
+use fish_speech_core::config::WhichQuantization;
+use fish_speech_core::lm::dual_ar::BaseModelArgs;
+use fish_speech_core::lm::quantized::quantize_safetensors;
+use std::path::PathBuf;
+use std::time::Instant;
+
+#[derive(Parser, Debug)]
+#[command(
+    author = "Jacob Keisling <jacob@keisling.me>",
+    version = "0.1",
+    about = "Quantizes Fish Speech LM weights to GGUF"
+)]
+struct Args {
+    /// Checkpoint directory containing model.safetensors and config.json
+    #[arg(long, default_value = "checkpoints/fish-speech-1.5")]
+    checkpoint: PathBuf,
+
+    #[arg(short, long, default_value = "q8_0")]
+    quantization: WhichQuantization,
+
+    /// Output path. Defaults to model-<quantization>.gguf in the checkpoint directory,
+    /// which is where the server and Python bindings look for it
+    #[arg(short, long)]
+    output_path: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let config = BaseModelArgs::from_file(args.checkpoint.join("config.json"))?;
+    let output_path = args
+        .output_path
+        .unwrap_or_else(|| args.checkpoint.join(args.quantization.gguf_file_name()));
+
+    println!(
+        "Quantizing {:?} to {} at {:?}",
+        args.checkpoint,
+        args.quantization.as_str(),
+        output_path
+    );
+    let start = Instant::now();
+    let (n_quantized, n_dense) = quantize_safetensors(
+        &args.checkpoint.join("model.safetensors"),
+        &output_path,
+        &config,
+        args.quantization,
+    )?;
+    println!(
+        "Wrote {} quantized and {} dense tensors in {:.2}s",
+        n_quantized,
+        n_dense,
+        start.elapsed().as_secs_f64()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use fish_speech_core::text::alignment::{
+    WordAlignment, align_words, default_alignment_layers, find_text_tokens, monotonic_alignment,
+};
+
+/// Attention peaked on `token` for each frame, with a little on every other token
+fn peaked(path: &[usize], n_tokens: usize) -> Vec<Vec<f32>> {
+    path.iter()
+        .map(|&token| {
+            (0..n_tokens)
+                .map(|j| if j == token { 0.8 } else { 0.05 })
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn alignment_follows_the_attention_peak() {
+    let spans = monotonic_alignment(&peaked(&[0, 0, 1, 1, 1, 2, 3, 3], 4));
+    assert_eq!(spans, vec![0..2, 2..5, 5..6, 6..8]);
+}
+
+#[test]
+fn alignment_stays_monotonic() {
+    // A stray look back at token 0 mid-way shouldn't send the alignment backwards
+    let spans = monotonic_alignment(&peaked(&[0, 1, 1, 0, 2, 2], 3));
+    for pair in spans.windows(2) {
+        assert!(pair[0].end <= pair[1].start);
+    }
+    assert_eq!(spans.last().unwrap().end, 6);
+}
+
+#[test]
+fn skipped_tokens_get_empty_spans() {
+    let spans = monotonic_alignment(&peaked(&[0, 0, 2, 2], 3));
+    assert_eq!(spans, vec![0..2, 2..2, 2..4]);
+}
+
+#[test]
+fn text_tokens_are_found_in_the_prompt() {
+    let prompt = [1, 2, 7, 8, 9, 3, 7, 8, 9, 4];
+    assert_eq!(find_text_tokens(&prompt, &[7, 8, 9]), Some(6..9));
+    // First token merged differently inside the template
+    assert_eq!(find_text_tokens(&prompt, &[6, 9, 4]), Some(7..10));
+    assert_eq!(find_text_tokens(&prompt, &[5, 6]), None);
+    assert_eq!(find_text_tokens(&prompt, &[]), None);
+}
+
+#[test]
+fn tokens_are_grouped_into_words() {
+    let text = "Hello, big world";
+    // "Hel" "lo," " big" " world"
+    let offsets = [(0, 3), (3, 6), (6, 10), (10, 16)];
+    let words = align_words(text, &offsets, &[0..2, 2..3, 3..5, 5..9]);
+    let expected = [("Hello,", 0, 3), ("big", 3, 5), ("world", 5, 9)];
+    assert_eq!(
+        words,
+        expected
+            .iter()
+            .map(|&(word, start_frame, end_frame)| WordAlignment {
+                word: word.to_string(),
+                start_frame,
+                end_frame,
+            })
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn cjk_characters_are_words() {
+    let text = "你好 ok";
+    let offsets = [(0, 6), (6, 9)];
+    let words = align_words(text, &offsets, &[0..4, 4..6]);
+    let words: Vec<(&str, usize, usize)> = words
+        .iter()
+        .map(|w| (w.word.as_str(), w.start_frame, w.end_frame))
+        .collect();
+    assert_eq!(words, vec![("你", 0, 2), ("好", 2, 4), ("ok", 4, 6)]);
+
+    // Extension B ideographs are words of their own too
+    let words = align_words("𠀀好", &[(0, 4), (4, 7)], &[0..2, 2..4]);
+    assert_eq!(words.len(), 2);
+    assert_eq!(words[0].word, "𠀀");
+}
+
+#[test]
+fn zero_width_tokens_are_skipped() {
+    let text = "Hello world";
+    // A zero-width token inside "Hello" covers no frames of its own
+    let offsets = [(0, 3), (3, 3), (3, 5), (5, 11)];
+    let words = align_words(text, &offsets, &[0..2, 2..3, 3..4, 4..8]);
+    let words: Vec<(&str, usize, usize)> = words
+        .iter()
+        .map(|w| (w.word.as_str(), w.start_frame, w.end_frame))
+        .collect();
+    assert_eq!(words, vec![("Hello", 0, 4), ("world", 4, 8)]);
+}
+
+#[test]
+fn default_layers_are_the_middle_third() {
+    assert_eq!(default_alignment_layers(24), (8..16).collect::<Vec<_>>());
+    assert_eq!(default_alignment_layers(2), vec![0, 1]);
+    assert_eq!(default_alignment_layers(1), vec![0]);
+}
@@ -0,0 +1,74 @@
+use fish_speech_core::text::captions::{ChunkTiming, WordTiming, to_srt, to_vtt};
+
+fn timings() -> Vec<ChunkTiming> {
+    vec![
+        ChunkTiming {
+            text: "Hello there.".to_string(),
+            start_sample: 0,
+            end_sample: 18_000,
+            tokens: 16,
+            words: Vec::new(),
+        },
+        ChunkTiming {
+            text: "General Kenobi --> you are a bold one.".to_string(),
+            start_sample: 20_400,
+            end_sample: 3_700 * 24_000 + 12,
+            tokens: 40,
+            words: Vec::new(),
+        },
+    ]
+}
+
+#[test]
+fn srt_numbers_cues_with_comma_millis() {
+    let srt = to_srt(&timings(), 24_000);
+    assert_eq!(
+        srt,
+        "1\n00:00:00,000 --> 00:00:00,750\nHello there.\n\n\
+         2\n00:00:00,850 --> 01:01:40,001\nGeneral Kenobi --> you are a bold one.\n\n"
+    );
+}
+
+#[test]
+fn vtt_has_a_header_and_escapes_arrows() {
+    let vtt = to_vtt(&timings(), 24_000);
+    assert!(vtt.starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:00.750\nHello there.\n\n"));
+    assert!(vtt.contains("00:00:00.850 --> 01:01:40.001\nGeneral Kenobi -> you are a bold one."));
+}
+
+#[test]
+fn vtt_tags_word_starts() {
+    let word = |word: &str, start_sample, end_sample| WordTiming {
+        word: word.to_string(),
+        start_sample,
+        end_sample,
+    };
+    let timings = vec![ChunkTiming {
+        text: "Fish & <chips>".to_string(),
+        start_sample: 0,
+        end_sample: 24_000,
+        tokens: 12,
+        words: vec![
+            word("Fish", 0, 6_000),
+            word("&", 8_000, 9_000),
+            word("<chips>", 12_000, 24_000),
+        ],
+    }];
+    let vtt = to_vtt(&timings, 24_000);
+    assert!(vtt.contains("Fish <00:00:00.333>&amp; <00:00:00.500>&lt;chips>\n"));
+    assert!(!to_srt(&timings, 24_000).contains("<00"));
+}
+
+#[test]
+fn vtt_escapes_chunks_without_word_timings() {
+    let timings = vec![ChunkTiming {
+        text: "Fish & <chips>".to_string(),
+        start_sample: 0,
+        end_sample: 24_000,
+        tokens: 12,
+        words: Vec::new(),
+    }];
+    let vtt = to_vtt(&timings, 24_000);
+    assert!(vtt.contains("\nFish &amp; &lt;chips>\n"));
+    assert!(to_srt(&timings, 24_000).contains("\nFish & <chips>\n"));
+}
@@ -0,0 +1,62 @@
+use candle_core::{Device, Tensor};
+use fish_speech_core::audio::seam::{PcmJoiner, with_left_context};
+
+#[test]
+fn left_context_takes_the_tail_of_the_previous_chunk() {
+    let device = Device::Cpu;
+    let prev = Tensor::arange(0u32, 10, &device)
+        .unwrap()
+        .reshape((2, 5))
+        .unwrap();
+    let codes = Tensor::arange(100u32, 106, &device)
+        .unwrap()
+        .reshape((2, 3))
+        .unwrap();
+
+    let (joined, n) = with_left_context(Some(&prev), &codes, 2).unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(
+        joined.to_vec2::<u32>().unwrap(),
+        vec![vec![3, 4, 100, 101, 102], vec![8, 9, 103, 104, 105]]
+    );
+
+    // Never more than the previous chunk has
+    let (_, n) = with_left_context(Some(&prev), &codes, 20).unwrap();
+    assert_eq!(n, 5);
+    let (unchanged, n) = with_left_context(None, &codes, 2).unwrap();
+    assert_eq!(n, 0);
+    assert_eq!(unchanged.dims(), codes.dims());
+}
+
+#[test]
+fn joiner_drops_context_and_crossfades_the_seam() {
+    let mut joiner = PcmJoiner::new(4);
+    let first: Vec<f32> = vec![1.0; 10];
+    let mut out = joiner.push(&first, 0);
+    // The crossfade-length tail is held back
+    assert_eq!(out.len(), 6);
+
+    // 6 samples of context re-rendering the end of the first chunk, then 8 new ones
+    let mut second = vec![0.0f32; 6];
+    second.extend(vec![0.0f32; 8]);
+    out.extend(joiner.push(&second, 6));
+    out.extend(joiner.finish());
+
+    // No samples are duplicated or lost
+    assert_eq!(out.len(), 10 + 8);
+    // The seam fades from the old chunk to the new one without jumps
+    let seam = &out[6..10];
+    assert!(seam.windows(2).all(|w| w[0] >= w[1]), "{:?}", seam);
+    assert!(seam[0] < 1.0 && seam[0] > 0.5);
+    assert!(seam[3] > 0.0 && seam[3] < 0.5);
+    assert!(out[10..].iter().all(|&s| s == 0.0));
+}
+
+#[test]
+fn joiner_without_context_concatenates() {
+    let mut joiner = PcmJoiner::new(3);
+    let mut out = joiner.push(&[1.0, 2.0, 3.0, 4.0], 0);
+    out.extend(joiner.push(&[5.0, 6.0], 0));
+    out.extend(joiner.finish());
+    assert_eq!(out, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+}
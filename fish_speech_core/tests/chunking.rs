@@ -0,0 +1,113 @@
+use fish_speech_core::text::chunking::{Chunker, TokenBudget};
+use fish_speech_core::text::clean::{estimate_syllables, preprocess_text, preprocess_text_chunked};
+use fish_speech_core::text::pause::{ChunkBreak, preprocess_text_with_breaks_chunked};
+use fish_speech_core::text::pipeline::TextPipeline;
+use fish_speech_core::text::segment::Segmenter;
+use std::collections::HashMap;
+use tokenizers::Tokenizer;
+use tokenizers::models::wordlevel::WordLevel;
+use tokenizers::pre_tokenizers::whitespace::Whitespace;
+
+/// One token per word or punctuation mark
+fn word_tokenizer() -> Tokenizer {
+    let vocab: HashMap<String, u32> = [("[unk]".to_string(), 0)].into_iter().collect();
+    let model = WordLevel::builder()
+        .vocab(vocab.into_iter().collect())
+        .unk_token("[unk]".to_string())
+        .build()
+        .unwrap();
+    let mut tokenizer = Tokenizer::new(model);
+    tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+    tokenizer
+}
+
+fn tokens(tokenizer: &Tokenizer, max_tokens: usize, max_frames: usize) -> Chunker<'_> {
+    Chunker::Tokens {
+        tokenizer,
+        budget: TokenBudget {
+            max_tokens,
+            max_frames,
+            frames_per_syllable: 5.0,
+        },
+    }
+}
+
+fn chunk(text: &str, chunker: &Chunker) -> Vec<String> {
+    preprocess_text_chunked(
+        text,
+        &TextPipeline::default(),
+        &Segmenter::default(),
+        chunker,
+    )
+}
+
+#[test]
+fn token_chunks_pack_sentences_after_the_first() {
+    let tokenizer = word_tokenizer();
+    let chunker = tokens(&tokenizer, 8, 1000);
+    assert_eq!(
+        chunk(
+            "One two. Three four. Five six. Seven eight nine ten.",
+            &chunker
+        ),
+        vec!["One two.", "Three four. Five six.", "Seven eight nine ten."]
+    );
+}
+
+#[test]
+fn long_sentences_split_on_commas_then_words() {
+    let tokenizer = word_tokenizer();
+    let chunker = tokens(&tokenizer, 4, 1000);
+    let chunks = chunk("Red, green, blue and yellow and purple. Hi.", &chunker);
+    assert_eq!(
+        chunks,
+        vec!["Red, green,", "blue and yellow and", "purple.", "Hi."]
+    );
+    for chunk in &chunks {
+        assert!(tokenizer.encode(chunk.as_str(), false).unwrap().len() <= 4);
+    }
+
+    // CJK without spaces splits between characters
+    let chunks = chunk("一二三四五六七八九十。", &tokens(&tokenizer, 100, 20));
+    assert_eq!(chunks, vec!["一二三四", "五六七八", "九十."]);
+}
+
+#[test]
+fn frame_budget_limits_chunks() {
+    let tokenizer = word_tokenizer();
+    // Each sentence is about one syllable per three letters, so 5 frames
+    let chunks = chunk("Yes. Yes. Yes. Yes.", &tokens(&tokenizer, 100, 10));
+    assert_eq!(chunks, vec!["Yes.", "Yes. Yes.", "Yes."]);
+}
+
+#[test]
+fn chunking_off_keeps_segments_whole() {
+    let text = "First sentence. Second sentence, long and winding.\n\nNext paragraph.";
+    let chunks = preprocess_text_with_breaks_chunked(
+        text,
+        &TextPipeline::default(),
+        &Segmenter::default(),
+        &Chunker::Off,
+    );
+    let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(
+        texts,
+        vec![
+            "First sentence. Second sentence, long and winding.",
+            "Next paragraph."
+        ]
+    );
+    assert_eq!(chunks[0].after, ChunkBreak::Paragraph);
+}
+
+#[test]
+fn character_chunking_is_the_default() {
+    let text = "Short one. Another short one. And a third.";
+    assert_eq!(chunk(text, &Chunker::default()), preprocess_text(text));
+}
+
+#[test]
+fn syllable_estimate_counts_cjk_per_character() {
+    assert_eq!(estimate_syllables("你好世界"), 4.0);
+    assert_eq!(estimate_syllables("abc def!"), 2.0);
+}
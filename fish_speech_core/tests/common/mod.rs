@@ -0,0 +1,62 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+use candle_core::{DType, Device, Tensor};
+use candle_nn::{VarBuilder, VarMap};
+use fish_speech_core::config::WhichLM;
+use fish_speech_core::lm::dual_ar::{BaseModelArgs, DualARTransformer, TokenConfig};
+
+pub const VOCAB_SIZE: u32 = 26;
+pub const IM_END_ID: u32 = 9;
+pub const SEMANTIC_START_ID: u32 = 10;
+
+/// A randomly initialized two-layer model with 3 codebooks of 16 codes, with weights drawn
+/// with standard deviation `init_std` so it isn't trivially uniform
+pub fn tiny_model(device: &Device, init_std: f32) -> DualARTransformer {
+    tiny_model_with_vars(device, init_std).0
+}
+
+/// [`tiny_model`], with its weights for tests to overwrite by name
+pub fn tiny_model_with_vars(device: &Device, init_std: f32) -> (DualARTransformer, VarMap) {
+    let cfg = BaseModelArgs {
+        vocab_size: VOCAB_SIZE as usize,
+        n_layer: 2,
+        n_fast_layer: 1,
+        n_head: 4,
+        n_local_heads: 2,
+        head_dim: 8,
+        dim: 32,
+        intermediate_size: Some(64),
+        max_seq_len: 64,
+        codebook_size: 16,
+        num_codebooks: 3,
+        ..BaseModelArgs::fish_speech_1_2()
+    };
+    let token_config = TokenConfig {
+        im_end_id: IM_END_ID,
+        pad_id: SEMANTIC_START_ID,
+        semantic_start_id: SEMANTIC_START_ID,
+        semantic_end_id: Some(VOCAB_SIZE - 1),
+    };
+    let varmap = VarMap::new();
+    let vb = VarBuilder::from_varmap(&varmap, DType::F32, device);
+    let model = DualARTransformer::load(&vb, &cfg, &token_config, WhichLM::DualAR).unwrap();
+    for var in varmap.all_vars() {
+        let init = Tensor::randn(0f32, init_std, var.shape(), device).unwrap();
+        var.set(&init).unwrap();
+    }
+    (model, varmap)
+}
+
+/// Largest absolute difference between two tensors of the same shape
+pub fn max_abs_diff(a: &Tensor, b: &Tensor) -> f32 {
+    (a - b)
+        .unwrap()
+        .abs()
+        .unwrap()
+        .flatten_all()
+        .unwrap()
+        .max(0)
+        .unwrap()
+        .to_scalar::<f32>()
+        .unwrap()
+}
@@ -0,0 +1,49 @@
+use candle_core::{Device, IndexOp, Tensor};
+use fish_speech_core::lm::generate::clamp_to_context;
+use fish_speech_core::text::prompt::ChunkHistory;
+
+#[test]
+fn clamp_to_context_limits_generation_to_remaining_positions() {
+    // Plenty of room: request passes through untouched
+    assert_eq!(clamp_to_context(4096, 1000, 100, 1024).unwrap(), 1024);
+    // Long speaker prompt + chunk: clamp to what is left
+    assert_eq!(clamp_to_context(4096, 3000, 500, 1024).unwrap(), 596);
+    // Prompt alone overflows the context
+    assert!(clamp_to_context(4096, 3900, 196, 1024).is_err());
+}
+
+fn turn(len: usize, marker: u32) -> Tensor {
+    Tensor::full(marker, (3, len), &Device::Cpu).unwrap()
+}
+
+#[test]
+fn chunk_history_keeps_last_turns_within_context() {
+    let mut history = ChunkHistory::new(2);
+    for marker in 1..=3 {
+        history.push(turn(10, marker));
+    }
+    // Only the last two turns survive
+    assert_eq!(history.n_turns(), 2);
+    let prompt = turn(5, 0);
+    let extended = history.prepend(&prompt, 20, 100, 40).unwrap();
+    let markers = extended.i((0, ..)).unwrap().to_vec1::<u32>().unwrap();
+    assert_eq!(markers.len(), 25);
+    assert_eq!(&markers[..2], &[2, 2]);
+    assert_eq!(&markers[10..12], &[3, 3]);
+    assert_eq!(&markers[20..], &[0; 5]);
+
+    // 20 cached + 10 history + 5 prompt + 60 reserve: the oldest turn goes
+    let extended = history.prepend(&prompt, 20, 100, 60).unwrap();
+    assert_eq!(extended.dim(1).unwrap(), 15);
+    assert_eq!(history.n_turns(), 1);
+
+    // No room at all: the prompt is passed through
+    let extended = history.prepend(&prompt, 20, 100, 90).unwrap();
+    assert_eq!(extended.dim(1).unwrap(), 5);
+    assert_eq!(history.n_turns(), 0);
+
+    let mut disabled = ChunkHistory::new(0);
+    disabled.push(turn(10, 1));
+    assert!(!disabled.is_enabled());
+    assert_eq!(disabled.n_turns(), 0);
+}
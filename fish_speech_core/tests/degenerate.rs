@@ -0,0 +1,105 @@
+use candle_core::{Device, Tensor};
+use fish_speech_core::lm::generate::degenerate::{Degeneracy, DegenerateConfig, detect_degenerate};
+
+const FRAME_RATE: f64 = 21.535;
+
+/// (name, codes, text, max_new_tokens, expected issue kinds)
+type Case<'a> = (
+    &'a str,
+    Vec<Vec<u32>>,
+    Option<&'a str>,
+    Option<usize>,
+    Vec<&'a str>,
+);
+
+/// (num_codebooks, n_frames) codes from a cheap deterministic generator
+fn varied_codes(n_codebooks: usize, n_frames: usize) -> Vec<Vec<u32>> {
+    let mut state = 12345u64;
+    (0..n_codebooks)
+        .map(|_| {
+            (0..n_frames)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    ((state >> 33) % 1024) as u32
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn kinds(
+    codes: Vec<Vec<u32>>,
+    text: Option<&str>,
+    max_new_tokens: Option<usize>,
+) -> Vec<&'static str> {
+    let codes = Tensor::new(codes, &Device::Cpu).unwrap();
+    detect_degenerate(
+        &codes,
+        text,
+        FRAME_RATE,
+        max_new_tokens,
+        &DegenerateConfig::default(),
+    )
+    .unwrap()
+    .iter()
+    .map(Degeneracy::kind)
+    .collect()
+}
+
+#[test]
+fn detects_degenerate_takes() {
+    let text = "The quick brown fox jumped over the lazy dog";
+    // ~3s for ~12 syllables
+    let normal = varied_codes(8, 64);
+
+    let stuck = vec![vec![7u32; 64]; 8];
+
+    let cycle = varied_codes(8, 6);
+    let looping: Vec<Vec<u32>> = cycle
+        .iter()
+        .map(|row| row.iter().copied().cycle().take(64).collect())
+        .collect();
+
+    let cases: Vec<Case> = vec![
+        ("clean take", normal.clone(), Some(text), Some(1024), vec![]),
+        (
+            "no text skips duration",
+            varied_codes(8, 2),
+            None,
+            None,
+            vec![],
+        ),
+        (
+            "too short",
+            varied_codes(8, 5),
+            Some(text),
+            None,
+            vec!["too_short"],
+        ),
+        (
+            "too long",
+            varied_codes(8, 400),
+            Some(text),
+            None,
+            vec!["too_long"],
+        ),
+        (
+            "hit limit",
+            normal,
+            Some(text),
+            Some(64),
+            vec!["hit_token_limit"],
+        ),
+        (
+            "stuck frame",
+            stuck,
+            Some(text),
+            None,
+            vec!["repeated_frames", "looping", "low_variance"],
+        ),
+        ("loop", looping, Some(text), None, vec!["looping"]),
+    ];
+    for (name, codes, text, max_new_tokens, expected) in cases {
+        assert_eq!(kinds(codes, text, max_new_tokens), expected, "{}", name);
+    }
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use candle_core::{D, DType, Device, Tensor};
+use common::tiny_model;
+use fish_speech_core::lm::generate::generate_blocking;
+use fish_speech_core::lm::sampling::{EosControl, SamplingArgs, legacy_softmax_sample};
+
+#[test]
+fn eos_bias_schedule() {
+    let eos = EosControl::from_durations(Some(1.0), Some(2.0), 10.0, Some(4.0));
+    assert_eq!(eos.min_frames, 10);
+    assert_eq!(eos.target_frames, Some(20));
+    assert!(eos.is_active());
+
+    assert_eq!(eos.eos_bias(9), f32::NEG_INFINITY);
+    assert_eq!(eos.eos_bias(10), -2.0);
+    assert_eq!(eos.eos_bias(20), 0.0);
+    assert_eq!(eos.eos_bias(30), 2.0);
+    assert_eq!(eos.eos_bias(100), 4.0);
+    assert_eq!(eos.max_new_tokens(1792), 40);
+
+    let min_only = EosControl::from_durations(Some(1.0), None, 10.0, None);
+    assert_eq!(min_only.eos_bias(10), 0.0);
+    assert_eq!(min_only.max_new_tokens(1792), 1792);
+    assert_eq!(min_only.max_new_tokens(4), 11);
+    assert!(!EosControl::default().is_active());
+}
+
+#[test]
+fn legacy_sample_suppresses_eos() {
+    for _ in 0..50 {
+        assert_eq!(
+            legacy_softmax_sample(-10.0, 10.0, 1, 2, f32::NEG_INFINITY),
+            1
+        );
+    }
+}
+
+#[test]
+fn generation_respects_min_frames() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let n_codebooks = model.cfg.num_codebooks;
+    let text = Tensor::new(&[1u32, 2, 3, 4, 5], &device).unwrap();
+    let prompt = Tensor::cat(
+        &[
+            text.unsqueeze(0).unwrap(),
+            Tensor::zeros((n_codebooks, 5), DType::U32, &device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap();
+    // Huge bias past a tiny target: EOS is forced as soon as it's allowed
+    let sampling_args = SamplingArgs {
+        temp: 0.0,
+        eos: EosControl {
+            min_frames: 5,
+            target_frames: Some(1),
+            bias: 1e4,
+        },
+        ..Default::default()
+    };
+    let codes = generate_blocking(&mut model, &prompt, 20, &sampling_args, false).unwrap();
+    assert_eq!(codes.dim(D::Minus1).unwrap(), 5);
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use candle_core::{DType, Device, Tensor};
+use common::{IM_END_ID, SEMANTIC_START_ID, VOCAB_SIZE, tiny_model, tiny_model_with_vars};
+use fish_speech_core::lm::generate::{GenerationEvent, InterleavedGenerator};
+use fish_speech_core::lm::sampling::SamplingArgs;
+use std::collections::HashMap;
+use tokenizers::Tokenizer;
+use tokenizers::models::wordlevel::WordLevel;
+
+fn tiny_tokenizer() -> Tokenizer {
+    let vocab: HashMap<String, u32> = (0..VOCAB_SIZE).map(|i| (format!("w{}", i), i)).collect();
+    let model = WordLevel::builder()
+        .vocab(vocab.into_iter().collect())
+        .unk_token("w0".to_string())
+        .build()
+        .unwrap();
+    Tokenizer::new(model)
+}
+
+#[test]
+fn interleaved_events() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 1.0);
+    let tokenizer = tiny_tokenizer();
+    let n_codebooks = model.cfg.num_codebooks;
+    let prompt = Tensor::cat(
+        &[
+            Tensor::new(&[[1u32, 2, 3]], &device).unwrap(),
+            Tensor::zeros((n_codebooks, 3), DType::U32, &device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap();
+    let sampling_args = SamplingArgs {
+        temp: 1.0,
+        top_p: 1.0,
+        top_k: 0,
+        ..Default::default()
+    };
+
+    for _ in 0..5 {
+        let generator =
+            InterleavedGenerator::new(&mut model, &tokenizer, &prompt, 30, &sampling_args).unwrap();
+        let events: Vec<GenerationEvent> = generator.map(|e| e.unwrap()).collect();
+        model.clear_slow_layer_caches();
+
+        let Some(GenerationEvent::End { finished }) = events.last() else {
+            panic!("Generation must end with an End event");
+        };
+        // One event per step, plus End
+        assert!(events.len() - 1 <= 30);
+        assert!(*finished || events.len() - 1 == 30);
+        for event in &events[..events.len() - 1] {
+            match event {
+                GenerationEvent::Text { token, .. } => {
+                    assert!(*token < SEMANTIC_START_ID && *token != IM_END_ID);
+                }
+                GenerationEvent::Audio { codes } => {
+                    assert_eq!(codes.dims(), &[n_codebooks, 1]);
+                }
+                GenerationEvent::End { .. } => panic!("End must be the last event"),
+            }
+        }
+    }
+}
+
+#[test]
+fn text_deltas_rebuild_the_forced_text() {
+    let device = Device::Cpu;
+    let (mut model, varmap) = tiny_model_with_vars(&device, 0.02);
+    let dim = model.cfg.dim;
+    let vocab = VOCAB_SIZE as usize;
+    // Give each token its own embedding axis so the residual stream is dominated by the last
+    // input token, then wire the head to chain 3 -> 4 -> ... -> 8 -> <|im_end|>
+    let mut embeddings = vec![0f32; vocab * dim];
+    for t in 0..vocab {
+        embeddings[t * dim + t] = 10.0;
+    }
+    let mut output = vec![0f32; vocab * dim];
+    for t in 3..=8 {
+        output[(t + 1) * dim + t] = 1.0;
+    }
+    {
+        let vars = varmap.data().lock().unwrap();
+        let set = |name: &str, data: Vec<f32>, shape: &[usize]| {
+            let tensor = Tensor::from_vec(data, shape, &device).unwrap();
+            vars[name].set(&tensor).unwrap();
+        };
+        set("embeddings.weight", embeddings, &[vocab, dim]);
+        set("output.weight", output, &[vocab, dim]);
+        set("norm.weight", vec![1.0; dim], &[dim]);
+    }
+
+    let tokenizer = tiny_tokenizer();
+    let n_codebooks = model.cfg.num_codebooks;
+    let prompt = Tensor::cat(
+        &[
+            Tensor::new(&[[1u32, 2, 3]], &device).unwrap(),
+            Tensor::zeros((n_codebooks, 3), DType::U32, &device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap();
+    let sampling_args = SamplingArgs {
+        temp: 0.1,
+        top_p: 1.0,
+        top_k: 0,
+        ..Default::default()
+    };
+    let generator =
+        InterleavedGenerator::new(&mut model, &tokenizer, &prompt, 30, &sampling_args).unwrap();
+    let events: Vec<GenerationEvent> = generator.map(|e| e.unwrap()).collect();
+
+    let (last, steps) = events.split_last().unwrap();
+    assert!(matches!(last, GenerationEvent::End { finished: true }));
+    let (tokens, deltas): (Vec<u32>, Vec<String>) = steps
+        .iter()
+        .map(|event| match event {
+            GenerationEvent::Text { token, text } => (*token, text.clone()),
+            _ => panic!("Expected only text events, got {:?}", event),
+        })
+        .unzip();
+    assert_eq!(tokens, vec![4, 5, 6, 7, 8]);
+    assert_eq!(deltas, vec!["w4", " w5", " w6", " w7", " w8"]);
+    assert_eq!(deltas.concat(), "w4 w5 w6 w7 w8");
+}
@@ -0,0 +1,105 @@
+use fish_speech_core::text::lexicon::{Lexicon, LexiconEntry, LexiconSpec};
+use fish_speech_core::text::pipeline::{ScriptRules, TextPipeline};
+use std::sync::Arc;
+
+fn lexicon(words: &[(&str, &str)]) -> Lexicon {
+    Lexicon::new(words.iter().map(|&(w, r)| LexiconEntry::new(w, r)))
+}
+
+#[test]
+fn replaces_whole_words_only() {
+    let lexicon = lexicon(&[
+        ("AWS", "A W S"),
+        ("C++", "C plus plus"),
+        (".NET", "dot net"),
+    ]);
+    let cases = [
+        ("Deploy to AWS.", "Deploy to A W S."),
+        ("LAWS and AWSome", "LAWS and AWSome"),
+        ("(AWS)", "(A W S)"),
+        ("C++ or C++17", "C plus plus or C plus plus17"),
+        ("Use .NET now", "Use dot net now"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(lexicon.apply(input), expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn matches_inside_unsegmented_cjk_text() {
+    let lexicon = lexicon(&[
+        ("AWS", "A W S"),
+        ("微软", "wei ruan"),
+        ("東京", "とうきょう"),
+    ]);
+    let cases = [
+        ("我们用AWS部署", "我们用A W S部署"),
+        ("微软公司发布了新产品", "wei ruan公司发布了新产品"),
+        ("東京都に住んでいる", "とうきょう都に住んでいる"),
+        // Latin text around the entry still needs a boundary
+        ("用LAWS部署", "用LAWS部署"),
+    ];
+    for (input, expected) in cases {
+        assert_eq!(lexicon.apply(input), expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn case_follows_the_word_unless_given() {
+    let mut entries = vec![
+        LexiconEntry::new("SQL", "sequel"),
+        LexiconEntry::new("nginx", "engine x"),
+    ];
+    entries.push(LexiconEntry {
+        case_sensitive: Some(false),
+        ..LexiconEntry::new("GIF", "jif")
+    });
+    let lexicon = Lexicon::new(entries);
+    assert_eq!(lexicon.apply("SQL, not sql"), "sequel, not sql");
+    assert_eq!(lexicon.apply("Nginx and NGINX"), "engine x and engine x");
+    assert_eq!(lexicon.apply("a gif"), "a jif");
+}
+
+#[test]
+fn longest_match_wins_in_one_pass() {
+    let lexicon = lexicon(&[
+        ("AWS", "A W S"),
+        ("AWS Lambda", "A W S lambda"),
+        ("lambda", "LAMBDA"),
+    ]);
+    // The replacement isn't rewritten by the "lambda" entry
+    assert_eq!(lexicon.apply("AWS Lambda on AWS"), "A W S lambda on A W S");
+
+    let overridden = lexicon.extend([LexiconEntry::new("AWS", "amazon web services")]);
+    assert_eq!(overridden.entries().len(), 3);
+    assert_eq!(overridden.apply("AWS"), "amazon web services");
+}
+
+#[test]
+fn spec_accepts_maps_and_entry_lists() {
+    let words: LexiconSpec = serde_json::from_str(r#"{"SQL": "sequel"}"#).unwrap();
+    let entries: LexiconSpec = serde_json::from_str(
+        r#"[{"word": "SQL", "replacement": "sequel", "case_sensitive": false}]"#,
+    )
+    .unwrap();
+    assert_eq!(
+        Lexicon::new(words.into_entries()).apply("SQL sql"),
+        "sequel sql"
+    );
+    assert_eq!(
+        Lexicon::new(entries.into_entries()).apply("SQL sql"),
+        "sequel sequel"
+    );
+}
+
+#[test]
+fn lexicon_runs_before_number_reading() {
+    let pipeline = TextPipeline::default()
+        .insert_before("symbols", Arc::new(ScriptRules::default()))
+        .prepend(Arc::new(lexicon(&[("3M", "three em")])));
+    assert_eq!(pipeline.step_names()[..2], ["lexicon", "numbers"]);
+    assert_eq!(
+        pipeline.normalize("3M sold 3 units."),
+        "three em sold three units."
+    );
+}
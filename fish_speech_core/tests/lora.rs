@@ -0,0 +1,71 @@
+use candle_core::{DType, Device, Module, Tensor};
+use candle_nn::VarBuilder;
+use fish_speech_core::lm::lora::{LoraAdapter, LoraLinear};
+use fish_speech_core::lm::quantized::LinearLoader;
+use std::collections::HashMap;
+
+mod common;
+use common::max_abs_diff;
+
+#[test]
+fn unmerged_lora_matches_merged_weights() {
+    let device = Device::Cpu;
+    let dir = tempfile::tempdir().unwrap();
+
+    let weight = Tensor::randn(0f32, 0.02, (32, 16), &device).unwrap();
+    let lora_a = Tensor::randn(0f32, 0.1, (4, 16), &device).unwrap();
+    let lora_b = Tensor::randn(0f32, 0.1, (32, 4), &device).unwrap();
+    let adapter_path = dir.path().join("style.safetensors");
+    candle_core::safetensors::save(
+        &HashMap::from([
+            (
+                "base_model.model.layers.0.feed_forward.w1.lora_A.weight".to_string(),
+                lora_a,
+            ),
+            (
+                "base_model.model.layers.0.feed_forward.w1.lora_B.weight".to_string(),
+                lora_b,
+            ),
+        ]),
+        &adapter_path,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.path().join("style.json"),
+        r#"{"r": 4, "lora_alpha": 8}"#,
+    )
+    .unwrap();
+
+    let adapter = LoraAdapter::load(&adapter_path, "style", DType::F32, &device).unwrap();
+    assert_eq!(adapter.weights["layers.0.feed_forward.w1"].scale, 2.0);
+
+    let mut base = HashMap::from([("layers.0.feed_forward.w1.weight".to_string(), weight)]);
+    let vb = VarBuilder::from_tensors(base.clone(), DType::F32, &device);
+    let mut layer = LoraLinear::load(
+        &LinearLoader::full_precision(),
+        &vb.pp("layers.0.feed_forward"),
+        (32, 16),
+        "w1",
+    )
+    .unwrap();
+    assert!(layer.add_adapter(&adapter));
+
+    assert_eq!(adapter.merge_into(&mut base).unwrap(), 1);
+    let merged = &base["layers.0.feed_forward.w1.weight"];
+
+    let xs = Tensor::randn(0f32, 1.0, (1, 3, 16), &device).unwrap();
+    layer.set_active(Some("style"));
+    let expected = xs.broadcast_matmul(&merged.t().unwrap()).unwrap();
+    assert!(max_abs_diff(&layer.forward(&xs).unwrap(), &expected) < 1e-5);
+
+    layer.set_active(None);
+    let base_out = xs
+        .broadcast_matmul(
+            &vb.get((32, 16), "layers.0.feed_forward.w1.weight")
+                .unwrap()
+                .t()
+                .unwrap(),
+        )
+        .unwrap();
+    assert!(max_abs_diff(&layer.forward(&xs).unwrap(), &base_out) < 1e-6);
+}
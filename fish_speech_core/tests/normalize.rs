@@ -0,0 +1,255 @@
+use fish_speech_core::text::normalize::{
+    chinese_number, japanese_number, normalize_chinese, normalize_english, normalize_japanese,
+    normalize_text, normalize_text_in, ordinal_words, year_words,
+};
+
+fn check(cases: &[(&str, &str)]) {
+    for (input, expected) in cases {
+        assert_eq!(normalize_english(input), *expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn cardinals_and_decimals() {
+    check(&[
+        ("I have 3 cats", "I have three cats"),
+        ("0", "zero"),
+        (
+            "1,234,567 stars",
+            "one million two hundred thirty-four thousand five hundred sixty-seven stars",
+        ),
+        ("It's 3.14 or so", "It's three point one four or so"),
+        ("0.05", "zero point zero five"),
+        ("down to -5 now", "down to minus five now"),
+        ("COVID-19", "COVID-nineteen"),
+        ("mp3 files", "mp3 files"),
+        (
+            "in 1999 and 2005",
+            "in nineteen ninety-nine and two thousand five",
+        ),
+        ("from 2024", "from twenty twenty-four"),
+        ("1905", "nineteen oh five"),
+        ("5000 people", "five thousand people"),
+    ]);
+}
+
+#[test]
+fn ordinals() {
+    check(&[
+        ("2nd place", "second place"),
+        ("the 21st century", "the twenty-first century"),
+        ("3RD", "third"),
+        ("100th", "one hundredth"),
+        ("12th and 40th", "twelfth and fortieth"),
+    ]);
+}
+
+#[test]
+fn currency() {
+    check(&[
+        ("$3.5M", "three point five million dollars"),
+        ("$1", "one dollar"),
+        ("$12.50", "twelve dollars and fifty cents"),
+        ("$0.99", "ninety-nine cents"),
+        ("$1,000", "one thousand dollars"),
+        ("£2.01", "two pounds and one penny"),
+        ("€20 bn", "twenty billion euros"),
+        ("¥500", "five hundred yen"),
+    ]);
+}
+
+#[test]
+fn percentages_and_units() {
+    check(&[
+        ("15% off", "fifteen percent off"),
+        ("2.5 %", "two point five percent"),
+        ("10km", "ten kilometers"),
+        ("1 km", "one kilometer"),
+        ("5 lbs", "five pounds"),
+        ("100 km/h", "one hundred kilometers per hour"),
+        ("30°C", "thirty degrees Celsius"),
+        ("500g of flour", "five hundred grams of flour"),
+        ("5 m", "five m"),
+    ]);
+}
+
+#[test]
+fn dates_and_times() {
+    check(&[
+        ("on 12/05/2024", "on December fifth, twenty twenty-four"),
+        ("2024-03-01", "March first, twenty twenty-four"),
+        ("13/45/2024", "thirteen/forty-five/twenty twenty-four"),
+        ("at 3:30pm,", "at three thirty p m,"),
+        ("9:05 a.m.", "nine oh five a m"),
+        ("at 7:00 PM", "at seven p m"),
+        ("15:00", "fifteen hundred"),
+    ]);
+}
+
+#[test]
+fn phone_numbers() {
+    check(&[
+        (
+            "Call (555) 123-4567",
+            "Call five five five, one two three, four five six seven",
+        ),
+        (
+            "+1 800-555-0199",
+            "eight zero zero, five five five, zero one nine nine",
+        ),
+    ]);
+}
+
+#[test]
+fn markers_are_left_alone() {
+    check(&[
+        (
+            "Wait 2 seconds [pause:1.5s] then 3",
+            "Wait two seconds [pause:1.5s] then three",
+        ),
+        ("[break=500ms]", "[break=500ms]"),
+    ]);
+}
+
+#[test]
+fn the_request_example() {
+    check(&[(
+        "$3.5M on 12/05/2024 at 3:30pm, 15% off, 2nd place, 10km",
+        "three point five million dollars on December fifth, twenty twenty-four at three thirty p m, \
+         fifteen percent off, second place, ten kilometers",
+    )]);
+}
+
+#[test]
+fn word_helpers() {
+    for (n, expected) in [
+        (1, "first"),
+        (8, "eighth"),
+        (20, "twentieth"),
+        (1_000_002, "one million second"),
+    ] {
+        assert_eq!(ordinal_words(n), expected);
+    }
+    for (n, expected) in [
+        (1100, "eleven hundred"),
+        (2000, "two thousand"),
+        (2010, "twenty ten"),
+        (1066, "ten sixty-six"),
+    ] {
+        assert_eq!(year_words(n), expected);
+    }
+}
+
+#[test]
+fn chinese_numbers() {
+    for (n, expected) in [
+        (0, "零"),
+        (10, "十"),
+        (15, "十五"),
+        (110, "一百一十"),
+        (200, "两百"),
+        (1005, "一千零五"),
+        (1200, "一千二百"),
+        (2222, "两千二百二十二"),
+        (12_000, "一万两千"),
+        (20_000, "两万"),
+        (220_000, "二十二万"),
+        (10_010, "一万零一十"),
+        (100_000_005, "一亿零五"),
+        (300_000_000, "三亿"),
+    ] {
+        assert_eq!(chinese_number(n), expected, "n = {}", n);
+    }
+}
+
+#[test]
+fn chinese_text() {
+    for (input, expected) in [
+        ("2024年3月5日", "二零二四年三月五日"),
+        ("2024-03-05", "二零二四年三月五日"),
+        ("我有2个苹果和12本书", "我有两个苹果和十二本书"),
+        ("第2名", "第二名"),
+        ("增长了15%", "增长了百分之十五"),
+        ("价格是¥3.5", "价格是三点五元"),
+        ("$100", "一百美元"),
+        ("卖了100元", "卖了一百元"),
+        ("跑了10km的路", "跑了十公里的路"),
+        ("气温30℃", "气温摄氏三十度"),
+        ("下午3:30开会", "下午三点三十分开会"),
+        ("8:05", "八点零五分"),
+        ("电话13812345678", "电话一三八一二三四五六七八"),
+        ("零下-5度", "零下负五度"),
+        ("等[pause:1.5s]一下", "等[pause:1.5s]一下"),
+    ] {
+        assert_eq!(normalize_chinese(input), expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn japanese_numbers() {
+    for (n, expected) in [
+        (0, "零"),
+        (10, "十"),
+        (15, "十五"),
+        (100, "百"),
+        (1005, "千五"),
+        (1500, "千五百"),
+        (2024, "二千二十四"),
+        (10_000, "一万"),
+        (20_305, "二万三百五"),
+        (100_000_000, "一億"),
+    ] {
+        assert_eq!(japanese_number(n), expected, "n = {}", n);
+    }
+}
+
+#[test]
+fn japanese_text() {
+    for (input, expected) in [
+        ("2024年3月5日", "二千二十四年三月五日"),
+        ("2024/03/05", "二千二十四年三月五日"),
+        ("りんごが3個あります", "りんごが三個あります"),
+        ("15%オフ", "十五パーセントオフ"),
+        ("¥1,500です", "千五百円です"),
+        ("10kmを走った", "十キロメートルを走った"),
+        ("午後3:30に", "午後三時三十分に"),
+        ("7:00", "七時"),
+        ("3.5倍", "三点五倍"),
+        ("090-1234-5678", "〇九〇の一二三四の五六七八"),
+    ] {
+        assert_eq!(normalize_japanese(input), expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn each_sentence_is_read_in_its_own_script() {
+    assert_eq!(
+        normalize_text("I have 2 cats. 我有2只猫。猫が2匹います。"),
+        "I have two cats. 我有两只猫。猫が二匹います。"
+    );
+    assert_eq!(normalize_text("안녕 3"), "안녕 3");
+}
+
+#[test]
+fn latin_text_is_only_read_as_english() {
+    for (language, input, expected) in [
+        ("en", "I am 23.", "I am twenty-three."),
+        ("de", "Ich bin 23.", "Ich bin 23."),
+        ("de", "Es kostet 3,5 Euro.", "Es kostet 3,5 Euro."),
+        ("fr", "J'ai 23 ans.", "J'ai 23 ans."),
+        (
+            "es",
+            "Tengo 23 años. 我有2只猫。",
+            "Tengo 23 años. 我有两只猫。",
+        ),
+        ("pt", "I have 2 cats.", "I have two cats."),
+    ] {
+        assert_eq!(
+            normalize_text_in(input, language),
+            expected,
+            "{}: {:?}",
+            language,
+            input
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use fish_speech_core::text::pause::{
+    ChunkBreak, MAX_PAUSE_MS, PauseConfig, preprocess_text_with_breaks,
+};
+
+#[test]
+fn paragraphs_and_markers_become_breaks() {
+    let text = "The first paragraph ends here.\n\nThe second one starts. [pause:1.5s] After the pause \
+                [break] and a plain break.";
+    let chunks = preprocess_text_with_breaks(text);
+    let breaks: Vec<ChunkBreak> = chunks.iter().map(|c| c.after).collect();
+    assert_eq!(
+        breaks,
+        vec![
+            ChunkBreak::Paragraph,
+            ChunkBreak::Marker(Some(1500.0)),
+            ChunkBreak::Marker(None),
+            ChunkBreak::None,
+        ]
+    );
+    assert_eq!(chunks[0].text, "The first paragraph ends here.");
+    assert!(chunks.iter().all(|c| !c.text.contains('[')));
+}
+
+#[test]
+fn adjacent_breaks_merge_and_trailing_ones_are_dropped() {
+    let chunks =
+        preprocess_text_with_breaks("[pause] One.\n\n[pause:200ms][pause:300ms]\n\nTwo.\n\n");
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(chunks[0].after, ChunkBreak::Marker(Some(500.0)));
+    assert_eq!(chunks[1].after, ChunkBreak::None);
+}
+
+#[test]
+fn pause_lengths_follow_the_config() {
+    let pauses = PauseConfig {
+        sentence_ms: 100.0,
+        paragraph_ms: 500.0,
+        marker_ms: 250.0,
+    };
+    assert_eq!(pauses.pause_samples(ChunkBreak::None, 1000), 0);
+    assert_eq!(pauses.pause_samples(ChunkBreak::Sentence, 1000), 100);
+    assert_eq!(pauses.pause_samples(ChunkBreak::Paragraph, 1000), 500);
+    assert_eq!(pauses.pause_samples(ChunkBreak::Marker(None), 1000), 250);
+    assert_eq!(
+        pauses.pause_samples(ChunkBreak::Marker(Some(40.0)), 1000),
+        40
+    );
+}
+
+#[test]
+fn pause_lengths_are_capped() {
+    let pauses = PauseConfig::default();
+    let max = (MAX_PAUSE_MS as usize) * 16;
+    assert_eq!(
+        pauses.pause_samples(ChunkBreak::Marker(Some(99_999_999_000.0)), 16000),
+        max
+    );
+    assert_eq!(
+        pauses.pause_samples(ChunkBreak::Marker(Some(f32::INFINITY)), 16000),
+        max
+    );
+}
@@ -0,0 +1,113 @@
+use fish_speech_core::text::pipeline::{
+    RegexRule, RegexRules, ScriptRules, TextNormalizer, TextPipeline,
+};
+use std::sync::Arc;
+
+fn rules(rules: &[(&str, &str)]) -> RegexRules {
+    let rules: Vec<RegexRule> = rules
+        .iter()
+        .map(|&(pattern, replacement)| RegexRule {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        })
+        .collect();
+    RegexRules::new(&rules).unwrap()
+}
+
+#[test]
+fn default_pipeline_cleans_deterministically() {
+    let pipeline = TextPipeline::default();
+    assert_eq!(
+        pipeline.step_names(),
+        vec!["symbols", "emoji", "punctuation"]
+    );
+    for _ in 0..8 {
+        assert_eq!(
+            pipeline.normalize("  Il dit « bonjour » ici 👋... “Oui”  "),
+            "Il dit \"bonjour\" ici . \"Oui\""
+        );
+    }
+}
+
+#[test]
+fn steps_are_chosen_by_name() {
+    let pipeline = TextPipeline::from_steps(&["numbers", "punctuation"]).unwrap();
+    assert_eq!(
+        pipeline.normalize("I have 2  cats 👋"),
+        "I have two cats 👋"
+    );
+    assert!(TextPipeline::from_steps(&["symbols", "nope"]).is_err());
+    assert_eq!(
+        TextPipeline::default().without("emoji").normalize("Hi 👋"),
+        "Hi 👋"
+    );
+}
+
+#[test]
+fn rules_run_before_punctuation_cleanup() {
+    let pipeline = TextPipeline::default().insert_before(
+        "punctuation",
+        Arc::new(rules(&[
+            (r"(?i)\bASAP\b", "as soon as possible"),
+            (r"v(\d)", "version $1"),
+        ])),
+    );
+    assert_eq!(
+        pipeline.step_names(),
+        vec!["symbols", "emoji", "rules", "punctuation"]
+    );
+    assert_eq!(
+        pipeline.normalize("Ship v2 asap ,, please"),
+        "Ship version 2 as soon as possible , please"
+    );
+
+    let numbers_first =
+        TextPipeline::default().insert_before("symbols", Arc::new(ScriptRules::default()));
+    assert_eq!(numbers_first.step_names()[0], "numbers");
+    // No such step: appended
+    let appended =
+        TextPipeline::new().insert_before("punctuation", Arc::new(ScriptRules::default()));
+    assert_eq!(appended.step_names(), vec!["numbers"]);
+}
+
+#[test]
+fn rules_load_from_json() {
+    let path = std::env::temp_dir().join(format!("text-rules-{}.json", std::process::id()));
+    std::fs::write(
+        &path,
+        r#"[{"pattern": "\\bGIF\\b", "replacement": "jif"}, {"pattern": "jif", "replacement": "gif"}]"#,
+    )
+    .unwrap();
+    let loaded = RegexRules::from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // In order: the second rule sees the first one's output
+    assert_eq!(loaded.normalize("A GIF"), "A gif");
+
+    let invalid = RegexRule {
+        pattern: "(".to_string(),
+        replacement: String::new(),
+    };
+    assert!(RegexRules::new(&[invalid]).is_err());
+}
+
+struct Shout;
+
+impl TextNormalizer for Shout {
+    fn name(&self) -> &str {
+        "shout"
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        text.to_uppercase()
+    }
+}
+
+#[test]
+fn custom_steps_extend_the_pipeline() {
+    let pipeline = TextPipeline::default().with(Shout);
+    assert_eq!(pipeline.normalize("hey  you"), "HEY YOU");
+    assert_eq!(
+        pipeline.without("shout").step_names(),
+        vec!["symbols", "emoji", "punctuation"]
+    );
+}
@@ -0,0 +1,43 @@
+use candle_core::{DType, Device, Module, Tensor};
+use fish_speech_core::config::WhichQuantization;
+use fish_speech_core::lm::dual_ar::BaseModelArgs;
+use fish_speech_core::lm::quantized::{LinearLayer, load_gguf, quantize_safetensors};
+use std::collections::HashMap;
+
+mod common;
+use common::max_abs_diff;
+
+#[test]
+fn quantized_gguf_roundtrip_keeps_linear_outputs_close() {
+    let device = Device::Cpu;
+    let cfg = BaseModelArgs::fish_speech_1_2();
+    let dir = tempfile::tempdir().unwrap();
+
+    let weight = Tensor::randn(0f32, 0.02, (64, 64), &device).unwrap();
+    let norm = Tensor::ones(64, DType::F32, &device).unwrap();
+    let tensors = HashMap::from([
+        ("layers.0.attention.wo.weight".to_string(), weight.clone()),
+        ("layers.0.attention_norm.weight".to_string(), norm),
+    ]);
+    let safetensors_path = dir.path().join("model.safetensors");
+    candle_core::safetensors::save(&tensors, &safetensors_path).unwrap();
+
+    let gguf_path = dir.path().join(WhichQuantization::Q8_0.gguf_file_name());
+    let (n_quantized, n_dense) =
+        quantize_safetensors(&safetensors_path, &gguf_path, &cfg, WhichQuantization::Q8_0).unwrap();
+    assert_eq!((n_quantized, n_dense), (1, 1));
+
+    let (vb, loader) = load_gguf(&gguf_path, DType::F32, &device).unwrap();
+    assert_eq!(loader.quantization(), Some(WhichQuantization::Q8_0));
+    assert!(vb.contains_tensor("layers.0.attention_norm.weight"));
+    let layer = loader
+        .load(&vb.pp("layers.0.attention"), (64, 64), "wo.weight")
+        .unwrap();
+    assert!(matches!(layer, LinearLayer::Quantized(_)));
+
+    let xs = Tensor::randn(0f32, 1.0, (1, 3, 64), &device).unwrap();
+    let expected = xs.broadcast_matmul(&weight.t().unwrap()).unwrap();
+    let actual = layer.forward(&xs).unwrap();
+    let max_err = max_abs_diff(&expected, &actual);
+    assert!(max_err < 0.05, "max abs error {}", max_err);
+}
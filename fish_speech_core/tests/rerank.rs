@@ -0,0 +1,95 @@
+mod common;
+
+use candle_core::{DType, Device, Tensor};
+use common::tiny_model;
+use fish_speech_core::lm::generate::rerank::{
+    RerankConfig, codebook_entropy, duration_penalty, generate_best_of, score_candidate,
+};
+use fish_speech_core::lm::sampling::SamplingArgs;
+
+#[test]
+fn codebook_entropy_flags_stuck_codes() {
+    let device = Device::Cpu;
+    let stuck = Tensor::new(&[[7u32; 16], [3u32; 16]], &device).unwrap();
+    assert_eq!(codebook_entropy(&stuck).unwrap(), 0.0);
+
+    let varied: Vec<u32> = (0..16).collect();
+    let varied = Tensor::new(vec![varied.clone(), varied], &device).unwrap();
+    assert!((codebook_entropy(&varied).unwrap() - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn duration_penalty_only_outside_plausible_rate() {
+    let config = RerankConfig::default();
+    let frame_rate = 21.535;
+    let text = "The quick brown fox jumped over the lazy dog";
+    // ~12 syllables: 3 seconds is a normal pace
+    assert_eq!(
+        duration_penalty(text, (3.0 * frame_rate) as usize, frame_rate, &config),
+        0.0
+    );
+    // Rambling for 60 seconds or swallowing it in a quarter second are both implausible
+    assert!(duration_penalty(text, (60.0 * frame_rate) as usize, frame_rate, &config) > 1.0);
+    assert!(duration_penalty(text, (0.25 * frame_rate) as usize, frame_rate, &config) > 1.0);
+}
+
+#[test]
+fn best_of_returns_the_top_scoring_candidate() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let n_codebooks = model.cfg.num_codebooks;
+    let prompt = Tensor::cat(
+        &[
+            Tensor::new(&[[1u32, 2, 3, 4, 5]], &device).unwrap(),
+            Tensor::zeros((n_codebooks, 5), DType::U32, &device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap();
+    let text = "The quick brown fox";
+    let sampling_args = SamplingArgs {
+        temp: 1.0,
+        top_p: 1.0,
+        top_k: 0,
+        ..Default::default()
+    };
+    // Strict enough that the penalties, not just the likelihood, decide the winner
+    let config = RerankConfig {
+        min_entropy: 0.9,
+        ..Default::default()
+    };
+    let max_new_tokens = 20;
+
+    let best = generate_best_of(
+        &mut model,
+        &prompt,
+        text,
+        3,
+        max_new_tokens,
+        &sampling_args,
+        &config,
+    )
+    .unwrap();
+    assert_eq!(model.curr_kv_size().unwrap(), 0);
+    assert_eq!(best.candidates.len(), 3);
+    assert!(best.winner < 3);
+
+    // The returned codes are the winning take, and rescore to its recorded score
+    let winner = &best.candidates[best.winner];
+    let rescored = score_candidate(&mut model, &prompt, &best.codes, text, &config).unwrap();
+    assert_eq!(rescored.scores.n_frames(), winner.scores.n_frames());
+    assert!((rescored.total - winner.total).abs() < 1e-4);
+
+    // It has the highest score among takes that stopped on their own, if any did
+    let hit_limit = |n_frames: usize| n_frames >= max_new_tokens;
+    let any_finished = best
+        .candidates
+        .iter()
+        .any(|c| !hit_limit(c.scores.n_frames()));
+    assert!(!any_finished || !hit_limit(winner.scores.n_frames()));
+    for candidate in &best.candidates {
+        if !any_finished || !hit_limit(candidate.scores.n_frames()) {
+            assert!(candidate.total <= winner.total);
+        }
+    }
+}
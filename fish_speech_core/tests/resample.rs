@@ -28,3 +28,27 @@ fn resample_linear_2x_on_simple_signal() {
         assert!(approx_eq(*a, *b, 1e-5), "{} vs {}", a, b);
     }
 }
+
+#[test]
+fn normalize_loudness_matches_rms_without_clipping() {
+    use fish_speech_core::audio::functional::normalize_loudness;
+
+    let rms = |pcm: &[f32]| (pcm.iter().map(|s| s * s).sum::<f32>() / pcm.len() as f32).sqrt();
+    let quiet: Vec<f32> = (0..1000).map(|i| 0.01 * (i as f32 * 0.1).sin()).collect();
+    let mut louder = quiet.clone();
+    normalize_loudness(&mut louder, 0.05, 8.0);
+    assert!(approx_eq(rms(&louder), 0.05, 1e-3), "{}", rms(&louder));
+
+    // Gain is capped, and peaks stay below full scale
+    let mut capped = quiet.clone();
+    normalize_loudness(&mut capped, 0.05, 2.0);
+    assert!(approx_eq(rms(&capped), 2.0 * rms(&quiet), 1e-4));
+    let mut spiky = vec![0.001f32; 1000];
+    spiky[10] = 0.5;
+    normalize_loudness(&mut spiky, 0.1, 100.0);
+    assert!(spiky.iter().all(|s| s.abs() <= 0.99));
+
+    let mut silence = vec![0.0f32; 10];
+    normalize_loudness(&mut silence, 0.1, 4.0);
+    assert!(silence.iter().all(|&s| s == 0.0));
+}
@@ -0,0 +1,165 @@
+use fish_speech_core::lm::sampling::processors::{
+    FrequencyPenalty, LogitsChain, LogitsProcessor, MinP, PresencePenalty, RepetitionPenalty,
+    TokenHistory, TopK, TypicalP,
+};
+use fish_speech_core::lm::sampling::{HeadOverrides, HeadSamplingArgs, SamplingArgs};
+use rand::SeedableRng;
+
+fn kept(logits: &[f32]) -> Vec<usize> {
+    logits
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.is_finite())
+        .map(|(i, _)| i)
+        .collect()
+}
+
+#[test]
+fn filters() {
+    let empty = TokenHistory::new(0);
+    // Probabilities proportional to 8, 4, 2, 1
+    let base: Vec<f32> = [8f32, 4.0, 2.0, 1.0].iter().map(|p| p.ln()).collect();
+
+    let mut logits = base.clone();
+    MinP(0.3).process(&mut logits, &empty);
+    assert_eq!(kept(&logits), vec![0, 1]);
+
+    let mut logits = base.clone();
+    TopK(3).process(&mut logits, &empty);
+    assert_eq!(kept(&logits), vec![0, 1, 2]);
+
+    // Uniform distribution: every token is equally typical, so mass decides
+    let mut logits = vec![0.0f32; 4];
+    TypicalP(0.5).process(&mut logits, &empty);
+    assert_eq!(kept(&logits).len(), 2);
+
+    // A spike plus a flat tail: the spike is atypical and goes first
+    let mut logits = vec![10.0f32, 0.0, 0.0, 0.0, 0.0];
+    TypicalP(0.0001).process(&mut logits, &empty);
+    assert_eq!(kept(&logits).len(), 1);
+}
+
+#[test]
+fn penalties_respect_window() {
+    let mut history = TokenHistory::new(2);
+    for token in [1, 1, 2] {
+        history.push(token);
+    }
+    // Window holds [2, 1]: one occurrence each, token 0 never seen
+    let mut logits = vec![1.0f32; 3];
+    FrequencyPenalty(0.5).process(&mut logits, &history);
+    assert_eq!(logits, vec![1.0, 0.5, 0.5]);
+
+    let mut logits = vec![1.0f32; 3];
+    PresencePenalty(0.25).process(&mut logits, &history);
+    assert_eq!(logits, vec![1.0, 0.75, 0.75]);
+
+    let mut logits = vec![2.0f32; 3];
+    RepetitionPenalty(2.0).process(&mut logits, &history);
+    assert_eq!(logits, vec![2.0, 1.0, 1.0]);
+
+    history.push(2);
+    let counts: Vec<(u32, u32)> = history.counts().collect();
+    assert_eq!(counts, vec![(2, 2)]);
+}
+
+#[test]
+fn greedy_chain_applies_penalties() {
+    let args = HeadSamplingArgs {
+        temp: 0.0,
+        top_p: 1.0,
+        top_k: 0,
+        min_p: 0.0,
+        typical_p: 1.0,
+        repetition_penalty: 1.0,
+        frequency_penalty: 0.0,
+        presence_penalty: 5.0,
+        rep_pen_window: 4,
+    };
+    let chain = LogitsChain::from_args(&args);
+    let mut history = TokenHistory::new(args.rep_pen_window);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    let logits = vec![3.0f32, 2.0, 0.0];
+
+    assert_eq!(chain.sample(logits.clone(), &history, &mut rng), 0);
+    history.push(0);
+    assert_eq!(chain.sample(logits, &history, &mut rng), 1);
+}
+
+#[test]
+fn sampling_stays_within_filter() {
+    let args = SamplingArgs {
+        temp: 1.0,
+        top_p: 1.0,
+        top_k: 2,
+        ..Default::default()
+    };
+    let chain = LogitsChain::from_args(&args.fast_head(16, false));
+    let history = TokenHistory::new(16);
+    let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+    for _ in 0..100 {
+        let token = chain.sample(vec![1.0, 0.9, 0.8, -5.0], &history, &mut rng);
+        assert!(token < 2);
+    }
+}
+
+#[test]
+fn head_defaults() {
+    let args = SamplingArgs {
+        frequency_penalty: 0.3,
+        rep_pen_window: Some(8),
+        ..Default::default()
+    };
+    let slow = args.slow_head(16);
+    assert_eq!(slow.repetition_penalty, 1.0);
+    assert_eq!(slow.frequency_penalty, 0.0);
+    assert_eq!(slow.rep_pen_window, 8);
+
+    let fast = args.fast_head(16, false);
+    assert_eq!(fast.repetition_penalty, args.repetition_penalty);
+    assert_eq!(fast.frequency_penalty, 0.3);
+    assert_eq!(args.fast_head(16, true).frequency_penalty, 0.0);
+
+    let overridden = SamplingArgs {
+        fast: HeadOverrides {
+            temp: Some(0.5),
+            ..Default::default()
+        },
+        ..args
+    };
+    assert_eq!(overridden.fast_head(16, true).temp, 0.5);
+    assert_eq!(overridden.fast_head(16, true).top_k, fast.top_k);
+    assert_eq!(overridden.slow_head(16).temp, overridden.temp);
+}
+
+#[test]
+fn temperature_bump_reaches_head_overrides() {
+    let mut args = SamplingArgs {
+        temp: 0.7,
+        slow: HeadOverrides {
+            temp: Some(0.4),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    args.bump_temperature(0.1);
+    assert!((args.slow_head(16).temp - 0.5).abs() < 1e-9);
+    assert!((args.fast_head(16, false).temp - 0.8).abs() < 1e-9);
+    assert_eq!(args.fast.temp, None);
+}
+
+#[test]
+fn head_overrides_parse_and_merge() {
+    let cli: HeadOverrides = "temp=0.5, top_k=30".parse().unwrap();
+    assert_eq!(cli.temp, Some(0.5));
+    assert_eq!(cli.top_k, Some(30));
+    assert!("top_k=2.5".parse::<HeadOverrides>().is_err());
+    assert!("warmth=1".parse::<HeadOverrides>().is_err());
+    assert!("".parse::<HeadOverrides>().unwrap().is_empty());
+
+    let request: HeadOverrides = serde_json::from_str(r#"{"temp": 0.9}"#).unwrap();
+    let merged = cli.merge(&request);
+    assert_eq!(merged.temp, Some(0.9));
+    assert_eq!(merged.top_k, Some(30));
+    assert!(serde_json::from_str::<HeadOverrides>(r#"{"warmth": 1}"#).is_err());
+}
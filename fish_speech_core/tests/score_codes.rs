@@ -0,0 +1,76 @@
+mod common;
+
+use candle_core::{D, DType, Device, IndexOp, Module, Tensor};
+use candle_nn::ops::log_softmax;
+use common::tiny_model;
+use fish_speech_core::lm::generate::score_codes;
+
+fn log_prob(logits: &Tensor, idx: usize) -> f32 {
+    log_softmax(&logits.flatten_all().unwrap(), D::Minus1)
+        .unwrap()
+        .i(idx)
+        .unwrap()
+        .to_scalar::<f32>()
+        .unwrap()
+}
+
+#[test]
+fn teacher_forced_scores_match_incremental_decoding() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let n_codebooks = model.cfg.num_codebooks;
+
+    let text = Tensor::new(&[1u32, 2, 3, 4, 5], &device).unwrap();
+    let prompt = Tensor::cat(
+        &[
+            text.unsqueeze(0).unwrap(),
+            Tensor::zeros((n_codebooks, 5), DType::U32, &device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap();
+    let codes: Vec<Vec<u32>> = vec![vec![3, 0, 15, 7], vec![1, 2, 3, 4], vec![9, 8, 7, 6]];
+    let codes_tensor = Tensor::new(codes.clone(), &device).unwrap();
+
+    let scores = score_codes(&mut model, &prompt, &codes_tensor).unwrap();
+    assert_eq!(model.curr_kv_size().unwrap(), 0);
+
+    // Reference: step through the frames with the KV-cached generation path
+    let (mut logits, mut hidden) = model
+        .forward_generate(&prompt.unsqueeze(0).unwrap(), 0, None)
+        .unwrap();
+    for t in 0..4 {
+        // Constrained slow head is [<|im_end|>, <|semantic:0|>, ..]
+        let constrained = logits.i((.., .., 9..26)).unwrap();
+        let expected = log_prob(&constrained, 1 + codes[0][t] as usize);
+        assert!((scores.semantic[t] - expected).abs() < 1e-4);
+
+        model.clear_fast_layer_caches();
+        let mut x = hidden.clone();
+        for (cb, row) in codes.iter().enumerate() {
+            let fast_logits = model.forward_generate_fast(&x, cb).unwrap();
+            let expected = log_prob(&fast_logits, row[t] as usize);
+            assert!((scores.codebooks[cb][t] - expected).abs() < 1e-4);
+            let code = Tensor::new(&[row[t]], &device).unwrap();
+            x = model
+                .fast_embeddings
+                .forward(&code)
+                .unwrap()
+                .unsqueeze(0)
+                .unwrap();
+        }
+
+        let mut frame = vec![10 + codes[0][t]];
+        frame.extend(codes.iter().map(|row| row[t]));
+        let frame = Tensor::new(frame, &device)
+            .unwrap()
+            .reshape((1, n_codebooks + 1, 1))
+            .unwrap();
+        (logits, hidden) = model.forward_generate(&frame, 5 + t, None).unwrap();
+    }
+    let expected_eos = log_prob(&logits.i((.., .., 9..26)).unwrap(), 0);
+    assert!((scores.eos - expected_eos).abs() < 1e-4);
+
+    let frame_totals = scores.frame_totals();
+    assert!((frame_totals.iter().sum::<f32>() - scores.total()).abs() < 1e-3);
+}
@@ -0,0 +1,122 @@
+use fish_speech_core::text::chunking::Chunker;
+use fish_speech_core::text::clean::preprocess_text;
+use fish_speech_core::text::pause::preprocess_text_with_breaks_chunked;
+use fish_speech_core::text::pipeline::TextPipeline;
+use fish_speech_core::text::segment::Segmenter;
+use fish_speech_core::text::ssml::{chunk_ssml, parse_ssml};
+
+fn check(segmenter: &Segmenter, cases: &[(&str, &[&str])]) {
+    for (input, expected) in cases {
+        assert_eq!(segmenter.split(input), *expected, "input: {:?}", input);
+    }
+}
+
+#[test]
+fn english_sentences() {
+    check(
+        &Segmenter::default(),
+        &[
+            (
+                "Hello there. General Kenobi!",
+                &["Hello there.", "General Kenobi!"],
+            ),
+            (
+                "Dr. Smith paid 3.50 for the U.S. version",
+                &["Dr. Smith paid 3.50 for the U.S. version"],
+            ),
+            (
+                "J. R. R. Tolkien wrote it. Then he died.",
+                &["J. R. R. Tolkien wrote it.", "Then he died."],
+            ),
+            (
+                "Apples, pears, e.g. fruit. Yes.",
+                &["Apples, pears, e.g. fruit.", "Yes."],
+            ),
+            ("Really?! Yes.", &["Really?!", "Yes."]),
+            (
+                "Wait... what? No... Stop.",
+                &["Wait... what?", "No...", "Stop."],
+            ),
+            (
+                "He said \"Go.\" Then he left.",
+                &["He said \"Go.\"", "Then he left."],
+            ),
+            ("(It was late.) We slept.", &["(It was late.)", "We slept."]),
+            ("\"Why?\" she asked.", &["\"Why?\" she asked."]),
+            (
+                "See https://example.com/a?b=1. Or mail j.doe@example.com. Thanks!",
+                &[
+                    "See https://example.com/a?b=1.",
+                    "Or mail j.doe@example.com.",
+                    "Thanks!",
+                ],
+            ),
+            ("It costs $3. Cheap", &["It costs $3.", "Cheap"]),
+            ("No terminator", &["No terminator"]),
+            ("   ", &[]),
+        ],
+    );
+}
+
+#[test]
+fn cjk_sentences() {
+    check(
+        &Segmenter::default(),
+        &[
+            ("你好。世界！好吗？", &["你好。", "世界！", "好吗？"]),
+            ("「行くよ。」と言った。", &["「行くよ。」", "と言った。"]),
+            // The symbol map turns 。 into ., which is then followed directly by CJK
+            (
+                "这是中文.これは日本語です.",
+                &["这是中文.", "これは日本語です."],
+            ),
+        ],
+    );
+}
+
+#[test]
+fn abbreviations_per_language() {
+    let german = Segmenter::for_language("de").unwrap();
+    check(
+        &german,
+        &[("Obst, z.B. Äpfel. Gut.", &["Obst, z.B. Äpfel.", "Gut."])],
+    );
+    // English doesn't know "z.B", but it's initials-shaped anyway; "bzw" isn't
+    check(&Segmenter::default(), &[("A bzw. B", &["A bzw.", "B"])]);
+    check(&german, &[("A bzw. B", &["A bzw. B"])]);
+
+    let custom = Segmenter::default().with_abbreviations(["Approx.", "Capt"]);
+    check(
+        &custom,
+        &[("Capt. Hook. Approx. Yes.", &["Capt. Hook.", "Approx. Yes."])],
+    );
+    assert!(Segmenter::for_language("xx").is_none());
+}
+
+#[test]
+fn preprocess_keeps_abbreviations_and_decimals_in_the_first_chunk() {
+    let chunks = preprocess_text("Dr. Smith paid 3.50 for the U.S. version. It was fine.");
+    assert_eq!(chunks[0], "Dr. Smith paid 3.50 for the U.S. version.");
+}
+
+#[test]
+fn breaks_and_ssml_chunking_use_the_given_segmenter() {
+    let german = Segmenter::for_language("de").unwrap();
+    let chunks = preprocess_text_with_breaks_chunked(
+        "Obst bzw. Gemüse. Gut.\n\nDanke.",
+        &TextPipeline::default(),
+        &german,
+        &Chunker::Characters,
+    );
+    let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(texts[0], "Obst bzw. Gemüse.");
+
+    let segments = parse_ssml("<speak>Obst bzw. Gemüse. Gut.</speak>").unwrap();
+    let runs = chunk_ssml(
+        &segments,
+        &TextPipeline::default(),
+        &german,
+        &Chunker::Characters,
+    );
+    assert_eq!(runs[0].chunks[0].text, "Obst bzw. Gemüse.");
+}
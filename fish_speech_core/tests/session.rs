@@ -0,0 +1,184 @@
+mod common;
+
+use candle_core::{DType, Device, Tensor};
+use common::tiny_model;
+use fish_speech_core::lm::dual_ar::DualARTransformer;
+use fish_speech_core::lm::generate::{
+    CancellationToken, FinishReason, GenerationSession, NoProgress, ProgressReporter, SessionEvent,
+    generate_with_progress,
+};
+use fish_speech_core::lm::sampling::{EosControl, SamplingArgs};
+
+fn prompt(model: &DualARTransformer, device: &Device) -> Tensor {
+    Tensor::cat(
+        &[
+            Tensor::new(&[[1u32, 2, 3, 4, 5]], device).unwrap(),
+            Tensor::zeros((model.cfg.num_codebooks, 5), DType::U32, device).unwrap(),
+        ],
+        0,
+    )
+    .unwrap()
+}
+
+#[test]
+fn session_events() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let prompt = prompt(&model, &device);
+    let session = GenerationSession::new(
+        &mut model,
+        &prompt,
+        12,
+        &SamplingArgs::default(),
+        CancellationToken::new(),
+    )
+    .unwrap();
+    let events: Vec<SessionEvent> = session.map(|e| e.unwrap()).collect();
+
+    let SessionEvent::PrefillDone {
+        prompt_tokens,
+        cached_tokens,
+        ..
+    } = &events[0]
+    else {
+        panic!("Sessions start with prefill");
+    };
+    assert_eq!((*prompt_tokens, *cached_tokens), (5, 0));
+    let frames: Vec<usize> = events
+        .iter()
+        .filter_map(|e| match e {
+            SessionEvent::Frame { index, token, .. } => {
+                assert!(token.is_audio || *index == 0);
+                Some(*index)
+            }
+            _ => None,
+        })
+        .collect();
+    assert_eq!(frames, (0..frames.len()).collect::<Vec<_>>());
+    let Some(SessionEvent::Finished {
+        reason, n_frames, ..
+    }) = events.last()
+    else {
+        panic!("Sessions end with Finished");
+    };
+    assert_eq!(*n_frames, frames.len());
+    match reason {
+        FinishReason::TokenLimit => assert_eq!(*n_frames, 12),
+        FinishReason::EndOfSpeech => assert!(*n_frames < 12),
+        FinishReason::Cancelled => panic!("Nothing cancelled the session"),
+    }
+}
+
+/// Cancels as soon as prefill is done
+struct CancelAfterPrefill {
+    cancel: CancellationToken,
+    events: Vec<&'static str>,
+}
+
+impl ProgressReporter for CancelAfterPrefill {
+    fn on_event(&mut self, event: &SessionEvent) {
+        self.events.push(match event {
+            SessionEvent::PrefillDone { .. } => {
+                self.cancel.cancel();
+                "prefill"
+            }
+            SessionEvent::Frame { .. } => "frame",
+            SessionEvent::Finished { .. } => "finished",
+        });
+    }
+}
+
+#[test]
+fn cancellation() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let prompt = prompt(&model, &device);
+
+    let cancel = CancellationToken::new();
+    let mut reporter = CancelAfterPrefill {
+        cancel: cancel.clone(),
+        events: Vec::new(),
+    };
+    // Speech can't end on its own before the token limit, so only cancellation stops it early
+    let sampling_args = SamplingArgs {
+        eos: EosControl {
+            min_frames: 12,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let output = generate_with_progress(
+        &mut model,
+        &prompt,
+        12,
+        &sampling_args,
+        true,
+        &mut reporter,
+        cancel,
+    )
+    .unwrap();
+    assert_eq!(output.reason, FinishReason::Cancelled);
+    // The prefill frame is already sampled, so it's still returned
+    assert_eq!(reporter.events, vec!["prefill", "frame", "finished"]);
+    assert_eq!(output.codes.dims(), &[3, 1]);
+    assert_eq!(output.hidden_states.unwrap().dim(0).unwrap(), 1);
+
+    // Cancelled before it starts: nothing to return
+    model.clear_slow_layer_caches();
+    let cancel = CancellationToken::new();
+    cancel.cancel();
+    assert!(
+        generate_with_progress(
+            &mut model,
+            &prompt,
+            12,
+            &SamplingArgs::default(),
+            false,
+            &mut NoProgress,
+            cancel,
+        )
+        .is_err()
+    );
+}
+
+#[test]
+fn captures_attention_for_each_frame() {
+    let device = Device::Cpu;
+    let mut model = tiny_model(&device, 0.5);
+    let prompt = prompt(&model, &device);
+    assert!(model.set_attention_capture(&[2]).is_err());
+
+    model.set_attention_capture(&[0, 1]).unwrap();
+    let output = generate_with_progress(
+        &mut model,
+        &prompt,
+        8,
+        &SamplingArgs::default(),
+        false,
+        &mut NoProgress,
+        CancellationToken::new(),
+    )
+    .unwrap();
+    let n_frames = output.codes.dim(1).unwrap();
+    assert_eq!(output.attention.len(), n_frames);
+    for (i, row) in output.attention.iter().enumerate() {
+        // Each step sees the prompt and every frame fed back before it
+        assert_eq!(row.len(), 5 + i);
+        assert!((row.iter().sum::<f32>() - 1.0).abs() < 1e-4);
+    }
+
+    model.set_attention_capture(&[]).unwrap();
+    assert!(!model.is_capturing_attention());
+    model.clear_slow_layer_caches();
+    let output = generate_with_progress(
+        &mut model,
+        &prompt,
+        4,
+        &SamplingArgs::default(),
+        false,
+        &mut NoProgress,
+        CancellationToken::new(),
+    )
+    .unwrap();
+    assert!(output.attention.is_empty());
+}
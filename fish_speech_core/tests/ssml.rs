@@ -0,0 +1,87 @@
+use fish_speech_core::text::chunking::Chunker;
+use fish_speech_core::text::pause::ChunkBreak;
+use fish_speech_core::text::pipeline::TextPipeline;
+use fish_speech_core::text::segment::Segmenter;
+use fish_speech_core::text::ssml::{SsmlSegment, chunk_ssml, is_ssml, parse_ssml};
+
+#[test]
+fn parses_breaks_substitutions_and_voices() {
+    let ssml = r#"<?xml version="1.0"?>
+<speak>
+  <p>Call <say-as interpret-as="characters">NASA</say-as> at
+  <say-as interpret-as="cardinal">1,204</say-as>.</p>
+  <break time="1.5s"/>
+  <voice name="alice">On <say-as interpret-as="date" format="mdy">03/15/2024</say-as>,
+  read <sub alias="World Wide Web Consortium">W3C</sub>.</voice>
+  <break strength="weak"/>Back again.
+</speak>"#;
+    assert!(is_ssml(ssml));
+    assert!(!is_ssml("Just <b>text</b>"));
+
+    let segments = parse_ssml(ssml).unwrap();
+    let text: String = segments
+        .iter()
+        .filter_map(|s| match s {
+            SsmlSegment::Text(t) => Some(t.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert!(text.contains("N A S A"));
+    assert!(text.contains("one thousand two hundred four"));
+    assert!(text.contains("March 15, 2024"));
+    assert!(text.contains("World Wide Web Consortium"));
+    assert!(!text.contains("W3C"));
+    assert!(segments.contains(&SsmlSegment::Break(ChunkBreak::Paragraph)));
+    assert!(segments.contains(&SsmlSegment::Break(ChunkBreak::Marker(Some(1500.0)))));
+    assert!(segments.contains(&SsmlSegment::Voice(Some("alice".to_string()))));
+    assert!(segments.contains(&SsmlSegment::Voice(None)));
+
+    let runs = chunk_ssml(
+        &segments,
+        &TextPipeline::default(),
+        &Segmenter::default(),
+        &Chunker::default(),
+    );
+    let voices: Vec<Option<&str>> = runs.iter().map(|r| r.voice.as_deref()).collect();
+    assert_eq!(voices, vec![None, Some("alice"), None]);
+    // The paragraph and the explicit break merge into one marker after the first run
+    assert_eq!(
+        runs[0].chunks.last().unwrap().after,
+        ChunkBreak::Marker(Some(1500.0))
+    );
+    assert_eq!(
+        runs[1].chunks.last().unwrap().after,
+        ChunkBreak::Marker(Some(250.0))
+    );
+    assert_eq!(runs[2].chunks.last().unwrap().after, ChunkBreak::None);
+    assert_eq!(runs[2].chunks[0].text, "Back again.");
+}
+
+#[test]
+fn sentences_are_terminated() {
+    let segments = parse_ssml("<speak><s>First one</s><s>Second one!</s></speak>").unwrap();
+    let runs = chunk_ssml(
+        &segments,
+        &TextPipeline::default(),
+        &Segmenter::default(),
+        &Chunker::default(),
+    );
+    assert_eq!(runs.len(), 1);
+    let text: Vec<&str> = runs[0].chunks.iter().map(|c| c.text.as_str()).collect();
+    assert_eq!(text.join(" "), "First one. Second one!");
+}
+
+#[test]
+fn rejects_malformed_documents() {
+    assert!(parse_ssml("<speak>unclosed").is_err());
+    assert!(parse_ssml("<p>no speak root</p>").is_err());
+    assert!(parse_ssml(r#"<speak><break time="soon"/></speak>"#).is_err());
+    assert!(parse_ssml(r#"<speak><voice>nameless</voice></speak>"#).is_err());
+}
+
+#[test]
+fn rejects_breaks_over_the_maximum() {
+    assert!(parse_ssml(r#"<speak>One.<break time="10s"/>Two.</speak>"#).is_ok());
+    assert!(parse_ssml(r#"<speak>One.<break time="10001ms"/>Two.</speak>"#).is_err());
+    assert!(parse_ssml(r#"<speak>One.<break time="1e30s"/>Two.</speak>"#).is_err());
+}
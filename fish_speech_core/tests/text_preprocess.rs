@@ -7,3 +7,40 @@ fn preprocess_mixed_languages_splits_sentences() {
     assert!(!chunks.is_empty());
     assert!(chunks.len() >= 3);
 }
+
+#[test]
+fn preprocess_mixed_scripts_keeps_each_sentence_apart() {
+    let input = "Hello world. Привет, мир! Γεια σου κόσμε; مرحبا بالعالم؟ नमस्ते दुनिया। שלום עולם.";
+    let chunks = preprocess_text(input);
+    assert_eq!(
+        chunks,
+        [
+            "Hello world.",
+            "Привет, мир!",
+            "Γεια σου κόσμε;",
+            "مرحبا بالعالم؟",
+            "नमस्ते दुनिया।",
+            "שלום עולם.",
+        ]
+    );
+}
+
+#[test]
+fn preprocess_splits_thai_on_spaces() {
+    let chunks = preprocess_text("สวัสดีครับ วันนี้อากาศดีมาก Hello there.");
+    assert_eq!(chunks, ["สวัสดีครับ", "วันนี้อากาศดีมาก Hello there."]);
+}
+
+#[test]
+fn preprocess_splits_long_arabic_on_arabic_commas() {
+    let clause = "هذه جملة طويلة جدا تحتوي على كلمات كثيرة";
+    let input = format!("مرحبا. {}", [clause; 12].join("، "));
+    let chunks = preprocess_text(&input);
+    assert_eq!(chunks[0], "مرحبا.");
+    assert!(chunks.len() > 2);
+    assert!(
+        chunks[1..]
+            .iter()
+            .all(|chunk| chunk.ends_with('،') || chunk == clause)
+    );
+}
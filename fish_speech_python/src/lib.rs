@@ -1,15 +1,18 @@
 mod codec;
 mod lm;
+mod text;
 mod utils;
 
 use pyo3::prelude::*;
 
 use codec::FireflyCodec;
 use lm::LM;
+use text::TextPipeline;
 
 #[pymodule]
 fn fish_speech(_py: Python, m: Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<FireflyCodec>()?;
     m.add_class::<LM>()?;
+    m.add_class::<TextPipeline>()?;
     Ok(())
 }
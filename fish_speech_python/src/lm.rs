@@ -1,31 +1,42 @@
 use candle_core::{D, DType, Device, Tensor};
 use candle_nn::VarBuilder;
 use fish_speech_core::config::{WhichLM, WhichModel};
-use fish_speech_core::lm::generate::generate_blocking;
+use fish_speech_core::lm::generate::{generate_blocking, lm_codes_to_codec, score_codes};
+use fish_speech_core::lm::lora::LoraAdapter;
+use fish_speech_core::lm::quantized::{LinearLoader, load_gguf};
+use fish_speech_core::lm::sampling::{HeadOverrides, SamplingArgs};
 use fish_speech_core::lm::{BaseModelArgs, DualARTransformer, dual_ar::TokenConfig};
-use fish_speech_core::text::prompt::PromptEncoder;
+use fish_speech_core::text::prompt::{ChunkHistory, PromptEncoder};
 use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
 use tokenizers::Tokenizer;
 
-use super::utils::{PyRes, get_device, get_version, wrap_err};
+use super::utils::{PyRes, get_device, get_quantization, get_version, wrap_err};
 
 #[pyclass]
 pub struct LM {
     model: DualARTransformer,
     device: Device,
+    dtype: DType,
     tokenizer: Tokenizer,
     cfg: BaseModelArgs,
 }
 
 #[pymethods]
 impl LM {
-    #[pyo3(signature = (dir, version="1.5", device="cpu", dtype="f32"))]
+    #[pyo3(signature = (dir, version="1.5", device="cpu", dtype="f32", quantization=None))]
     #[new]
-    fn new(dir: std::path::PathBuf, version: &str, device: &str, dtype: &str) -> PyResult<Self> {
+    fn new(
+        dir: std::path::PathBuf,
+        version: &str,
+        device: &str,
+        dtype: &str,
+        quantization: Option<&str>,
+    ) -> PyResult<Self> {
         let model_type = get_version(version)
             .map_err(|_| PyException::new_err(format!("Unsupported model version: {}", version)))?;
+        let quantization = quantization.map(get_quantization).transpose().w()?;
 
         let dtype = match (dtype, device) {
             ("bf16", "cuda") | ("bf16", "metal") => DType::BF16,
@@ -37,10 +48,18 @@ impl LM {
                 )));
             }
         };
+        // Quantized matmuls run on F32 activations
+        let dtype = match quantization {
+            Some(_) => DType::F32,
+            None => dtype,
+        };
         let device = get_device(device)?;
-        let vb = match model_type {
-            WhichModel::Fish1_2 => {
+        let gguf_path = quantization.map(|q| dir.join(q.gguf_file_name()));
+        let (vb, linear_loader) = match (model_type, gguf_path) {
+            (_, Some(gguf_path)) if gguf_path.exists() => load_gguf(&gguf_path, dtype, &device),
+            (WhichModel::Fish1_2, _) => {
                 VarBuilder::from_pth(dir.join("model.safetensors"), dtype, &device)
+                    .map(|vb| (vb, LinearLoader::quantize_on_load(quantization)))
             }
             _ => unsafe {
                 VarBuilder::from_mmaped_safetensors(
@@ -48,7 +67,8 @@ impl LM {
                     dtype,
                     &device,
                 )
-            },
+            }
+            .map(|vb| (vb, LinearLoader::quantize_on_load(quantization))),
         }
         .map_err(wrap_err)?;
         let cfg = BaseModelArgs::from_file(dir.join("config.json")).map_err(wrap_err)?;
@@ -58,17 +78,33 @@ impl LM {
         let lm_type = WhichLM::from_model(model_type);
         let token_config = TokenConfig::new(lm_type, &tokenizer, &cfg)
             .map_err(|e| PyException::new_err(format!("Failed to create token config: {}", e)))?;
-        let model = DualARTransformer::load(&vb, &cfg, &token_config, lm_type).map_err(wrap_err)?;
+        let model = DualARTransformer::load_with(&vb, &cfg, &token_config, lm_type, &linear_loader)
+            .map_err(wrap_err)?;
 
         Ok(Self {
             model,
             device,
+            dtype,
             tokenizer,
             cfg,
         })
     }
 
-    #[pyo3(signature = (input, sysprompt= Some("Speak out the provided text".into()), speaker_prompt=None, temp=0.7, top_p=0.9, top_k=50, repetition_penalty=1.2))]
+    /// Loads a LoRA adapter (safetensors) that `__call__` can select with `adapter=name`
+    fn load_adapter(&mut self, name: &str, path: std::path::PathBuf) -> PyResult<()> {
+        let adapter = LoraAdapter::load(&path, name, self.dtype, &self.device).map_err(wrap_err)?;
+        self.model.add_lora_adapter(&adapter).map_err(wrap_err)
+    }
+
+    fn remove_adapter(&mut self, name: &str) {
+        self.model.remove_lora_adapter(name);
+    }
+
+    /// Generates up to `max_new_tokens` frames of codes for each input chunk. Arguments after
+    /// `repetition_penalty` are keyword-only; `slow` and `fast` are dicts of sampling settings for
+    /// the semantic and codebook heads alone.
+    #[pyo3(signature = (input, sysprompt= Some("Speak out the provided text".into()), speaker_prompt=None, temp=0.7, top_p=0.9, top_k=50, repetition_penalty=1.2, *, adapter=None, context_turns=0, max_new_tokens=1024, min_p=0.0, typical_p=1.0, frequency_penalty=0.0, presence_penalty=0.0, rep_pen_window=None, slow=None, fast=None))]
+    #[allow(clippy::too_many_arguments)]
     fn __call__(
         &mut self,
         input: Bound<'_, PyAny>,
@@ -78,26 +114,39 @@ impl LM {
         top_p: f64,
         top_k: usize,
         repetition_penalty: f32,
+        adapter: Option<&str>,
+        context_turns: usize,
+        max_new_tokens: usize,
+        min_p: f64,
+        typical_p: f64,
+        frequency_penalty: f32,
+        presence_penalty: f32,
+        rep_pen_window: Option<usize>,
+        slow: Option<&Bound<'_, PyDict>>,
+        fast: Option<&Bound<'_, PyDict>>,
     ) -> PyResult<Py<PyAny>> {
+        let sampling_args = SamplingArgs {
+            temp,
+            top_p,
+            top_k,
+            repetition_penalty,
+            min_p,
+            typical_p,
+            frequency_penalty,
+            presence_penalty,
+            rep_pen_window,
+            slow: head_overrides(slow)?,
+            fast: head_overrides(fast)?,
+            ..Default::default()
+        };
+        self.model.set_lora_adapter(adapter).map_err(wrap_err)?;
         self.model.clear_slow_layer_caches();
 
         let py = input.py();
         let input_vec: Vec<String> = input.extract()?;
-        let maybe_speaker_prompt = match speaker_prompt {
-            Some(codes) => {
-                let codes = codes.as_array();
-                let codes_shape = codes.shape().to_vec();
-                let codes = codes
-                    .to_slice()
-                    .ok_or(PyException::new_err("input data is not contiguous"))?;
-
-                let codes =
-                    Tensor::from_slice(codes, codes_shape, &self.device).map_err(wrap_err)?;
-                let codes = codes.squeeze(0).map_err(wrap_err)?;
-                Some(codes)
-            }
-            None => None,
-        };
+        let maybe_speaker_prompt = speaker_prompt
+            .map(|codes| self.codes_to_tensor(codes))
+            .transpose()?;
 
         let prompt_encoder = PromptEncoder::new(
             &self.tokenizer,
@@ -108,27 +157,40 @@ impl LM {
 
         // TODO: Implement voice encoding logic
         let (num_conditioning_tokens, prompts) = prompt_encoder
-            .encode_sequence(input_vec, sysprompt, maybe_speaker_prompt, true)
+            .encode_sequence(input_vec.clone(), sysprompt, maybe_speaker_prompt, true)
             .map_err(wrap_err)?;
+        let mut history = ChunkHistory::new(context_turns);
 
         let mut outputs: Vec<Tensor> = Vec::with_capacity(prompts.len());
-        let sampling_args = fish_speech_core::lm::sampling::SamplingArgs {
-            temp,
-            top_p,
-            top_k,
-            repetition_penalty,
-        };
-        for prompt in prompts {
+        for (i, (prompt, text)) in prompts.iter().zip(&input_vec).enumerate() {
+            let n_cached = if i == 0 { 0 } else { num_conditioning_tokens };
+            let prompt = history
+                .prepend(prompt, n_cached, self.cfg.max_seq_len, max_new_tokens)
+                .map_err(wrap_err)?;
             let x = py
                 .detach(|| {
-                    generate_blocking(&mut self.model, &prompt, 1024, &sampling_args, false)
+                    generate_blocking(
+                        &mut self.model,
+                        &prompt,
+                        max_new_tokens,
+                        &sampling_args,
+                        false,
+                    )
                 })
                 .w()?;
 
-            outputs.push(x);
             self.model
                 .clear_slow_caches_until(num_conditioning_tokens)
                 .map_err(wrap_err)?;
+            if history.is_enabled() {
+                let codec_codes =
+                    lm_codes_to_codec(&x, &self.model.model_type).map_err(wrap_err)?;
+                let turn = prompt_encoder
+                    .encode_turn(text, &codec_codes)
+                    .map_err(wrap_err)?;
+                history.push(turn);
+            }
+            outputs.push(x);
         }
         let output = Tensor::cat(&outputs, D::Minus1)
             .map_err(wrap_err)?
@@ -143,6 +205,46 @@ impl LM {
         Ok(codes.into_any().unbind())
     }
 
+    /// Teacher-forced log-likelihoods of `codes` (1, num_codebooks, T) as speech for `input`
+    #[pyo3(signature = (input, codes, sysprompt= Some("Speak out the provided text".into()), speaker_prompt=None, adapter=None))]
+    fn score(
+        &mut self,
+        py: Python<'_>,
+        input: String,
+        codes: numpy::PyReadonlyArray3<u32>,
+        sysprompt: Option<String>,
+        speaker_prompt: Option<numpy::PyReadonlyArray3<u32>>,
+        adapter: Option<&str>,
+    ) -> PyResult<Py<PyAny>> {
+        self.model.set_lora_adapter(adapter).map_err(wrap_err)?;
+        self.model.clear_slow_layer_caches();
+        let codes = self.codes_to_tensor(codes)?;
+        let maybe_speaker_prompt = speaker_prompt
+            .map(|codes| self.codes_to_tensor(codes))
+            .transpose()?;
+        let prompt_encoder = PromptEncoder::new(
+            &self.tokenizer,
+            &self.device,
+            self.cfg.num_codebooks,
+            self.model.model_type,
+        );
+        let (_, prompts) = prompt_encoder
+            .encode_sequence(vec![input], sysprompt, maybe_speaker_prompt, false)
+            .map_err(wrap_err)?;
+        let scores = py
+            .detach(|| score_codes(&mut self.model, &prompts[0], &codes))
+            .w()?;
+
+        let out = PyDict::new(py);
+        out.set_item("total", scores.total())?;
+        out.set_item("mean_per_frame", scores.mean_per_frame())?;
+        out.set_item("eos", scores.eos)?;
+        out.set_item("frames", scores.frame_totals())?;
+        out.set_item("semantic", &scores.semantic)?;
+        out.set_item("codebooks", &scores.codebooks)?;
+        Ok(out.into_any().unbind())
+    }
+
     fn create_speaker_prompt(&self, input: Vec<Bound<'_, PyDict>>) -> PyResult<Py<PyAny>> {
         let prompt_encoder = PromptEncoder::new(
             &self.tokenizer,
@@ -197,4 +299,27 @@ impl LM {
     }
 }
 
-impl LM {}
+/// Per-head sampling settings from a dict such as `{"temp": 0.9}`
+fn head_overrides(settings: Option<&Bound<'_, PyDict>>) -> PyResult<HeadOverrides> {
+    let mut overrides = HeadOverrides::default();
+    for (name, value) in settings.into_iter().flat_map(|dict| dict.iter()) {
+        overrides
+            .set(&name.extract::<String>()?, value.extract()?)
+            .w()?;
+    }
+    Ok(overrides)
+}
+
+impl LM {
+    /// (1, num_codebooks, T) numpy codes to a (num_codebooks, T) tensor
+    fn codes_to_tensor(&self, codes: numpy::PyReadonlyArray3<u32>) -> PyResult<Tensor> {
+        let codes = codes.as_array();
+        let codes_shape = codes.shape().to_vec();
+        let codes = codes
+            .to_slice()
+            .ok_or(PyException::new_err("input data is not contiguous"))?;
+
+        let codes = Tensor::from_slice(codes, codes_shape, &self.device).map_err(wrap_err)?;
+        codes.squeeze(0).map_err(wrap_err)
+    }
+}
@@ -0,0 +1,139 @@
+use fish_speech_core::text::chunking::Chunker;
+use fish_speech_core::text::clean::preprocess_text_chunked;
+use fish_speech_core::text::lexicon::{Lexicon, LexiconEntry, LexiconSpec};
+use fish_speech_core::text::pipeline::{RegexRule, RegexRules, ScriptRules};
+use fish_speech_core::text::segment::Segmenter;
+use pyo3::prelude::*;
+use std::sync::Arc;
+
+use super::utils::PyRes;
+
+/// Text normalization steps applied before chunking
+#[pyclass]
+pub struct TextPipeline {
+    pipeline: fish_speech_core::text::pipeline::TextPipeline,
+    /// Respellings so far, kept as one step
+    lexicon: Lexicon,
+    segmenter: Segmenter,
+    /// Whether number reading is English; see `set_language`
+    language: String,
+}
+
+#[pymethods]
+impl TextPipeline {
+    /// Built-in `steps` in order (symbols, emoji, numbers, punctuation); the default cleanup if
+    /// unset
+    #[pyo3(signature = (steps=None))]
+    #[new]
+    fn new(steps: Option<Vec<String>>) -> PyResult<Self> {
+        let pipeline = match steps {
+            Some(steps) => {
+                fish_speech_core::text::pipeline::TextPipeline::from_steps(&steps).w()?
+            }
+            None => Default::default(),
+        };
+        Ok(Self {
+            pipeline,
+            lexicon: Lexicon::default(),
+            segmenter: Segmenter::default(),
+            language: "en".to_string(),
+        })
+    }
+
+    /// Adds `(pattern, replacement)` regex rules, applied before punctuation cleanup
+    fn add_rules(&mut self, rules: Vec<(String, String)>) -> PyResult<()> {
+        let rules: Vec<RegexRule> = rules
+            .into_iter()
+            .map(|(pattern, replacement)| RegexRule {
+                pattern,
+                replacement,
+            })
+            .collect();
+        self.insert_rules(RegexRules::new(&rules).w()?);
+        Ok(())
+    }
+
+    /// Adds the rules in a JSON file of `{"pattern", "replacement"}` objects
+    fn load_rules(&mut self, path: std::path::PathBuf) -> PyResult<()> {
+        self.insert_rules(RegexRules::from_file(&path).w()?);
+        Ok(())
+    }
+
+    /// Adds `{word: replacement}` respellings, applied before every other step. Words with
+    /// capitals match case exactly; lowercase ones match any case.
+    fn add_lexicon(&mut self, words: std::collections::BTreeMap<String, String>) {
+        self.insert_lexicon(LexiconSpec::Words(words).into_entries());
+    }
+
+    /// Adds the respellings in a JSON lexicon file
+    fn load_lexicon(&mut self, path: std::path::PathBuf) -> PyResult<()> {
+        let lexicon = Lexicon::from_file(&path).w()?;
+        self.insert_lexicon(lexicon.entries().to_vec());
+        Ok(())
+    }
+
+    /// Reads numbers, dates, currency and units as words before the other steps
+    fn add_number_reading(&mut self) {
+        if !self.pipeline.step_names().contains(&"numbers") {
+            self.pipeline = self
+                .pipeline
+                .clone()
+                .insert_before("symbols", Arc::new(ScriptRules::new(&self.language)));
+        }
+    }
+
+    /// Splits sentences around the abbreviations of `language`: en (the default), de, fr or es.
+    /// Replaces abbreviations added so far. Number reading is English only, so it leaves Latin
+    /// text alone for the others.
+    fn set_language(&mut self, language: &str) -> PyResult<()> {
+        self.segmenter = Segmenter::for_language(language).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(format!(
+                "Unknown language {:?}; expected en, de, fr or es",
+                language
+            ))
+        })?;
+        self.pipeline = self.pipeline.clone().with_language(language);
+        self.language = language.to_string();
+        Ok(())
+    }
+
+    /// Abbreviations that don't end a sentence, with or without their final period
+    fn add_abbreviations(&mut self, abbreviations: Vec<String>) {
+        self.segmenter = self.segmenter.clone().with_abbreviations(abbreviations);
+    }
+
+    fn steps(&self) -> Vec<String> {
+        self.pipeline
+            .step_names()
+            .into_iter()
+            .map(String::from)
+            .collect()
+    }
+
+    fn normalize(&self, text: &str) -> String {
+        self.pipeline.normalize(text)
+    }
+
+    /// Normalizes `text` and splits it into chunks for `LM.__call__`
+    fn chunk(&self, text: &str) -> Vec<String> {
+        preprocess_text_chunked(text, &self.pipeline, &self.segmenter, &Chunker::Characters)
+    }
+}
+
+impl TextPipeline {
+    fn insert_lexicon(&mut self, entries: Vec<LexiconEntry>) {
+        self.lexicon = std::mem::take(&mut self.lexicon).extend(entries);
+        self.pipeline = self
+            .pipeline
+            .clone()
+            .without("lexicon")
+            .prepend(Arc::new(self.lexicon.clone()));
+    }
+
+    fn insert_rules(&mut self, rules: RegexRules) {
+        self.pipeline = self
+            .pipeline
+            .clone()
+            .insert_before("punctuation", Arc::new(rules));
+    }
+}
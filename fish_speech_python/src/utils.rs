@@ -1,5 +1,5 @@
 use candle_core::Device;
-use fish_speech_core::config::WhichModel;
+use fish_speech_core::config::{WhichModel, WhichQuantization};
 use pyo3::exceptions::PyException;
 use pyo3::prelude::*;
 
@@ -29,6 +29,11 @@ pub fn get_version(raw_version: &str) -> Result<WhichModel, anyhow::Error> {
     }
 }
 
+pub fn get_quantization(raw_quantization: &str) -> Result<WhichQuantization, anyhow::Error> {
+    WhichQuantization::from_name(raw_quantization)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported quantization: {}", raw_quantization))
+}
+
 pub fn get_device(device: &str) -> Result<Device, PyErr> {
     #[cfg(all(not(feature = "cuda"), not(feature = "metal")))]
     match device {
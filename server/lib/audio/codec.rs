@@ -2,7 +2,7 @@ use crate::audio::mimi;
 use candle_core::{Result, Tensor};
 use fish_speech_core::codec::FireflyCodec;
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, OwnedMutexGuard};
 use tracing::debug;
 
 pub enum Codec {
@@ -30,19 +30,38 @@ impl Codec {
     }
 
     pub async fn decode_batch(&self, semantic_tokens: &Tensor) -> Result<Tensor> {
+        self.lock().await.decode_batch(semantic_tokens)
+    }
+
+    /// Takes Mimi's lock up front, so decoding can then run off the async runtime
+    pub async fn lock(&self) -> LockedCodec {
+        match self {
+            Codec::Mimi(model_mutex) => LockedCodec::Mimi(model_mutex.clone().lock_owned().await),
+            Codec::Firefly(state) => LockedCodec::Firefly(state.clone()),
+        }
+    }
+}
+
+/// A codec ready to decode on any thread, from [`Codec::lock`]
+pub enum LockedCodec {
+    Mimi(OwnedMutexGuard<mimi::Tokenizer>),
+    Firefly(Arc<FireflyCodec>),
+}
+
+impl LockedCodec {
+    pub fn decode_batch(&mut self, semantic_tokens: &Tensor) -> Result<Tensor> {
         let semantic_tokens = if semantic_tokens.rank() == 2 {
             &semantic_tokens.unsqueeze(0)?
         } else {
             semantic_tokens
         };
         match self {
-            Codec::Mimi(model_mutex) => {
-                let mut model = model_mutex.lock().await;
+            LockedCodec::Mimi(model) => {
                 let tokens = model.decode_batch(semantic_tokens);
                 model.reset();
                 tokens
             }
-            Codec::Firefly(state) => state.decode(semantic_tokens),
+            LockedCodec::Firefly(state) => state.decode(semantic_tokens),
         }
     }
 }
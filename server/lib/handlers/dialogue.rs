@@ -0,0 +1,205 @@
+use super::error::AppError;
+use super::speech::{
+    EncodedChunks, GenerationOptions, encode_chunks_within_context, pcm_joiner, select_adapter,
+    spawn_chunk_pipeline, sysprompt_text, with_timings,
+};
+use super::text::TextOptions;
+use crate::state::AppState;
+use anyhow::Context;
+use axum::{Json, body::Body, extract::State, http::StatusCode, response::Response};
+use fish_speech_core::audio::{functional::normalize_loudness, wav::write_pcm_as_wav};
+use fish_speech_core::text::pause::{MAX_PAUSE_MS, preprocess_text_with_breaks_chunked};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+
+/// Loudness each turn is normalized to
+const TARGET_RMS: f32 = 0.08;
+/// Most a turn is amplified or attenuated by loudness normalization
+const MAX_GAIN: f32 = 4.0;
+const DEFAULT_GAP_MS: f32 = 300.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DialogueTurn {
+    /// Voice name, as for `/v1/audio/speech`
+    pub speaker: String,
+    pub text: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DialogueRequest {
+    pub turns: Vec<DialogueTurn>,
+    /// Silence between turns; default 300ms, at most 10s
+    pub gap_ms: Option<f32>,
+    /// `wav` (default), with turn timestamps in the `X-Turn-Timestamps` header, or `json` for
+    /// the WAV and the timestamps as parts of a `multipart/mixed` body
+    pub response_format: Option<String>,
+    pub speaker_prompt: Option<String>,
+    pub adapter: Option<String>,
+    /// Bring every turn to the same loudness; default true
+    pub normalize_loudness: Option<bool>,
+    #[serde(flatten)]
+    pub text: TextOptions,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnTiming {
+    pub turn: usize,
+    /// Seconds from the start of the audio
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Renders a script of speaker turns into one audio file.
+///
+/// Turns are generated grouped by speaker, so each voice's conditioning is prefilled once and
+/// stays cached across its turns, then put back in script order.
+pub async fn generate_dialogue(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<DialogueRequest>,
+) -> Result<Response<Body>, AppError> {
+    if request.turns.is_empty() {
+        return Err(AppError::BadRequest("turns must not be empty".to_string()));
+    }
+    let gap_ms = request.gap_ms.unwrap_or(DEFAULT_GAP_MS);
+    if !(0.0..=MAX_PAUSE_MS).contains(&gap_ms) {
+        return Err(AppError::BadRequest(format!(
+            "gap_ms must be between 0 and {} milliseconds",
+            MAX_PAUSE_MS
+        )));
+    }
+    let json = match request.response_format.as_deref() {
+        None | Some("wav") => false,
+        Some("json") => true,
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "response_format must be \"wav\" or \"json\", not {:?}",
+                other
+            )));
+        }
+    };
+
+    let segmenter = request.text.segmenter(&state)?;
+    let chunker = request.text.chunker(&state)?;
+
+    // Speakers in order of first appearance, each with the turns they speak
+    let mut speakers: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (i, turn) in request.turns.iter().enumerate() {
+        match speakers.iter_mut().find(|(s, _)| *s == turn.speaker) {
+            Some((_, turns)) => turns.push(i),
+            None => speakers.push((&turn.speaker, vec![i])),
+        }
+    }
+
+    let permit = state
+        .concurrency
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| AppError::Message(format!("semaphore closed: {e}")))?;
+    let sysprompt_text = sysprompt_text(&state, request.speaker_prompt.clone());
+    let mut runs: Vec<EncodedChunks> = Vec::with_capacity(speakers.len());
+    // Turn of each generated chunk, in generation order
+    let mut chunk_turns: Vec<usize> = Vec::new();
+    for (speaker, turns) in speakers.iter() {
+        let voice_embedding = match *speaker {
+            "unconditioned" => None,
+            name => Some(
+                state
+                    .lm
+                    .voices
+                    .read()
+                    .await
+                    .get(name)
+                    .ok_or_else(|| AppError::BadRequest(format!("Unknown speaker {:?}", name)))?
+                    .clone(),
+            ),
+        };
+        let pipeline = request.text.pipeline(&state, speaker)?;
+        let mut chunks = Vec::new();
+        let mut input_turns = Vec::new();
+        for &turn in turns {
+            let text = &request.turns[turn].text;
+            let turn_chunks =
+                preprocess_text_with_breaks_chunked(text, &pipeline, &segmenter, &chunker);
+            input_turns.extend(std::iter::repeat_n(turn, turn_chunks.len()));
+            chunks.extend(turn_chunks);
+        }
+        let encoded =
+            encode_chunks_within_context(&state, chunks, sysprompt_text.clone(), voice_embedding)?;
+        chunk_turns.extend(encoded.sources.iter().map(|&source| input_turns[source]));
+        runs.push(encoded);
+    }
+    if chunk_turns.is_empty() {
+        return Err(AppError::BadRequest(
+            "turns contain no speakable text".to_string(),
+        ));
+    }
+    let chunk_pauses: Vec<usize> = runs
+        .iter()
+        .flat_map(|run| run.breaks.iter())
+        .map(|&chunk_break| state.pauses.pause_samples(chunk_break, state.sample_rate))
+        .collect();
+
+    info!(
+        "Rendering {} turns by {} speakers in {} chunks",
+        request.turns.len(),
+        speakers.len(),
+        chunk_turns.len()
+    );
+    // Pauses are applied per turn below, so the options only steer generation
+    let options = GenerationOptions::default();
+    select_adapter(&state, request.adapter.as_deref()).await?;
+    let mut turn_pcm: Vec<Vec<f32>> = vec![Vec::new(); request.turns.len()];
+    let mut joiners: Vec<_> = request.turns.iter().map(|_| pcm_joiner(&state)).collect();
+    let mut chunks = spawn_chunk_pipeline(state.clone(), runs, options, permit);
+    let mut chunk_idx = 0;
+    while let Some(chunk) = chunks.recv().await {
+        let chunk = chunk?;
+        let turn = chunk_turns[chunk_idx];
+        let joiner = &mut joiners[turn];
+        turn_pcm[turn].extend(joiner.push(&chunk.pcm.to_vec1::<f32>()?, chunk.context_samples));
+        // Pauses within a turn; the gap between turns is added below
+        let last_in_turn = chunk_turns.get(chunk_idx + 1) != Some(&turn);
+        if !last_in_turn {
+            turn_pcm[turn].extend(joiner.pause(chunk_pauses[chunk_idx]));
+        }
+        chunk_idx += 1;
+    }
+
+    let normalize = request.normalize_loudness.unwrap_or(true);
+    let gap = (gap_ms * state.sample_rate as f32 / 1000.0).round() as usize;
+    let seconds = |samples: usize| samples as f64 / state.sample_rate as f64;
+    let mut audio = Vec::new();
+    let mut timings = Vec::with_capacity(request.turns.len());
+    for (turn, (mut pcm, mut joiner)) in turn_pcm.into_iter().zip(joiners).enumerate() {
+        pcm.extend(joiner.finish());
+        if normalize {
+            normalize_loudness(&mut pcm, TARGET_RMS, MAX_GAIN);
+        }
+        if turn > 0 {
+            audio.resize(audio.len() + gap, 0.0);
+        }
+        let start = seconds(audio.len());
+        audio.extend(pcm);
+        timings.push(TurnTiming {
+            turn,
+            start,
+            end: seconds(audio.len()),
+        });
+    }
+
+    let mut audio_buf = Vec::new();
+    write_pcm_as_wav(&mut audio_buf, &audio, state.sample_rate)
+        .context("Failed to write PCM as WAV")?;
+    let timestamps = serde_json::to_string(&timings).context("Failed to serialize timestamps")?;
+    let response = Response::builder().status(StatusCode::OK);
+    if json {
+        return with_timings(response, audio_buf, "application/json", timestamps);
+    }
+    Ok(response
+        .header("Content-Type", "audio/wav")
+        .header("X-Turn-Timestamps", timestamps)
+        .body(Body::from(audio_buf))
+        .context("Failed to build WAV response")?)
+}
@@ -29,6 +29,9 @@ pub enum AppError {
 
     #[error("Application error: {0}")]
     Message(String),
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
 }
 
 impl axum::response::IntoResponse for AppError {
@@ -44,6 +47,7 @@ impl axum::response::IntoResponse for AppError {
             AppError::Axum(_) => (StatusCode::INTERNAL_SERVER_ERROR, "axum"),
             AppError::Anyhow(_) => (StatusCode::INTERNAL_SERVER_ERROR, "anyhow"),
             AppError::Message(_) => (StatusCode::INTERNAL_SERVER_ERROR, "message"),
+            AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
             AppError::Multipart(_) => (StatusCode::INTERNAL_SERVER_ERROR, "multipart"),
         };
         let message = self.to_string();
@@ -1,5 +1,8 @@
+pub mod dialogue;
 pub mod encode_speech;
 mod error;
+pub mod score;
 pub mod send_hidden_states;
 pub mod speech;
 pub mod supported_voices;
+pub mod text;
@@ -0,0 +1,111 @@
+use super::error::AppError;
+use super::speech::select_adapter;
+use crate::state::AppState;
+use axum::{Json, extract::State};
+use candle_core::Tensor;
+use fish_speech_core::config::{WhichFishVersion, WhichLM, WhichModel};
+use fish_speech_core::lm::generate::score_codes;
+use fish_speech_core::text::prompt::PromptEncoder;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScoreRequest {
+    pub voice: String,
+    /// Text the codes are supposed to speak; scored as a single chunk
+    pub input: String,
+    /// (num_codebooks, T) codes, as returned by `/v1/audio/encoding`
+    pub codes: Vec<Vec<u32>>,
+    pub speaker_prompt: Option<String>,
+    pub adapter: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScoreResponse {
+    /// Sum over all frames of the semantic and codebook log-likelihoods
+    pub total: f32,
+    pub mean_per_frame: f32,
+    /// Log-likelihood of ending right after the last frame
+    pub eos: f32,
+    /// Per-frame totals
+    pub frames: Vec<f32>,
+    pub semantic: Vec<f32>,
+    /// (num_codebooks, T)
+    pub codebooks: Vec<Vec<f32>>,
+}
+
+pub async fn score_speech(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ScoreRequest>,
+) -> Result<Json<ScoreResponse>, AppError> {
+    let num_codebooks = state.lm.config.num_codebooks;
+    let n_frames = request.codes.first().map(Vec::len).unwrap_or(0);
+    if request.codes.len() != num_codebooks
+        || n_frames == 0
+        || request.codes.iter().any(|row| row.len() != n_frames)
+    {
+        return Err(AppError::BadRequest(format!(
+            "codes must be a non-empty ({}, T) array",
+            num_codebooks
+        )));
+    }
+
+    let _permit = state
+        .concurrency
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| AppError::Message(format!("semaphore closed: {e}")))?;
+    let voice_embedding = match &*request.voice {
+        "unconditioned" => None,
+        _ => Some(
+            state
+                .lm
+                .voices
+                .read()
+                .await
+                .get(&request.voice)
+                .unwrap_or(&state.lm.default_voice)
+                .clone(),
+        ),
+    };
+    let sysprompt_text = request.speaker_prompt.or(match state.model_type {
+        WhichModel::Fish1_5 => Some("Speak out the provided text.".to_string()),
+        _ => None,
+    });
+    let prompt_encoder = PromptEncoder::new(
+        &state.lm.tokenizer,
+        &state.device,
+        num_codebooks,
+        state.lm.model_type,
+    );
+    let (_, prompts) = prompt_encoder.encode_sequence(
+        vec![request.input],
+        sysprompt_text,
+        voice_embedding,
+        false,
+    )?;
+
+    let codes = Tensor::new(request.codes, &state.device)?;
+    // Fish 1.4 and below generate codes offset by one from the codec's
+    let codes = match state.lm.model_type {
+        WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => codes,
+        _ => codes.broadcast_add(&Tensor::ones_like(&codes)?)?,
+    };
+
+    select_adapter(&state, request.adapter.as_deref()).await?;
+    let mut model = state.lm.model.lock().await;
+    model.clear_slow_layer_caches();
+    let scores = score_codes(&mut model, &prompts[0], &codes);
+    model.clear_slow_layer_caches();
+    let scores = scores?;
+
+    Ok(Json(ScoreResponse {
+        total: scores.total(),
+        mean_per_frame: scores.mean_per_frame(),
+        eos: scores.eos,
+        frames: scores.frame_totals(),
+        semantic: scores.semantic,
+        codebooks: scores.codebooks,
+    }))
+}
@@ -1,5 +1,5 @@
 use super::error::AppError;
-use super::speech::{server_lm_generate_blocking, vocode_semantic_tokens};
+use super::speech::{ChunkVocoder, pcm_joiner, select_adapter, server_lm_generate_blocking};
 use crate::state::AppState;
 use anyhow::Context;
 use axum::body::Body;
@@ -23,6 +23,12 @@ pub async fn generate_hidden_states(
     State(state): State<Arc<AppState>>,
     Json(request): Json<GenerateHiddenStatesRequest>,
 ) -> Result<Response<Body>, AppError> {
+    let _permit = state
+        .concurrency
+        .clone()
+        .acquire_owned()
+        .await
+        .map_err(|e| AppError::Message(format!("semaphore closed: {e}")))?;
     let voice_embedding = state
         .lm
         .voices
@@ -46,31 +52,42 @@ pub async fn generate_hidden_states(
         WhichModel::Fish1_5 => Some("Speak out the provided text.".to_string()),
         _ => None,
     };
-    let (n_conditioning_tokens, prompts) =
-        prompt_encoder.encode_sequence(chunks, sysprompt_text, Some(voice_embedding), true)?;
+    let (n_conditioning_tokens, prompts) = prompt_encoder.encode_sequence(
+        chunks.clone(),
+        sysprompt_text,
+        Some(voice_embedding),
+        true,
+    )?;
 
+    // Hidden states are always taken from the base weights
+    select_adapter(&state, None).await?;
     let mut all_hidden_states = Vec::new();
     let mut all_pcm: Vec<f32> = Vec::new();
+    let mut vocoder = ChunkVocoder::default();
+    let mut joiner = pcm_joiner(&state);
 
     // Non-streaming path stays relatively simple
-    for prompt in prompts.iter() {
-        let (semantic_tokens, maybe_hidden) = server_lm_generate_blocking(
+    for (prompt, text) in prompts.iter().zip(chunks.iter()) {
+        let (semantic_tokens, maybe_hidden, _) = server_lm_generate_blocking(
             state.clone(),
             prompt,
+            Some(text),
             &state.lm.default_sampling_args,
             n_conditioning_tokens,
             true,
+            false,
         )
         .await?;
-        let pcm = vocode_semantic_tokens(state.clone(), &semantic_tokens).await?;
+        let (pcm, context_samples) = vocoder.vocode(state.clone(), &semantic_tokens).await?;
         if let Some(hidden) = maybe_hidden {
             // println!("{:?} maybe?", hidden.squeeze(1)?.shape());
             all_hidden_states.push(hidden.squeeze(1)?);
         }
         if request.return_audio {
-            all_pcm.extend(pcm.to_vec1::<f32>()?);
+            all_pcm.extend(joiner.push(&pcm.to_vec1::<f32>()?, context_samples));
         }
     }
+    all_pcm.extend(joiner.finish());
 
     let mut model = state.lm.model.lock().await;
     // Final cache eviction
@@ -1,74 +1,498 @@
 use super::error::AppError;
+use super::text::TextOptions;
 use crate::audio::opus::OpusEncoder;
 use crate::state::AppState;
 use anyhow::{Context, Result};
 use axum::{Json, body::Body, extract::State, http::StatusCode, response::Response};
 use bytes::Bytes;
 use candle_core::{D, IndexOp, Tensor};
-use fish_speech_core::audio::{functional::resample, wav::write_pcm_as_wav};
-use fish_speech_core::config::{WhichFishVersion, WhichLM, WhichModel};
-use fish_speech_core::lm::generate::{generate_blocking_with_hidden, generate_static_batch};
-use fish_speech_core::lm::sampling::SamplingArgs;
-use fish_speech_core::text::{clean::preprocess_text, prompt::PromptEncoder};
+use fish_speech_core::audio::{
+    functional::resample,
+    seam::{PcmJoiner, with_left_context},
+    wav::write_pcm_as_wav,
+};
+use fish_speech_core::config::{WhichLM, WhichModel};
+use fish_speech_core::lm::generate::{
+    CancellationToken, LogProgress, SessionOutput, clamp_to_context,
+    degenerate::{Degeneracy, detect_degenerate},
+    generate_static_batch, generate_with_progress, lm_codes_to_codec,
+    rerank::{RerankConfig, generate_best_of},
+};
+use fish_speech_core::lm::sampling::{EosControl, HeadOverrides, SamplingArgs};
+use fish_speech_core::text::{
+    alignment::{WordAlignment, default_alignment_layers, word_alignment},
+    captions::{ChunkTiming, WordTiming, to_srt, to_vtt},
+    pause::{ChunkBreak, MAX_PAUSE_MS, PauseConfig, TextChunk},
+    prompt::{ChunkHistory, PromptEncoder},
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{Mutex, OwnedSemaphorePermit};
-use tracing::{debug, info};
+use tokio::sync::{Mutex, OwnedSemaphorePermit, mpsc};
+use tracing::{debug, info, warn};
+
+/// What it took to generate one chunk
+#[derive(Debug, Clone, Default)]
+pub struct GenerationReport {
+    pub attempts: usize,
+    /// Issues still present in the returned take
+    pub issues: Vec<Degeneracy>,
+    /// Words of the chunk text and the frames they were spoken over, if alignment was requested
+    pub words: Vec<WordAlignment>,
+}
 
 // Blocking token generation
 pub async fn server_lm_generate_blocking(
     state: Arc<AppState>,
     encoded_input: &Tensor,
+    text: Option<&str>,
     sampling_args: &SamplingArgs,
     n_conditioning_tokens: usize,
     collect_hidden_states: bool,
-) -> Result<(Tensor, Option<Tensor>), anyhow::Error> {
+    align_words: bool,
+) -> Result<(Tensor, Option<Tensor>, GenerationReport), anyhow::Error> {
     let mut model = state.lm.model.lock().await;
-    let (tokens, hidden_states) = generate_blocking_with_hidden(
-        &mut model,
-        encoded_input,
-        state.lm.max_new_tokens,
-        sampling_args,
-        collect_hidden_states,
-        true,
-    )
-    .context("Failed to generate tokens")?;
-
-    let mut tokens = tokens;
-    let mut hidden_states = hidden_states;
-    // It's the caller's responsibility to do final clear
-    model.clear_slow_caches_until(n_conditioning_tokens)?;
-    if tokens.dim(D::Minus1)? == state.lm.max_new_tokens {
-        info!("Failed generation suspected. Rerolling once");
-        let (new_tokens, new_hidden_states) = generate_blocking_with_hidden(
-            &mut model,
-            encoded_input,
-            state.lm.max_new_tokens,
-            sampling_args,
-            collect_hidden_states,
-            true,
-        )
-        .context("Failed to generate tokens")?;
-        if new_tokens.dim(D::Minus1)? != state.lm.max_new_tokens {
-            tokens = new_tokens;
-            hidden_states = new_hidden_states;
-        } else {
-            anyhow::bail!(
-                "Encoded input failed for second time. Bailing: {:?}",
-                encoded_input
+    let chunk_text = text;
+    let capture_layers = match (align_words, state.alignment_layers.is_empty()) {
+        (false, _) => Vec::new(),
+        (true, true) => default_alignment_layers(model.cfg.n_layer),
+        (true, false) => state.alignment_layers.clone(),
+    };
+    // Also turns off capture left on by a request that failed mid-generation
+    model.set_attention_capture(&capture_layers)?;
+    let prompt_pos = model.curr_kv_size()?;
+    let requested_tokens = sampling_args.eos.max_new_tokens(state.lm.max_new_tokens);
+    let max_new_tokens = clamp_to_context(
+        model.cfg.max_seq_len,
+        model.curr_kv_size()?,
+        encoded_input.dim(D::Minus1)?,
+        requested_tokens,
+    )?;
+    // Running out of context isn't the model's fault, so don't retry for it
+    let clamped = max_new_tokens < requested_tokens;
+    // Duration controls override the text-based duration check
+    let text = text.filter(|_| !sampling_args.eos.is_active());
+    let policy = &state.lm.retry_policy;
+    let mut sampling_args = sampling_args.clone();
+    let mut best: Option<(SessionOutput, Vec<Degeneracy>)> = None;
+    let mut attempts = 0;
+
+    for attempt in 0..=policy.max_retries {
+        if attempt > 0 {
+            sampling_args.bump_temperature(policy.temperature_bump);
+            info!(
+                "Retrying degenerate chunk ({} of {}) at temperature {:.2}",
+                attempt, policy.max_retries, sampling_args.temp
             );
         }
+        let output = state
+            .in_lm_pool(|| {
+                generate_with_progress(
+                    &mut model,
+                    encoded_input,
+                    max_new_tokens,
+                    &sampling_args,
+                    collect_hidden_states,
+                    &mut LogProgress::new(state.lm.model_type.frame_rate()),
+                    CancellationToken::new(),
+                )
+            })
+            .context("Failed to generate tokens")?;
+        // It's the caller's responsibility to do final clear
+        model.clear_slow_caches_until(n_conditioning_tokens)?;
+        attempts += 1;
+
+        if clamped && output.codes.dim(D::Minus1)? == max_new_tokens {
+            warn!(
+                "Generation ran out of context after {} tokens; audio may be truncated",
+                max_new_tokens
+            );
+        }
+        let issues = detect_degenerate(
+            &output.codes,
+            text,
+            state.lm.model_type.frame_rate(),
+            (!clamped).then_some(max_new_tokens),
+            &state.lm.degenerate_config,
+        )?;
+        if !issues.is_empty() {
+            let summary: Vec<String> = issues.iter().map(|i| i.to_string()).collect();
+            warn!(
+                "Degenerate output on attempt {}: {}",
+                attempt + 1,
+                summary.join("; ")
+            );
+        }
+        // Keep the least broken take; never ending is the worst failure
+        let badness = |issues: &[Degeneracy]| {
+            let hit_limit = issues
+                .iter()
+                .any(|i| matches!(i, Degeneracy::HitTokenLimit { .. }));
+            (hit_limit, issues.len())
+        };
+        let is_better = best
+            .as_ref()
+            .is_none_or(|(_, best_issues)| badness(&issues) < badness(best_issues));
+        let done = issues.is_empty();
+        if is_better {
+            best = Some((output, issues));
+        }
+        if done {
+            break;
+        }
+    }
+
+    if align_words {
+        model.set_attention_capture(&[])?;
+    }
+    let Some((output, issues)) = best else {
+        anyhow::bail!("No generation attempts were made");
+    };
+    if issues
+        .iter()
+        .any(|i| matches!(i, Degeneracy::HitTokenLimit { .. }))
+    {
+        anyhow::bail!(
+            "Generation never finished after {} attempts. Bailing: {:?}",
+            attempts,
+            encoded_input
+        );
+    }
+
+    let tokens = lm_codes_to_codec(&output.codes, &state.lm.model_type)?;
+    let words = match chunk_text {
+        Some(text) if align_words => {
+            let prompt_ids = encoded_input.i((0, ..))?.to_vec1::<u32>()?;
+            // Timings are a nicety; don't fail the audio over them
+            word_alignment(
+                &state.lm.tokenizer,
+                text,
+                &prompt_ids,
+                prompt_pos,
+                &output.attention,
+            )
+            .unwrap_or_else(|e| {
+                warn!("Could not align words: {:#}", e);
+                Vec::new()
+            })
+        }
+        _ => Vec::new(),
+    };
+
+    Ok((
+        tokens,
+        output.hidden_states,
+        GenerationReport {
+            attempts,
+            issues,
+            words,
+        },
+    ))
+}
+
+/// Per-request generation settings
+#[derive(Debug, Clone, Default)]
+pub struct GenerationOptions {
+    pub best_of: Option<usize>,
+    /// EOS steering for each chunk; empty without duration controls
+    pub chunk_eos: Vec<EosControl>,
+    /// Previous chunks kept in context for the next one
+    pub context_turns: usize,
+    /// Silence inserted between chunks
+    pub pauses: PauseConfig,
+    /// Align each chunk's words to its frames (see [`GenerationReport::words`])
+    pub word_timestamps: bool,
+    /// Per-head sampling changes on top of the server's
+    pub slow_sampling: HeadOverrides,
+    pub fast_sampling: HeadOverrides,
+}
+
+impl GenerationOptions {
+    pub fn sampling_args(&self, state: &AppState, chunk_idx: usize) -> SamplingArgs {
+        let mut sampling_args = state.lm.default_sampling_args.clone();
+        sampling_args.slow = sampling_args.slow.merge(&self.slow_sampling);
+        sampling_args.fast = sampling_args.fast.merge(&self.fast_sampling);
+        if let Some(eos) = self.chunk_eos.get(chunk_idx) {
+            sampling_args.eos = eos.clone();
+        }
+        sampling_args
     }
+}
 
-    let tokens = match state.lm.model_type {
-        WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => tokens,
-        _ => tokens
-            .broadcast_sub(&Tensor::ones_like(&tokens).context("Failed to create ones tensor")?)
-            .context("Failed to broadcast subtract")?,
+/// Spreads a request's durations over its chunks in proportion to their length
+fn chunk_eos_controls(
+    texts: &[String],
+    min_duration: Option<f64>,
+    target_duration: Option<f64>,
+    eos_bias: Option<f32>,
+    frame_rate: f64,
+) -> Vec<EosControl> {
+    if min_duration.is_none() && target_duration.is_none() {
+        return Vec::new();
+    }
+    let lengths: Vec<usize> = texts.iter().map(|t| t.chars().count().max(1)).collect();
+    let total: usize = lengths.iter().sum();
+    lengths
+        .iter()
+        .map(|&len| {
+            let share = len as f64 / total as f64;
+            EosControl::from_durations(
+                min_duration.map(|d| d * share),
+                target_duration.map(|d| d * share),
+                frame_rate,
+                eos_bias,
+            )
+        })
+        .collect()
+}
+
+/// Best-of-N generation for one chunk, returning the winning take's codec codes
+async fn generate_chunk_codes_best_of(
+    state: Arc<AppState>,
+    prompt: &Tensor,
+    text: &str,
+    best_of: usize,
+    sampling_args: &SamplingArgs,
+) -> anyhow::Result<(Tensor, GenerationReport)> {
+    let max_new_tokens = sampling_args.eos.max_new_tokens(state.lm.max_new_tokens);
+    let best = {
+        let mut model = state.lm.model.lock().await;
+        state
+            .in_lm_pool(|| {
+                generate_best_of(
+                    &mut model,
+                    prompt,
+                    text,
+                    best_of,
+                    max_new_tokens,
+                    sampling_args,
+                    &RerankConfig::default(),
+                )
+            })
+            .context("Failed to generate best-of candidates")?
+    };
+    let issues = detect_degenerate(
+        &best.codes,
+        Some(text).filter(|_| !sampling_args.eos.is_active()),
+        state.lm.model_type.frame_rate(),
+        Some(max_new_tokens),
+        &state.lm.degenerate_config,
+    )?;
+    let report = GenerationReport {
+        attempts: best_of,
+        issues,
+        words: Vec::new(),
+    };
+    let tokens = lm_codes_to_codec(&best.codes, &state.lm.model_type)?;
+    Ok((tokens, report))
+}
+
+/// Generates codec codes for one chunk, with best-of-N reranking if requested.
+///
+/// Previous chunks in `history` are replayed ahead of the prompt, and this one is added after.
+async fn generate_codes_for_chunk(
+    state: Arc<AppState>,
+    encoded_chunks: &EncodedChunks,
+    chunk_idx: usize,
+    sampling_args: &SamplingArgs,
+    options: &GenerationOptions,
+    history: &mut ChunkHistory,
+) -> anyhow::Result<(Tensor, GenerationReport)> {
+    let text = &encoded_chunks.texts[chunk_idx];
+    let n_conditioning_tokens = encoded_chunks.n_conditioning_tokens;
+    let n_cached = if chunk_idx == 0 {
+        0
+    } else {
+        n_conditioning_tokens
     };
+    let prompt = history.prepend(
+        &encoded_chunks.prompts[chunk_idx],
+        n_cached,
+        state.lm.config.max_seq_len,
+        sampling_args.eos.max_new_tokens(state.lm.max_new_tokens),
+    )?;
+    let (tokens, report) = match options.best_of {
+        Some(n) if n > 1 => {
+            let prompt = encoded_chunks.with_conditioning(chunk_idx, &prompt)?;
+            generate_chunk_codes_best_of(state.clone(), &prompt, text, n, sampling_args).await?
+        }
+        _ => {
+            let (tokens, _, report) = server_lm_generate_blocking(
+                state.clone(),
+                &prompt,
+                Some(text),
+                sampling_args,
+                n_conditioning_tokens,
+                false,
+                options.word_timestamps,
+            )
+            .await
+            .context("Failed to generate semantic tokens")?;
+            (tokens, report)
+        }
+    };
+    if history.is_enabled() {
+        let prompt_encoder = PromptEncoder::new(
+            &state.lm.tokenizer,
+            &state.device,
+            state.lm.config.num_codebooks,
+            state.lm.model_type,
+        );
+        history.push(prompt_encoder.encode_turn(text, &tokens)?);
+    }
+    Ok((tokens, report))
+}
+
+type ChunkResult = anyhow::Result<(Tensor, GenerationReport)>;
+
+/// Vocoded audio for one chunk, to be joined onto the previous chunks with a [`PcmJoiner`]
+pub struct VocodedChunk {
+    pub pcm: Tensor,
+    /// Leading samples that re-render the end of the previous chunk
+    pub context_samples: usize,
+    /// Semantic tokens generated for the chunk
+    pub frames: usize,
+    pub report: GenerationReport,
+}
+
+/// Vocodes consecutive chunks of one utterance, giving each the previous chunk's last codes
+/// as left context so the vocoder doesn't start cold at every seam
+#[derive(Default)]
+pub struct ChunkVocoder {
+    prev_codes: Option<Tensor>,
+}
+
+impl ChunkVocoder {
+    /// Returns the PCM and how many of its leading samples are left context
+    pub async fn vocode(
+        &mut self,
+        state: Arc<AppState>,
+        semantic_tokens: &Tensor,
+    ) -> anyhow::Result<(Tensor, usize)> {
+        let codes = codes_for_vocoder(&state, semantic_tokens)?;
+        let (input, context_frames) = with_left_context(
+            self.prev_codes.as_ref(),
+            &codes,
+            state.vocoder_context_frames,
+        )?;
+        let pcm = decode_codes(state, &input).await?;
+        let n_frames = input.dim(D::Minus1)?;
+        let context_samples = (pcm.dim(0)? * context_frames)
+            .checked_div(n_frames)
+            .unwrap_or(0);
+        self.prev_codes = Some(codes);
+        Ok((pcm, context_samples))
+    }
+}
+
+/// Joins chunks with a crossfade of `state.crossfade_ms` at each seam
+pub fn pcm_joiner(state: &AppState) -> PcmJoiner {
+    PcmJoiner::from_ms(state.crossfade_ms, state.sample_rate)
+}
+
+/// Samples of silence after each chunk, across all runs
+fn chunk_pauses(
+    state: &AppState,
+    runs: &[EncodedChunks],
+    options: &GenerationOptions,
+) -> Vec<usize> {
+    runs.iter()
+        .flat_map(|run| run.breaks.iter())
+        .map(|&chunk_break| options.pauses.pause_samples(chunk_break, state.sample_rate))
+        .collect()
+}
+
+/// Generates the chunks of each run in order on one task and vocodes them on another, so the
+/// LM moves on to chunk i+1 while chunk i is vocoded. Each run has its own speaker conditioning.
+///
+/// At most `state.pipeline_depth` generated chunks wait for the vocoder; at depth 0 each chunk is
+/// vocoded before the next is generated. Results arrive in chunk order, and generation stops at
+/// the first error or once the receiver is dropped. Both tasks hold `permit`, so it's released
+/// only once the last chunk is vocoded, even if the receiver is dropped first.
+pub(crate) fn spawn_chunk_pipeline(
+    state: Arc<AppState>,
+    runs: Vec<EncodedChunks>,
+    options: GenerationOptions,
+    permit: OwnedSemaphorePermit,
+) -> mpsc::Receiver<anyhow::Result<VocodedChunk>> {
+    let permit = Arc::new(permit);
+    let (pcm_tx, pcm_rx) = mpsc::channel(1);
+    let depth = state.pipeline_depth;
+    let codes_tx = (depth > 0).then(|| {
+        let (codes_tx, mut codes_rx) = mpsc::channel::<ChunkResult>(depth);
+        let vocoder_state = state.clone();
+        let pcm_tx = pcm_tx.clone();
+        let permit = permit.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+            let mut vocoder = ChunkVocoder::default();
+            while let Some(codes) = codes_rx.recv().await {
+                let pcm = vocode_chunk(vocoder_state.clone(), &mut vocoder, codes).await;
+                if pcm_tx.send(pcm).await.is_err() {
+                    break;
+                }
+            }
+        });
+        codes_tx
+    });
 
-    Ok((tokens, hidden_states))
+    tokio::spawn(async move {
+        let _permit = permit;
+        // Only used when vocoding inline
+        let mut vocoder = ChunkVocoder::default();
+        let n_chunks: usize = runs.iter().map(|run| run.prompts.len()).sum();
+        let mut chunk_idx = 0;
+        'runs: for encoded_chunks in runs.iter() {
+            // History from another speaker would carry their voice over
+            let mut history = ChunkHistory::new(options.context_turns);
+            for i in 0..encoded_chunks.prompts.len() {
+                info!("Generating chunk {} of {}", chunk_idx, n_chunks);
+                let sampling_args = options.sampling_args(&state, chunk_idx);
+                chunk_idx += 1;
+                let codes = generate_codes_for_chunk(
+                    state.clone(),
+                    encoded_chunks,
+                    i,
+                    &sampling_args,
+                    &options,
+                    &mut history,
+                )
+                .await;
+                let failed = codes.is_err();
+                let sent = match &codes_tx {
+                    Some(codes_tx) => codes_tx.send(codes).await.is_ok(),
+                    None => pcm_tx
+                        .send(vocode_chunk(state.clone(), &mut vocoder, codes).await)
+                        .await
+                        .is_ok(),
+                };
+                if failed || !sent {
+                    break 'runs;
+                }
+            }
+            // The next run starts from its own conditioning
+            state.lm.model.lock().await.clear_slow_layer_caches();
+        }
+        // Final cache eviction
+        state.lm.model.lock().await.clear_slow_layer_caches();
+        info!("Final cache cleared");
+    });
+    pcm_rx
+}
+
+async fn vocode_chunk(
+    state: Arc<AppState>,
+    vocoder: &mut ChunkVocoder,
+    codes: ChunkResult,
+) -> anyhow::Result<VocodedChunk> {
+    let (tokens, report) = codes?;
+    let (pcm, context_samples) = vocoder.vocode(state, &tokens).await?;
+    Ok(VocodedChunk {
+        pcm,
+        context_samples,
+        frames: tokens.dim(D::Minus1)?,
+        report,
+    })
 }
 
 pub async fn generate_pcm_batched(
@@ -96,16 +520,36 @@ pub async fn vocode_semantic_tokens(
     state: Arc<AppState>,
     semantic_tokens: &Tensor,
 ) -> anyhow::Result<Tensor> {
-    let vocoder_start = Instant::now();
+    let tokens = codes_for_vocoder(&state, semantic_tokens)?;
+    decode_codes(state, &tokens).await
+}
+
+/// Drops the trailing frame DualAR generates along with EOS
+fn codes_for_vocoder(state: &AppState, semantic_tokens: &Tensor) -> anyhow::Result<Tensor> {
     let (_, seqlen) = semantic_tokens.dims2()?;
     debug!("Shape: {:?}, seqlen: {}", semantic_tokens.shape(), seqlen);
-    let tokens = match state.lm.model_type {
+    Ok(match state.lm.model_type {
         WhichLM::DualAR => semantic_tokens.i((.., ..seqlen - 1))?,
         _ => semantic_tokens.clone(),
-    };
-    // println!("Tokens shape: {:?},s eqlen: {}", tokens.shape(), seqlen);
+    })
+}
 
-    let out = state.codec.decode_batch(&tokens).await?;
+async fn decode_codes(state: Arc<AppState>, tokens: &Tensor) -> anyhow::Result<Tensor> {
+    let vocoder_start = Instant::now();
+    let out = if state.stage_pools.is_some() {
+        // Lock on the runtime, then vocode on a blocking thread so the pool doesn't hold up a
+        // tokio worker for the whole decode
+        let mut codec = state.codec.lock().await;
+        let tokens = tokens.clone();
+        let pool_state = state.clone();
+        tokio::task::spawn_blocking(move || {
+            pool_state.in_vocoder_pool(|| codec.decode_batch(&tokens))
+        })
+        .await
+        .context("Vocoder task panicked")??
+    } else {
+        state.codec.decode_batch(tokens).await?
+    };
     let duration = vocoder_start.elapsed();
     info!("Vocoding took: {} ms", duration.as_millis());
 
@@ -113,123 +557,241 @@ pub async fn vocode_semantic_tokens(
     Ok(out)
 }
 
-async fn generate_pcm_chunk(
-    state: Arc<AppState>,
-    encoded_input: &Tensor,
-    n_conditioning_tokens: usize,
-) -> anyhow::Result<Tensor> {
-    let (semantic_tokens, _) = server_lm_generate_blocking(
-        state.clone(),
-        encoded_input,
-        &state.lm.default_sampling_args,
-        n_conditioning_tokens,
-        false,
-    )
-    .await
-    .context("Failed to generate semantic tokens")?;
-    vocode_semantic_tokens(state.clone(), &semantic_tokens).await
-}
-
 async fn generate_speech_blocking(
     state: Arc<AppState>,
-    prompts: (usize, Vec<Tensor>),
+    runs: Vec<EncodedChunks>,
     maybe_bsz: Option<usize>,
+    options: GenerationOptions,
+    timing_format: Option<TimingFormat>,
+    permit: OwnedSemaphorePermit,
 ) -> Result<Response<Body>, AppError> {
-    // GPU work is serialized at the request level; no acquisition here
+    // GPU work is serialized by the passed-in permit; no acquisition here
     let mut all_pcm = Vec::new();
-    let (n_conditioning_tokens, prompts) = prompts;
+    let mut reports = Vec::new();
+    let mut timings = Vec::new();
+    // Batches are vocoded as one sequence, so there's nowhere to put pauses between their chunks
+    let pauses = chunk_pauses(&state, &runs, &options);
+    let sequential_only = options.best_of.is_some_and(|n| n > 1)
+        || pauses.iter().any(|&n_samples| n_samples > 0)
+        || !options.chunk_eos.is_empty()
+        || !options.slow_sampling.is_empty()
+        || !options.fast_sampling.is_empty()
+        || options.context_turns > 0
+        || timing_format.is_some();
 
     match maybe_bsz {
-        Some(batch_size) => {
+        Some(batch_size) if !sequential_only => {
             // Opt-in internal batching
-            for (i, batch) in prompts.chunks(batch_size).enumerate() {
-                info!("Processing batch {} ({}) prompts", i, batch.len());
-                let pcm = generate_pcm_batched(state.clone(), batch)
-                    .await?
-                    .to_vec1::<f32>()?;
-                all_pcm.extend(pcm);
+            for encoded_chunks in runs.iter() {
+                for (i, batch) in encoded_chunks.prompts.chunks(batch_size).enumerate() {
+                    info!("Processing batch {} ({}) prompts", i, batch.len());
+                    let pcm = generate_pcm_batched(state.clone(), batch)
+                        .await?
+                        .to_vec1::<f32>()?;
+                    all_pcm.extend(pcm);
+                }
             }
+            let mut model = state.lm.model.lock().await;
+            // Final cache eviction
+            model.clear_slow_layer_caches();
         }
-        None => {
-            // Single batch
-            for (i, prompt) in prompts.iter().enumerate() {
-                info!("Beginning chunk {} of {}", i, prompts.len());
-                let pcm = generate_pcm_chunk(state.clone(), prompt, n_conditioning_tokens)
-                    .await?
-                    .to_vec1::<f32>()?;
-                all_pcm.extend(pcm);
+        _ => {
+            if maybe_bsz.is_some() {
+                warn!(
+                    "best_of, duration controls, per-head sampling, context_turns, timing_format and pauses take precedence over batch_size; ignoring batch_size"
+                );
+            }
+            let mut joiner = pcm_joiner(&state);
+            let texts: Vec<String> = runs.iter().flat_map(|run| run.texts.clone()).collect();
+            let mut chunks = spawn_chunk_pipeline(state.clone(), runs, options, permit);
+            while let Some(chunk) = chunks.recv().await {
+                let chunk = chunk?;
+                let pcm = chunk.pcm.to_vec1::<f32>()?;
+                all_pcm.extend(joiner.push(&pcm, chunk.context_samples));
+                // The chunk's own audio ends the joined signal so far
+                let end_sample = all_pcm.len() + joiner.pending();
+                let start_sample = end_sample - (pcm.len() - chunk.context_samples.min(pcm.len()));
+                let frame_sample = |frame: usize| {
+                    let offset = ((end_sample - start_sample) * frame)
+                        .checked_div(chunk.frames)
+                        .unwrap_or(0);
+                    (start_sample + offset).min(end_sample)
+                };
+                let words = chunk
+                    .report
+                    .words
+                    .iter()
+                    .map(|w| WordTiming {
+                        word: w.word.clone(),
+                        start_sample: frame_sample(w.start_frame),
+                        end_sample: frame_sample(w.end_frame),
+                    })
+                    .collect();
+                timings.push(ChunkTiming {
+                    text: texts[reports.len()].clone(),
+                    start_sample,
+                    end_sample,
+                    tokens: chunk.frames,
+                    words,
+                });
+                all_pcm.extend(joiner.pause(pauses[reports.len()]));
+                reports.push(chunk.report);
             }
+            all_pcm.extend(joiner.finish());
         }
     }
-    // Initial prefill
     info!("Generation complete");
-    let mut model = state.lm.model.lock().await;
-    // Final cache eviction
-    model.clear_slow_layer_caches();
-    info!("Final cache cleared");
 
     let mut audio_buf = Vec::new();
     write_pcm_as_wav(&mut audio_buf, &all_pcm, state.sample_rate)
         .context("Failed to write PCM as WAV")?;
 
-    Ok(Response::builder()
-        .status(StatusCode::OK)
-        .header("Content-Type", "audio/wav")
-        .body(Body::from(audio_buf))
-        .context("Failed to build WAV response")?)
+    let mut response = Response::builder().status(StatusCode::OK);
+    // Batched generation doesn't retry, so it has nothing to report
+    if !reports.is_empty() {
+        let attempts: usize = reports.iter().map(|r| r.attempts).sum();
+        let degenerate: Vec<String> = reports
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| !r.issues.is_empty())
+            .map(|(i, r)| {
+                let kinds: Vec<&str> = r.issues.iter().map(|issue| issue.kind()).collect();
+                format!("{}:{}", i, kinds.join(","))
+            })
+            .collect();
+        response = response.header("X-Generation-Attempts", attempts);
+        if !degenerate.is_empty() {
+            response = response.header("X-Degenerate-Chunks", degenerate.join(";"));
+        }
+    }
+
+    let Some(timing_format) = timing_format else {
+        return Ok(response
+            .header("Content-Type", "audio/wav")
+            .body(Body::from(audio_buf))
+            .context("Failed to build WAV response")?);
+    };
+    let (content_type, timing_body) = match timing_format {
+        TimingFormat::Json => (
+            "application/json",
+            serde_json::to_string(&timings).context("Failed to serialize timings")?,
+        ),
+        TimingFormat::Srt => ("application/x-subrip", to_srt(&timings, state.sample_rate)),
+        TimingFormat::Vtt => ("text/vtt", to_vtt(&timings, state.sample_rate)),
+    };
+    with_timings(response, audio_buf, content_type, timing_body)
+}
+
+/// A `multipart/mixed` response of WAV audio followed by its timings
+pub(crate) fn with_timings(
+    response: axum::http::response::Builder,
+    audio_buf: Vec<u8>,
+    content_type: &str,
+    timing_body: String,
+) -> Result<Response<Body>, AppError> {
+    let mut body = Vec::with_capacity(audio_buf.len() + timing_body.len() + 256);
+    for (part_type, part) in [
+        ("audio/wav", audio_buf.as_slice()),
+        (content_type, timing_body.as_bytes()),
+    ] {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Type: {}\r\n\r\n",
+                MULTIPART_BOUNDARY, part_type
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(part);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", MULTIPART_BOUNDARY).as_bytes());
+    Ok(response
+        .header(
+            "Content-Type",
+            format!("multipart/mixed; boundary={}", MULTIPART_BOUNDARY),
+        )
+        .body(Body::from(body))
+        .context("Failed to build multipart response")?)
+}
+
+/// Separates the audio and timing parts of a response with `timing_format`
+const MULTIPART_BOUNDARY: &str = "fish-speech-timings";
+
+/// Chunk timings returned alongside WAV audio
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimingFormat {
+    Json,
+    Srt,
+    Vtt,
 }
 
 async fn generate_speech_streaming(
     state: Arc<AppState>,
-    prompts: (usize, Vec<Tensor>),
+    runs: Vec<EncodedChunks>,
+    options: GenerationOptions,
     permit: OwnedSemaphorePermit,
 ) -> Result<Response<Body>, AppError> {
     // GPU work is serialized by the passed-in permit held for the stream
     let src_rate: u32 = state.sample_rate;
     const DST_RATE: u32 = 24000;
-    let (n_conditioning_tokens, prompts) = prompts;
 
     // Move all stream setup before the stream definition
     let encoder = Arc::new(Mutex::new(
         OpusEncoder::new().context("Failed to create Opus encoder")?,
     ));
 
-    // Create a single clone of everything the stream will need
-    let stream_state = state.clone();
-    // Move the permit into the stream to keep it alive for its entire lifetime
-    let guard = permit;
+    let mut joiner = pcm_joiner(&state);
+    let pauses = chunk_pauses(&state, &runs, &options);
+    // The permit moves into the pipeline and is released once generation stops
+    let mut chunks = spawn_chunk_pipeline(state, runs, options, permit);
 
     let stream = async_stream::stream! {
-        let _permit = guard;
-        for (i, prompt) in prompts.iter().enumerate() {
-            info!("Generating chunk {} of {}", i, prompts.len());
-            match generate_pcm_chunk(stream_state.clone(), prompt, n_conditioning_tokens).await {
-                Ok(pcm_data) => {
-                    if i == prompts.len() - 1 {
-                        let mut model = stream_state.lm.model.lock().await;
-                        model.clear_slow_layer_caches();
-                    };
-                    let resample_start = std::time::Instant::now();
-                    let resampled_pcm: Tensor = resample(&pcm_data.unsqueeze(0).unwrap(), src_rate, DST_RATE)
-                        .map_err(|e| std::io::Error::other(e.to_string()))?
-                        .flatten_all()
-                        .map_err(|e| std::io::Error::other(e.to_string()))?;
-                    let resampled_pcm = resampled_pcm
+        let mut chunk_idx = 0;
+        loop {
+            // The joiner holds back each chunk's tail for the next seam, so flush it at the end
+            let (pcm_data, last) = match chunks.recv().await {
+                // Headers are already sent; degenerate chunks are only logged
+                Some(Ok(chunk)) => {
+                    let pcm = chunk
+                        .pcm
                         .to_vec1::<f32>()
                         .map_err(|e| std::io::Error::other(format!("PCM generation failed: {}", e)))?;
-                    let duration = resample_start.elapsed();
-                    info!("CPU resampling took: {:?}", duration);
-                    let mut encoder = encoder.lock().await;
-                    match encoder.encode_pcm(&resampled_pcm) {
-                        Ok(encoded) => {
-                            for chunk in encoded.chunks(1024) {
-                                yield Ok(Bytes::copy_from_slice(chunk));
-                            }
+                    let mut pcm = joiner.push(&pcm, chunk.context_samples);
+                    pcm.extend(joiner.pause(pauses[chunk_idx]));
+                    chunk_idx += 1;
+                    (pcm, false)
+                }
+                Some(Err(e)) => {
+                    yield Err(std::io::Error::other(format!("PCM generation failed: {}", e)));
+                    continue;
+                }
+                None => (joiner.finish(), true),
+            };
+            if !pcm_data.is_empty() {
+                let resample_start = std::time::Instant::now();
+                let pcm_data = Tensor::from_vec(pcm_data, (1, ()), &candle_core::Device::Cpu)
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                let resampled_pcm: Tensor = resample(&pcm_data, src_rate, DST_RATE)
+                    .map_err(|e| std::io::Error::other(e.to_string()))?
+                    .flatten_all()
+                    .map_err(|e| std::io::Error::other(e.to_string()))?;
+                let resampled_pcm = resampled_pcm
+                    .to_vec1::<f32>()
+                    .map_err(|e| std::io::Error::other(format!("PCM generation failed: {}", e)))?;
+                let duration = resample_start.elapsed();
+                info!("CPU resampling took: {:?}", duration);
+                let mut encoder = encoder.lock().await;
+                match encoder.encode_pcm(&resampled_pcm) {
+                    Ok(encoded) => {
+                        for chunk in encoded.chunks(1024) {
+                            yield Ok(Bytes::copy_from_slice(chunk));
                         }
-                        Err(e) => yield Err(std::io::Error::other(format!("PCM generation failed: {}", e)))
                     }
+                    Err(e) => yield Err(std::io::Error::other(format!("PCM generation failed: {}", e)))
                 }
-                Err(e) => yield Err(std::io::Error::other(format!("PCM generation failed: {}", e)))
+            }
+            if last {
+                break;
             }
         }
     };
@@ -242,7 +804,162 @@ async fn generate_speech_streaming(
         .context("Failed to build streaming response")?)
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Smallest number of frames a chunk must leave room for after its prompt
+const MIN_CHUNK_GENERATION_BUDGET: usize = 256;
+
+/// Upper bound on `best_of`, since all candidates for a chunk are generated in one batch
+const MAX_BEST_OF: usize = 8;
+
+/// Prompts ready for generation, alongside the (possibly re-split) chunk text they encode
+pub struct EncodedChunks {
+    pub n_conditioning_tokens: usize,
+    /// Chunk 0 carries the conditioning prefix itself; later chunks reuse it from the KV cache
+    pub prompts: Vec<Tensor>,
+    pub texts: Vec<String>,
+    /// What follows each chunk
+    pub breaks: Vec<ChunkBreak>,
+    /// Index of the input chunk each prompt was encoded from
+    pub sources: Vec<usize>,
+}
+
+impl EncodedChunks {
+    /// Prompt for `chunk_idx` with the conditioning prefix, for generation from an empty cache
+    pub fn full_prompt(&self, chunk_idx: usize) -> candle_core::Result<Tensor> {
+        self.with_conditioning(chunk_idx, &self.prompts[chunk_idx])
+    }
+
+    /// Adds the conditioning prefix to `prompt`, a (possibly extended) prompt for `chunk_idx`
+    pub fn with_conditioning(
+        &self,
+        chunk_idx: usize,
+        prompt: &Tensor,
+    ) -> candle_core::Result<Tensor> {
+        if chunk_idx == 0 || self.n_conditioning_tokens == 0 {
+            return Ok(prompt.clone());
+        }
+        let conditioning = self.prompts[0].narrow(D::Minus1, 0, self.n_conditioning_tokens)?;
+        Tensor::cat(&[&conditioning, prompt], D::Minus1)
+    }
+}
+
+/// Splits a chunk roughly in half, preferring a comma or whitespace boundary near the middle
+fn split_chunk(chunk: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = chunk.chars().collect();
+    if chars.len() < 2 {
+        return None;
+    }
+    let middle = chars.len() / 2;
+    let split_at = (1..chars.len())
+        .filter(|&i| matches!(chars[i - 1], ',' | '，' | '、') || chars[i - 1].is_whitespace())
+        .min_by_key(|&i| i.abs_diff(middle))
+        .unwrap_or(middle);
+    let left: String = chars[..split_at].iter().collect();
+    let right: String = chars[split_at..].iter().collect();
+    if left.trim().is_empty() || right.trim().is_empty() {
+        return None;
+    }
+    Some((left.trim().to_string(), right.trim().to_string()))
+}
+
+/// Encodes chunks for generation, re-chunking any prompt that would overflow the model context.
+pub fn encode_chunks_within_context(
+    state: &AppState,
+    chunks: Vec<TextChunk>,
+    sysprompt_text: Option<String>,
+    voice_embedding: Option<Tensor>,
+) -> Result<EncodedChunks, AppError> {
+    let max_seq_len = state.lm.config.max_seq_len;
+    let (mut chunks, mut breaks): (Vec<String>, Vec<ChunkBreak>) =
+        chunks.into_iter().map(|c| (c.text, c.after)).unzip();
+    let mut sources: Vec<usize> = (0..chunks.len()).collect();
+    loop {
+        let prompt_encoder = PromptEncoder::new(
+            &state.lm.tokenizer,
+            &state.device,
+            state.lm.config.num_codebooks,
+            state.lm.model_type,
+        );
+        let (n_conditioning_tokens, prompts) = prompt_encoder.encode_sequence(
+            chunks.clone(),
+            sysprompt_text.clone(),
+            voice_embedding.clone(),
+            true,
+        )?;
+        if n_conditioning_tokens + MIN_CHUNK_GENERATION_BUDGET >= max_seq_len {
+            return Err(AppError::BadRequest(format!(
+                "Speaker conditioning is {} tokens, leaving no room to generate within max_seq_len {}",
+                n_conditioning_tokens, max_seq_len
+            )));
+        }
+
+        let mut overflowing = None;
+        for (i, prompt) in prompts.iter().enumerate() {
+            let n_cached = if i == 0 { 0 } else { n_conditioning_tokens };
+            let prompt_len = prompt.dim(D::Minus1)?;
+            if n_cached + prompt_len + MIN_CHUNK_GENERATION_BUDGET > max_seq_len {
+                overflowing = Some((i, n_cached + prompt_len));
+                break;
+            }
+        }
+        let Some((i, needed)) = overflowing else {
+            return Ok(EncodedChunks {
+                n_conditioning_tokens,
+                prompts,
+                texts: chunks,
+                breaks,
+                sources,
+            });
+        };
+        match split_chunk(&chunks[i]) {
+            Some((left, right)) => {
+                info!(
+                    "Chunk {} needs {} of {} context tokens; re-chunking",
+                    i, needed, max_seq_len
+                );
+                chunks.splice(i..=i, [left, right]);
+                breaks.insert(i, ChunkBreak::None);
+                sources.insert(i, sources[i]);
+            }
+            None => {
+                return Err(AppError::BadRequest(format!(
+                    "Input chunk {:?} needs {} prompt tokens plus {} for generation, exceeding max_seq_len {}",
+                    chunks[i], needed, MIN_CHUNK_GENERATION_BUDGET, max_seq_len
+                )));
+            }
+        }
+    }
+}
+
+/// Selects the request's LoRA adapter, or the base weights without one.
+///
+/// The choice persists on the shared model, so every handler that runs it calls this under the
+/// concurrency permit, once the request is validated and just before generating.
+pub(crate) async fn select_adapter(
+    state: &AppState,
+    adapter: Option<&str>,
+) -> Result<(), AppError> {
+    state
+        .lm
+        .model
+        .lock()
+        .await
+        .set_lora_adapter(adapter)
+        .map_err(|e| AppError::BadRequest(e.to_string()))
+}
+
+/// The request's speaker prompt, or the model's default instruction
+pub(crate) fn sysprompt_text(state: &AppState, speaker_prompt: Option<String>) -> Option<String> {
+    if speaker_prompt.is_some() {
+        speaker_prompt
+    } else {
+        match state.model_type {
+            WhichModel::Fish1_5 => Some("Speak out the provided text.".to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct GenerateRequest {
     pub model: String, // Ignored for now
     pub voice: String,
@@ -251,6 +968,36 @@ pub struct GenerateRequest {
     pub response_format: Option<String>,
     pub batch_size: Option<usize>,
     pub speaker_prompt: Option<String>,
+    /// LoRA adapter loaded from --lora-dir; base weights if unset
+    pub adapter: Option<String>,
+    /// Generate this many candidates per chunk and keep the best-scoring one
+    pub best_of: Option<usize>,
+    /// Seconds of audio below which generation may not end
+    pub min_duration: Option<f64>,
+    /// Seconds of audio to steer generation towards
+    pub target_duration: Option<f64>,
+    /// Strength of the steering towards `target_duration`, as an EOS logit bias
+    pub eos_bias: Option<f32>,
+    /// `json`, `srt` or `vtt`: return chunk timings with the WAV, as `multipart/mixed`
+    pub timing_format: Option<String>,
+    /// Add word-level timings to `timing_format` (JSON by default), aligned from attention
+    pub word_timestamps: Option<bool>,
+    /// `text` or `ssml`; detected from a leading `<speak>` if unset
+    pub input_format: Option<String>,
+    #[serde(flatten)]
+    pub text: TextOptions,
+    /// Previous chunks kept in context for the next one; defaults to --context-turns
+    pub context_turns: Option<usize>,
+    /// Silence after chunks ending a sentence; defaults to --sentence-pause-ms
+    pub sentence_pause_ms: Option<f32>,
+    /// Silence at blank lines; defaults to --paragraph-pause-ms
+    pub paragraph_pause_ms: Option<f32>,
+    /// Silence at `[pause]` markers without a duration; defaults to --break-pause-ms
+    pub break_pause_ms: Option<f32>,
+    /// Semantic head settings, over --slow-sampling
+    pub slow_sampling: Option<HeadOverrides>,
+    /// Codebook head settings, over --fast-sampling
+    pub fast_sampling: Option<HeadOverrides>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -283,31 +1030,157 @@ pub async fn generate_speech(
         ),
     };
 
-    let state = state.clone();
-    let num_codebooks = state.lm.config.num_codebooks;
-    let chunks = preprocess_text(&request.input);
-    // Prompt encoding creates device tensors; runs under the same permit
-    let prompt_encoder = PromptEncoder::new(
-        &state.lm.tokenizer,
-        &state.device,
-        num_codebooks,
-        state.lm.model_type,
-    );
-    let sysprompt_text = if request.speaker_prompt.is_some() {
-        request.speaker_prompt
-    } else {
-        match state.model_type {
-            WhichModel::Fish1_5 => Some("Speak out the provided text.".to_string()),
-            _ => None,
+    if let Some(best_of) = request.best_of
+        && !(1..=MAX_BEST_OF).contains(&best_of)
+    {
+        return Err(AppError::BadRequest(format!(
+            "best_of must be between 1 and {}",
+            MAX_BEST_OF
+        )));
+    }
+
+    for (name, duration) in [
+        ("min_duration", request.min_duration),
+        ("target_duration", request.target_duration),
+    ] {
+        if let Some(d) = duration
+            && !(d.is_finite() && d > 0.0)
+        {
+            return Err(AppError::BadRequest(format!(
+                "{} must be a positive number of seconds",
+                name
+            )));
+        }
+    }
+    if let (Some(min), Some(target)) = (request.min_duration, request.target_duration)
+        && min > target
+    {
+        return Err(AppError::BadRequest(
+            "min_duration must not exceed target_duration".to_string(),
+        ));
+    }
+    if let Some(bias) = request.eos_bias
+        && !(bias.is_finite() && bias >= 0.0)
+    {
+        return Err(AppError::BadRequest(
+            "eos_bias must be a non-negative number".to_string(),
+        ));
+    }
+
+    for (name, pause) in [
+        ("sentence_pause_ms", request.sentence_pause_ms),
+        ("paragraph_pause_ms", request.paragraph_pause_ms),
+        ("break_pause_ms", request.break_pause_ms),
+    ] {
+        if let Some(ms) = pause
+            && !(0.0..=MAX_PAUSE_MS).contains(&ms)
+        {
+            return Err(AppError::BadRequest(format!(
+                "{} must be between 0 and {} milliseconds",
+                name, MAX_PAUSE_MS
+            )));
+        }
+    }
+
+    let word_timestamps = request.word_timestamps.unwrap_or(false);
+    if word_timestamps && request.best_of.is_some_and(|n| n > 1) {
+        return Err(AppError::BadRequest(
+            "word_timestamps is not supported with best_of".to_string(),
+        ));
+    }
+    let timing_format = match request.timing_format.as_deref() {
+        None if word_timestamps => Some(TimingFormat::Json),
+        None => None,
+        Some("json") => Some(TimingFormat::Json),
+        Some("srt") => Some(TimingFormat::Srt),
+        Some("vtt") => Some(TimingFormat::Vtt),
+        Some(other) => {
+            return Err(AppError::BadRequest(format!(
+                "timing_format must be \"json\", \"srt\" or \"vtt\", not {:?}",
+                other
+            )));
         }
     };
+    let streaming = request.response_format == Some("opus".into());
+    if streaming && timing_format.is_some() {
+        return Err(AppError::BadRequest(
+            "timing_format is only supported for WAV responses".to_string(),
+        ));
+    }
+
+    let state = state.clone();
+    let text_runs = request.text.chunk_input(
+        &state,
+        &request.voice,
+        &request.input,
+        request.input_format.as_deref(),
+    )?;
+    let sysprompt_text = sysprompt_text(&state, request.speaker_prompt);
 
-    let prompts = prompt_encoder.encode_sequence(chunks, sysprompt_text, voice_embedding, true)?;
+    // Prompt encoding creates device tensors; runs under the same permit
+    let mut runs = Vec::with_capacity(text_runs.len());
+    for run in text_runs {
+        let voice_embedding = match run.voice.as_deref() {
+            None => voice_embedding.clone(),
+            Some("unconditioned") => None,
+            Some(name) => Some(
+                state
+                    .lm
+                    .voices
+                    .read()
+                    .await
+                    .get(name)
+                    .ok_or_else(|| {
+                        AppError::BadRequest(format!("Unknown voice {:?} in SSML", name))
+                    })?
+                    .clone(),
+            ),
+        };
+        runs.push(encode_chunks_within_context(
+            &state,
+            run.chunks,
+            sysprompt_text.clone(),
+            voice_embedding,
+        )?);
+    }
+    let texts: Vec<String> = runs.iter().flat_map(|run| run.texts.clone()).collect();
 
-    if request.response_format == Some("opus".into()) {
-        generate_speech_streaming(state, prompts, permit).await
+    let options = GenerationOptions {
+        best_of: request.best_of,
+        chunk_eos: chunk_eos_controls(
+            &texts,
+            request.min_duration,
+            request.target_duration,
+            request.eos_bias,
+            state.lm.model_type.frame_rate(),
+        ),
+        context_turns: request.context_turns.unwrap_or(state.lm.context_turns),
+        pauses: PauseConfig {
+            sentence_ms: request
+                .sentence_pause_ms
+                .unwrap_or(state.pauses.sentence_ms),
+            paragraph_ms: request
+                .paragraph_pause_ms
+                .unwrap_or(state.pauses.paragraph_ms),
+            marker_ms: request.break_pause_ms.unwrap_or(state.pauses.marker_ms),
+        },
+        word_timestamps,
+        slow_sampling: request.slow_sampling.clone().unwrap_or_default(),
+        fast_sampling: request.fast_sampling.clone().unwrap_or_default(),
+    };
+
+    select_adapter(&state, request.adapter.as_deref()).await?;
+    if streaming {
+        generate_speech_streaming(state, runs, options, permit).await
     } else {
-        let _permit = permit; // keep alive for the blocking path
-        generate_speech_blocking(state, prompts, request.batch_size).await
+        generate_speech_blocking(
+            state,
+            runs,
+            request.batch_size,
+            options,
+            timing_format,
+            permit,
+        )
+        .await
     }
 }
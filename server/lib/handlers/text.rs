@@ -0,0 +1,240 @@
+use super::error::AppError;
+use crate::state::AppState;
+use axum::{Json, extract::State};
+use fish_speech_core::text::{
+    chunking::{Chunker, ChunkingMode, TokenBudget},
+    lexicon::LexiconSpec,
+    pause::{ChunkBreak, preprocess_text_with_breaks_chunked},
+    pipeline::{RegexRule, RegexRules, ScriptRules, TextPipeline},
+    segment::Segmenter,
+    ssml::{SsmlRun, chunk_ssml, is_ssml, parse_ssml},
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// How a request's text is normalized and chunked, for the speech, dialogue and preview endpoints
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TextOptions {
+    /// Spell out numbers, dates, times, currency and units before generation
+    pub normalize_text: Option<bool>,
+    /// Built-in text normalization steps, in order; defaults to --text-steps
+    pub text_steps: Option<Vec<String>>,
+    /// Regex rules applied after the server's and the voice's
+    pub text_rules: Option<Vec<RegexRule>>,
+    /// Respellings added to the server's and the voice's lexicon
+    pub lexicon: Option<LexiconSpec>,
+    /// `en`, `de`, `fr` or `es`, for the abbreviations sentences don't end after and whether
+    /// numbers are read as English; defaults to --text-language
+    pub language: Option<String>,
+    /// Abbreviations added to the language's
+    pub abbreviations: Option<Vec<String>>,
+    /// `characters`, `tokens` or `off`; defaults to --chunking, or `tokens` if a budget is given
+    pub chunking: Option<String>,
+    /// Most tokenizer tokens per chunk; defaults to --max-chunk-tokens
+    pub max_chunk_tokens: Option<usize>,
+    /// Most estimated audio frames per chunk; defaults to --max-chunk-frames
+    pub max_chunk_frames: Option<usize>,
+}
+
+impl TextOptions {
+    /// The text pipeline for `voice`: the lexicon first, then the requested steps or the
+    /// server's (reading numbers first if `normalize_text`), with the server's, the voice's and
+    /// the request's rules before `punctuation`
+    pub(crate) fn pipeline(&self, state: &AppState, voice: &str) -> Result<TextPipeline, AppError> {
+        let language = self.language.as_deref().unwrap_or(&state.text_language);
+        let mut pipeline = match &self.text_steps {
+            Some(steps) => {
+                TextPipeline::from_steps(steps).map_err(|e| AppError::BadRequest(e.to_string()))?
+            }
+            None => state.text_pipeline.clone(),
+        }
+        .with_language(language);
+        if self.normalize_text.unwrap_or(false) && !pipeline.step_names().contains(&"numbers") {
+            // Before symbols, which turn the full-width colons in times into commas
+            pipeline = pipeline.insert_before("symbols", Arc::new(ScriptRules::new(language)));
+        }
+        let request_rules = self
+            .text_rules
+            .as_deref()
+            .map(RegexRules::new)
+            .transpose()
+            .map_err(|e| AppError::BadRequest(format!("{:#}", e)))?
+            .map(Arc::new);
+        for rules in [
+            state.text_rules.clone(),
+            state.voice_text_rules.get(voice).cloned(),
+            request_rules,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            pipeline = pipeline.insert_before("punctuation", rules);
+        }
+
+        // Respellings go first, so numbers and symbols in words like "3M" are left to them
+        let mut lexicon = state.lexicon.clone();
+        if let Some(voice_lexicon) = state.voice_lexicons.get(voice) {
+            lexicon = lexicon.extend(voice_lexicon.entries().iter().cloned());
+        }
+        if let Some(spec) = &self.lexicon {
+            lexicon = lexicon.extend(spec.clone().into_entries());
+        }
+        if !lexicon.is_empty() {
+            pipeline = pipeline.prepend(Arc::new(lexicon));
+        }
+        Ok(pipeline)
+    }
+
+    /// Sentence splitting for the request's language, falling back to the server's
+    pub(crate) fn segmenter(&self, state: &AppState) -> Result<Segmenter, AppError> {
+        let segmenter = match self.language.as_deref() {
+            Some(language) => Segmenter::for_language(language).ok_or_else(|| {
+                AppError::BadRequest(format!(
+                    "language must be \"en\", \"de\", \"fr\" or \"es\", not {:?}",
+                    language
+                ))
+            })?,
+            None => state.segmenter.clone(),
+        };
+        Ok(match &self.abbreviations {
+            Some(abbreviations) => segmenter.with_abbreviations(abbreviations),
+            None => segmenter,
+        })
+    }
+
+    /// The request's chunking, falling back to the server's
+    pub(crate) fn chunker<'a>(&self, state: &'a AppState) -> Result<Chunker<'a>, AppError> {
+        let (max_tokens, max_frames) = (self.max_chunk_tokens, self.max_chunk_frames);
+        let mode = match self.chunking.as_deref() {
+            None if max_tokens.is_some() || max_frames.is_some() => ChunkingMode::Tokens,
+            None => state.chunking,
+            Some("characters") => ChunkingMode::Characters,
+            Some("tokens") => ChunkingMode::Tokens,
+            Some("off") => ChunkingMode::Off,
+            Some(other) => {
+                return Err(AppError::BadRequest(format!(
+                    "chunking must be \"characters\", \"tokens\" or \"off\", not {:?}",
+                    other
+                )));
+            }
+        };
+        if max_tokens == Some(0) || max_frames == Some(0) {
+            return Err(AppError::BadRequest(
+                "max_chunk_tokens and max_chunk_frames must be positive".to_string(),
+            ));
+        }
+        let budget = TokenBudget {
+            max_tokens: max_tokens.unwrap_or(state.chunk_budget.max_tokens),
+            max_frames: max_frames.unwrap_or(state.chunk_budget.max_frames),
+            ..state.chunk_budget
+        };
+        Ok(Chunker::new(mode, &state.lm.tokenizer, budget))
+    }
+
+    /// Normalizes and chunks `input` for `voice`, as plain text or SSML per `input_format`
+    pub(crate) fn chunk_input(
+        &self,
+        state: &AppState,
+        voice: &str,
+        input: &str,
+        input_format: Option<&str>,
+    ) -> Result<Vec<SsmlRun>, AppError> {
+        let ssml = match input_format {
+            None => is_ssml(input),
+            Some("ssml") => true,
+            Some("text") => false,
+            Some(other) => {
+                return Err(AppError::BadRequest(format!(
+                    "input_format must be \"text\" or \"ssml\", not {:?}",
+                    other
+                )));
+            }
+        };
+        let pipeline = self.pipeline(state, voice)?;
+        let segmenter = self.segmenter(state)?;
+        let chunker = self.chunker(state)?;
+        Ok(if ssml {
+            let segments =
+                parse_ssml(input).map_err(|e| AppError::BadRequest(format!("{:#}", e)))?;
+            chunk_ssml(&segments, &pipeline, &segmenter, &chunker)
+        } else {
+            vec![SsmlRun {
+                voice: None,
+                chunks: preprocess_text_with_breaks_chunked(input, &pipeline, &segmenter, &chunker),
+            }]
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PreviewRequest {
+    pub voice: Option<String>,
+    pub input: String,
+    /// `text` or `ssml`; detected from a leading `<speak>` if unset
+    pub input_format: Option<String>,
+    #[serde(flatten)]
+    pub text: TextOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewChunk {
+    pub text: String,
+    /// SSML `<voice>` the chunk is spoken in, if it switches voices
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// `none`, `sentence`, `paragraph` or `pause`
+    pub after: String,
+    /// Silence after the chunk, in milliseconds, for `pause` markers with a length
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pause_ms: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PreviewResponse {
+    /// Normalization steps, in the order they ran
+    pub steps: Vec<String>,
+    /// The chunks `/v1/audio/speech` would generate
+    pub chunks: Vec<PreviewChunk>,
+}
+
+/// Shows how `/v1/audio/speech` would rewrite and chunk the input, without generating anything
+pub async fn preview_text(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<PreviewRequest>,
+) -> Result<Json<PreviewResponse>, AppError> {
+    let voice = request.voice.as_deref().unwrap_or("default");
+    let steps = request
+        .text
+        .pipeline(&state, voice)?
+        .step_names()
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let runs = request.text.chunk_input(
+        &state,
+        voice,
+        &request.input,
+        request.input_format.as_deref(),
+    )?;
+    let chunks = runs
+        .into_iter()
+        .flat_map(|run| {
+            let voice = run.voice;
+            run.chunks.into_iter().map(move |chunk| {
+                let (after, pause_ms) = match chunk.after {
+                    ChunkBreak::None => ("none", None),
+                    ChunkBreak::Sentence => ("sentence", None),
+                    ChunkBreak::Paragraph => ("paragraph", None),
+                    ChunkBreak::Marker(ms) => ("pause", ms),
+                };
+                PreviewChunk {
+                    text: chunk.text,
+                    voice: voice.clone(),
+                    after: after.to_string(),
+                    pause_ms,
+                }
+            })
+        })
+        .collect();
+    Ok(Json(PreviewResponse { steps, chunks }))
+}
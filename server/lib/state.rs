@@ -3,7 +3,13 @@ use candle_core::{Device, Tensor};
 use fish_speech_core::config::{WhichLM, WhichModel};
 use fish_speech_core::lm::DualARTransformer;
 use fish_speech_core::lm::dual_ar::BaseModelArgs;
+use fish_speech_core::lm::generate::degenerate::{DegenerateConfig, RetryPolicy};
 use fish_speech_core::lm::sampling::SamplingArgs;
+use fish_speech_core::text::chunking::{ChunkingMode, TokenBudget};
+use fish_speech_core::text::lexicon::Lexicon;
+use fish_speech_core::text::pause::PauseConfig;
+use fish_speech_core::text::pipeline::{RegexRules, TextPipeline};
+use fish_speech_core::text::segment::Segmenter;
 use std::collections::HashMap;
 use std::sync::Arc;
 use tokenizers::Tokenizer;
@@ -18,6 +24,10 @@ pub struct LMState {
     pub default_voice: Arc<Tensor>,
     pub default_sampling_args: SamplingArgs,
     pub max_new_tokens: usize,
+    pub retry_policy: RetryPolicy,
+    pub degenerate_config: DegenerateConfig,
+    /// Default number of previous chunks kept in context
+    pub context_turns: usize,
 }
 
 pub struct AppState {
@@ -29,4 +39,82 @@ pub struct AppState {
     pub voice_dir: std::path::PathBuf,
     /// Limit concurrent GPU work (Metal backend is not re-entrant across threads)
     pub concurrency: Arc<Semaphore>,
+    /// Generated chunks that may wait for the vocoder; 0 vocodes each chunk before generating the next
+    pub pipeline_depth: usize,
+    /// Frames of the previous chunk's codes the vocoder sees before each chunk
+    pub vocoder_context_frames: usize,
+    /// Crossfade at each chunk seam
+    pub crossfade_ms: f32,
+    /// Default silence between chunks, by what separates them
+    pub pauses: PauseConfig,
+    /// Slow layers whose attention aligns words for `word_timestamps`; empty for the default
+    pub alignment_layers: Vec<usize>,
+    /// Default chunking, and the limits for [`ChunkingMode::Tokens`]
+    pub chunking: ChunkingMode,
+    pub chunk_budget: TokenBudget,
+    /// Default text normalization, before any rules
+    pub text_pipeline: TextPipeline,
+    /// Rules from --text-rules, and each voice's from `<voice>.rules.json`
+    pub text_rules: Option<Arc<RegexRules>>,
+    pub voice_text_rules: HashMap<String, Arc<RegexRules>>,
+    /// Respellings from --lexicon, and each voice's from `<voice>.lexicon.json`
+    pub lexicon: Lexicon,
+    pub voice_lexicons: HashMap<String, Lexicon>,
+    /// --text-language
+    pub text_language: String,
+    /// Sentence splitting for --text-language, with --abbreviations
+    pub segmenter: Segmenter,
+    /// CPU only: separate thread pools for the LM and vocoder stages
+    pub stage_pools: Option<StagePools>,
+}
+
+/// Separate CPU thread pools for the LM and the vocoder, so pipelined stages don't contend
+pub struct StagePools {
+    pub lm: rayon::ThreadPool,
+    pub vocoder: rayon::ThreadPool,
+}
+
+impl StagePools {
+    /// Gives the vocoder `vocoder_threads` (default: a quarter of the cores) and the LM the rest
+    pub fn new(vocoder_threads: Option<usize>) -> anyhow::Result<Self> {
+        let total = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        let vocoder_threads = vocoder_threads
+            .unwrap_or(total / 4)
+            .clamp(1, total.saturating_sub(1).max(1));
+        let lm_threads = total.saturating_sub(vocoder_threads).max(1);
+        let lm = rayon::ThreadPoolBuilder::new()
+            .num_threads(lm_threads)
+            .thread_name(|i| format!("lm-{}", i))
+            .build()?;
+        let vocoder = rayon::ThreadPoolBuilder::new()
+            .num_threads(vocoder_threads)
+            .thread_name(|i| format!("vocoder-{}", i))
+            .build()?;
+        tracing::info!(
+            "CPU stage pools: {} LM threads, {} vocoder threads",
+            lm_threads,
+            vocoder_threads
+        );
+        Ok(Self { lm, vocoder })
+    }
+}
+
+impl AppState {
+    /// Runs LM work on the LM pool, if there is one
+    pub fn in_lm_pool<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        match &self.stage_pools {
+            Some(pools) => pools.lm.install(f),
+            None => f(),
+        }
+    }
+
+    /// Runs vocoder work on the vocoder pool, if there is one
+    pub fn in_vocoder_pool<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        match &self.stage_pools {
+            Some(pools) => pools.vocoder.install(f),
+            None => f(),
+        }
+    }
 }
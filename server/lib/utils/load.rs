@@ -1,22 +1,33 @@
 use crate::audio::{codec::Codec, mimi};
 use crate::state::LMState;
-use crate::utils::load_speaker_prompts;
+use crate::utils::{load_speaker_prompts, load_voice_files};
+use anyhow::Context;
 pub use bytes::Bytes;
 use candle_core::{DType, Device};
 use candle_nn::VarBuilder;
 use clap::Parser;
 use fish_speech_core::codec::{FireflyCodec, FireflyConfig};
 use fish_speech_core::{
-    config::{WhichCodec, WhichFishVersion, WhichLM, WhichModel},
+    config::{WhichCodec, WhichFishVersion, WhichLM, WhichModel, WhichQuantization},
     lm::{
         DualARTransformer,
         dual_ar::{BaseModelArgs, TokenConfig},
-        sampling::SamplingArgs,
+        generate::degenerate::{DegenerateConfig, RetryPolicy},
+        lora::LoraAdapter,
+        quantized::{LinearLoader, load_gguf},
+        sampling::{HeadOverrides, SamplingArgs},
+    },
+    text::{
+        chunking::ChunkingMode,
+        lexicon::Lexicon,
+        pipeline::{RegexRules, TextPipeline},
+        segment::Segmenter,
     },
 };
 pub use futures_util::Stream;
 use hf_hub::api::sync::{Api, ApiRepo};
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokenizers::Tokenizer;
 use tokio::sync::{Mutex, RwLock};
@@ -47,9 +58,152 @@ pub struct Args {
     #[arg(long, default_value = "0.8")]
     pub top_p: f64,
 
+    /// Min-p sampling threshold, relative to the most likely token. 0 disables
+    #[arg(long, default_value = "0.0")]
+    pub min_p: f64,
+
+    /// Typical sampling mass. 1 disables
+    #[arg(long, default_value = "1.0")]
+    pub typical_p: f64,
+
+    /// Penalty per occurrence of a codebook token within the window
+    #[arg(long, default_value = "0.0")]
+    pub frequency_penalty: f32,
+
+    /// Flat penalty for codebook tokens seen within the window
+    #[arg(long, default_value = "0.0")]
+    pub presence_penalty: f32,
+
+    /// Frames of history the penalties look at (default 16)
+    #[arg(long)]
+    pub rep_pen_window: Option<usize>,
+
+    /// Semantic head settings that differ from the shared ones, e.g. `temp=0.5,top_k=30`
+    #[arg(long)]
+    pub slow_sampling: Option<HeadOverrides>,
+
+    /// Codebook head settings that differ from the shared ones, e.g. `temp=0.9`
+    #[arg(long)]
+    pub fast_sampling: Option<HeadOverrides>,
+
     /// Run a warmup inference before accepting requests
     #[arg(long, default_value = "false")]
     pub warmup: bool,
+
+    /// Quantize LM linear layers (uses model-<quantization>.gguf from the checkpoint if present)
+    #[arg(long)]
+    pub quantization: Option<WhichQuantization>,
+
+    /// Directory of LoRA adapters (<name>.safetensors), selectable per request by name
+    #[arg(long)]
+    pub lora_dir: Option<PathBuf>,
+
+    /// Generated chunks that may queue for the vocoder while the LM moves on. 0 disables pipelining
+    /// (default: 2 on CPU; 0 on GPUs, where the LM and vocoder can't overlap)
+    #[arg(long)]
+    pub pipeline_depth: Option<usize>,
+
+    /// CPU only: threads reserved for the vocoder (default: a quarter of the cores)
+    #[arg(long)]
+    pub vocoder_threads: Option<usize>,
+
+    /// Frames of the previous chunk's codes given to the vocoder as left context for the next.
+    /// 0 vocodes chunks independently
+    #[arg(long, default_value = "8")]
+    pub vocoder_context_frames: usize,
+
+    /// Crossfade at chunk seams, in milliseconds
+    #[arg(long, default_value = "10")]
+    pub crossfade_ms: f32,
+
+    /// Silence after a chunk ending a sentence, in milliseconds
+    #[arg(long, default_value = "100")]
+    pub sentence_pause_ms: f32,
+
+    /// Silence at paragraph breaks (blank lines), in milliseconds
+    #[arg(long, default_value = "600")]
+    pub paragraph_pause_ms: f32,
+
+    /// Silence at `[pause]` markers without a duration, in milliseconds
+    #[arg(long, default_value = "500")]
+    pub break_pause_ms: f32,
+
+    /// Slow layers whose attention is used to align words for `word_timestamps`, comma-separated
+    /// (default: the middle third)
+    #[arg(long, value_delimiter = ',')]
+    pub alignment_layers: Vec<usize>,
+
+    /// How long inputs are cut into chunks: by characters per script, by tokenizer tokens and
+    /// estimated audio frames, or not at all (one chunk per paragraph or `[pause]`)
+    #[arg(long, value_enum, default_value = "characters")]
+    pub chunking: ChunkingMode,
+
+    /// Most tokenizer tokens in a chunk, with `--chunking tokens`
+    #[arg(long, default_value = "150")]
+    pub max_chunk_tokens: usize,
+
+    /// Most estimated audio frames in a chunk, with `--chunking tokens`
+    #[arg(long, default_value = "600")]
+    pub max_chunk_frames: usize,
+
+    /// Built-in text normalization steps, in order, comma-separated: symbols, emoji, numbers,
+    /// punctuation
+    #[arg(
+        long,
+        value_delimiter = ',',
+        default_value = "symbols,emoji,punctuation"
+    )]
+    pub text_steps: Vec<String>,
+
+    /// JSON file of `{"pattern", "replacement"}` regex rules applied to all input text
+    #[arg(long)]
+    pub text_rules: Option<PathBuf>,
+
+    /// JSON pronunciation lexicon applied to all input text (default: `lexicon.json` in the
+    /// voice directory, if present)
+    #[arg(long)]
+    pub lexicon: Option<PathBuf>,
+
+    /// Language whose abbreviations don't end sentences: en, de, fr or es
+    #[arg(long, default_value = "en")]
+    pub text_language: String,
+
+    /// More abbreviations that don't end sentences, comma-separated (`Approx.,Capt`)
+    #[arg(long, value_delimiter = ',')]
+    pub abbreviations: Vec<String>,
+
+    /// Previous chunks (text and generated audio) kept in context for the next one. 0 disables
+    #[arg(long, default_value = "0")]
+    pub context_turns: usize,
+
+    /// Times to regenerate a chunk whose output looks degenerate
+    #[arg(long, default_value = "1")]
+    pub max_retries: usize,
+
+    /// Temperature added on each retry of a degenerate chunk
+    #[arg(long, default_value = "0.1")]
+    pub retry_temp_bump: f64,
+
+    /// Longest run of identical frames, in seconds, before a chunk counts as stuck
+    #[arg(long, default_value = "1.5")]
+    pub max_repeated_seconds: f64,
+
+    /// Largest fraction of frame n-grams that may repeat an earlier one before a chunk counts as
+    /// looping
+    #[arg(long, default_value = "0.3")]
+    pub max_ngram_repeat_ratio: f32,
+
+    /// Slowest plausible speaking rate for a chunk, in estimated syllables per second
+    #[arg(long, default_value = "0.75")]
+    pub min_syllables_per_second: f32,
+
+    /// Fastest plausible speaking rate for a chunk, in estimated syllables per second
+    #[arg(long, default_value = "12.0")]
+    pub max_syllables_per_second: f32,
+
+    /// Lowest normalized entropy any codebook may have before a chunk counts as degenerate
+    #[arg(long, default_value = "0.25")]
+    pub min_codebook_entropy: f32,
 }
 
 pub fn get_model_repo(model_type: WhichModel) -> anyhow::Result<ApiRepo> {
@@ -83,7 +237,7 @@ pub fn load_lm(
         Some(dir) => dir.join(weight_str),
         None => repo.get(weight_str)?,
     };
-    let tokenizer_path = match checkpoint_dir {
+    let tokenizer_path = match checkpoint_dir.as_ref() {
         Some(dir) => dir.join("tokenizer.json"),
         None => {
             let _special_tokens = repo.get("special_tokens_map.json")?;
@@ -94,21 +248,42 @@ pub fn load_lm(
 
     let semantic_config = BaseModelArgs::from_file(config_path)?;
     let tokenizer = Arc::new(Tokenizer::from_file(tokenizer_path).unwrap());
+    // Quantized matmuls run on F32 activations
+    let dtype = match args.quantization {
+        Some(_) => DType::F32,
+        None => dtype,
+    };
+    let gguf_path = match (args.quantization, checkpoint_dir.as_ref()) {
+        (Some(quantization), Some(dir)) => Some(dir.join(quantization.gguf_file_name())),
+        _ => None,
+    };
     let lm_version = WhichLM::from_model(args.fish_version);
-    let vb_lm = match lm_version {
-        WhichLM::Fish(WhichFishVersion::Fish1_2) => {
-            VarBuilder::from_pth(weight_path, dtype, device)?
+    let (vb_lm, linear_loader) = match (lm_version, gguf_path) {
+        (_, Some(gguf_path)) if gguf_path.exists() => {
+            info!("Loading pre-quantized LM weights from {:?}", gguf_path);
+            load_gguf(&gguf_path, dtype, device)?
         }
-        _ => unsafe { VarBuilder::from_mmaped_safetensors(&[weight_path], dtype, device)? },
+        (WhichLM::Fish(WhichFishVersion::Fish1_2), _) => (
+            VarBuilder::from_pth(weight_path, dtype, device)?,
+            LinearLoader::quantize_on_load(args.quantization),
+        ),
+        _ => (
+            unsafe { VarBuilder::from_mmaped_safetensors(&[weight_path], dtype, device)? },
+            LinearLoader::quantize_on_load(args.quantization),
+        ),
     };
-    let lm_version = WhichLM::from_model(args.fish_version);
     let semantic_token_config = TokenConfig::new(lm_version, &tokenizer, &semantic_config)?;
-    let semantic_model = Arc::new(Mutex::new(DualARTransformer::load(
+    let mut semantic_model = DualARTransformer::load_with(
         &vb_lm,
         &semantic_config,
         &semantic_token_config,
         lm_version,
-    )?));
+        &linear_loader,
+    )?;
+    if let Some(lora_dir) = args.lora_dir.as_ref() {
+        load_lora_adapters(&mut semantic_model, lora_dir, dtype, device)?;
+    }
+    let semantic_model = Arc::new(Mutex::new(semantic_model));
     // Load all voices into memory
     let (speakers, default_speaker) = load_speaker_prompts(
         &args.voice_dir,
@@ -127,6 +302,14 @@ pub fn load_lm(
             WhichLM::DualAR | WhichLM::Fish(WhichFishVersion::Fish1_5) => 1.4,
             _ => 1.2,
         },
+        min_p: args.min_p,
+        typical_p: args.typical_p,
+        frequency_penalty: args.frequency_penalty,
+        presence_penalty: args.presence_penalty,
+        rep_pen_window: args.rep_pen_window,
+        slow: args.slow_sampling.clone().unwrap_or_default(),
+        fast: args.fast_sampling.clone().unwrap_or_default(),
+        ..Default::default()
     };
 
     Ok(LMState {
@@ -139,9 +322,48 @@ pub fn load_lm(
         default_sampling_args,
         // TODO Totally arbitrary value, make this configurable from CLI
         max_new_tokens: 1792,
+        retry_policy: RetryPolicy {
+            max_retries: args.max_retries,
+            temperature_bump: args.retry_temp_bump,
+        },
+        degenerate_config: DegenerateConfig {
+            max_repeated_seconds: args.max_repeated_seconds,
+            max_ngram_repeat_ratio: args.max_ngram_repeat_ratio,
+            min_syllables_per_second: args.min_syllables_per_second,
+            max_syllables_per_second: args.max_syllables_per_second,
+            min_codebook_entropy: args.min_codebook_entropy,
+            ..Default::default()
+        },
+        context_turns: args.context_turns,
     })
 }
 
+fn load_lora_adapters(
+    model: &mut DualARTransformer,
+    lora_dir: &Path,
+    dtype: DType,
+    device: &Device,
+) -> anyhow::Result<()> {
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(lora_dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "safetensors"))
+        .collect();
+    paths.sort();
+    for path in paths {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let adapter = LoraAdapter::load(&path, name, dtype, device)?;
+        model.add_lora_adapter(&adapter)?;
+        info!(
+            "Loaded LoRA adapter {} ({} layers)",
+            name,
+            adapter.weights.len()
+        );
+    }
+    Ok(())
+}
+
 /// (codec, sample_rate)
 pub fn load_codec(
     args: &Args,
@@ -191,3 +413,53 @@ pub fn load_codec(
         }
     }
 }
+
+/// The server's text normalization
+pub struct TextConfig {
+    /// `--text-steps`
+    pub pipeline: TextPipeline,
+    /// `--text-rules`, and each voice's `<voice>.rules.json`
+    pub rules: Option<Arc<RegexRules>>,
+    pub voice_rules: HashMap<String, Arc<RegexRules>>,
+    /// `--lexicon`, and each voice's `<voice>.lexicon.json`
+    pub lexicon: Lexicon,
+    pub voice_lexicons: HashMap<String, Lexicon>,
+    /// `--text-language`
+    pub language: String,
+    /// `--text-language` and `--abbreviations`
+    pub segmenter: Segmenter,
+}
+
+pub fn load_text_config(args: &Args) -> anyhow::Result<TextConfig> {
+    let rules = args
+        .text_rules
+        .as_deref()
+        .map(RegexRules::from_file)
+        .transpose()?
+        .map(Arc::new);
+    let default_lexicon = args.voice_dir.join("lexicon.json");
+    let lexicon = match &args.lexicon {
+        Some(path) => Lexicon::from_file(path)?,
+        None if default_lexicon.exists() => Lexicon::from_file(&default_lexicon)?,
+        None => Lexicon::default(),
+    };
+    let segmenter = Segmenter::for_language(&args.text_language)
+        .with_context(|| {
+            format!(
+                "Unknown --text-language {:?}; expected en, de, fr or es",
+                args.text_language
+            )
+        })?
+        .with_abbreviations(&args.abbreviations);
+    Ok(TextConfig {
+        pipeline: TextPipeline::from_steps(&args.text_steps)?,
+        rules,
+        voice_rules: load_voice_files(&args.voice_dir, ".rules.json", |path| {
+            RegexRules::from_file(path).map(Arc::new)
+        })?,
+        lexicon,
+        voice_lexicons: load_voice_files(&args.voice_dir, ".lexicon.json", Lexicon::from_file)?,
+        language: args.text_language.clone(),
+        segmenter,
+    })
+}
@@ -86,3 +86,27 @@ pub fn load_speaker_prompts(
 
     Ok((speakers, default_prompt))
 }
+
+/// Per-voice files in `voice_dir`, named `<voice><suffix>`, loaded by voice name
+pub fn load_voice_files<T>(
+    voice_dir: &Path,
+    suffix: &str,
+    load: impl Fn(&Path) -> anyhow::Result<T>,
+) -> anyhow::Result<HashMap<String, T>> {
+    let mut files = HashMap::new();
+    if !voice_dir.exists() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(voice_dir)? {
+        let path = entry?.path();
+        if let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|n| n.strip_suffix(suffix))
+            .filter(|name| !name.is_empty())
+        {
+            files.insert(name.to_string(), load(&path)?);
+        }
+    }
+    Ok(files)
+}
@@ -1,9 +1,11 @@
 use axum::{Json, extract::State};
 use candle_core::{DType, Device};
 use clap::Parser;
+use fish_speech_core::text::chunking::TokenBudget;
+use fish_speech_core::text::pause::PauseConfig;
 use server::handlers::speech::{GenerateRequest, generate_speech};
-use server::state::AppState;
-use server::utils::load::{Args, load_codec, load_lm};
+use server::state::{AppState, StagePools};
+use server::utils::load::{Args, load_codec, load_lm, load_text_config};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Semaphore;
@@ -44,6 +46,21 @@ async fn run_speech_test() -> anyhow::Result<()> {
     let dt = start_load.elapsed();
     info!("Models loaded in {:.2}s", dt.as_secs_f64());
 
+    let stage_pools = if device.is_cpu() {
+        Some(StagePools::new(args.vocoder_threads)?)
+    } else {
+        None
+    };
+    let text = load_text_config(&args)?;
+    let chunk_budget = TokenBudget::new(
+        args.max_chunk_tokens,
+        args.max_chunk_frames,
+        lm_state.model_type.frame_rate(),
+    );
+    // Overlapping the LM and the vocoder only pays off with CPU threads to spare
+    let pipeline_depth = args
+        .pipeline_depth
+        .unwrap_or(if device.is_cpu() { 2 } else { 0 });
     let state = Arc::new(AppState {
         lm: Arc::new(lm_state),
         codec: Arc::new(codec_state),
@@ -52,6 +69,25 @@ async fn run_speech_test() -> anyhow::Result<()> {
         sample_rate,
         voice_dir: args.voice_dir.clone(),
         concurrency: Arc::new(Semaphore::new(1)),
+        pipeline_depth,
+        vocoder_context_frames: args.vocoder_context_frames,
+        crossfade_ms: args.crossfade_ms,
+        pauses: PauseConfig {
+            sentence_ms: args.sentence_pause_ms,
+            paragraph_ms: args.paragraph_pause_ms,
+            marker_ms: args.break_pause_ms,
+        },
+        alignment_layers: args.alignment_layers.clone(),
+        chunking: args.chunking,
+        chunk_budget,
+        text_pipeline: text.pipeline,
+        text_rules: text.rules,
+        voice_text_rules: text.voice_rules,
+        lexicon: text.lexicon,
+        voice_lexicons: text.voice_lexicons,
+        text_language: text.language,
+        segmenter: text.segmenter,
+        stage_pools,
     });
 
     // First request: batch_size = 1
@@ -62,6 +98,7 @@ async fn run_speech_test() -> anyhow::Result<()> {
         response_format: Some("wav".to_string()),
         batch_size: None,
         speaker_prompt: None,
+        ..Default::default()
     };
 
     info!("Creating first request with batch_size=1");
@@ -84,6 +121,7 @@ async fn run_speech_test() -> anyhow::Result<()> {
         response_format: Some("wav".to_string()),
         batch_size: Some(1),
         speaker_prompt: None,
+        ..Default::default()
     };
 
     info!("Creating first request with batch_size=1");
@@ -106,6 +144,7 @@ async fn run_speech_test() -> anyhow::Result<()> {
         response_format: Some("wav".to_string()),
         batch_size: Some(4),
         speaker_prompt: None,
+        ..Default::default()
     };
 
     info!("Creating second request with batch_size=4");
@@ -8,15 +8,20 @@ pub use bytes::Bytes;
 use candle_core::{DType, Device};
 use clap::Parser;
 use fish_speech_core::config::WhichModel;
+use fish_speech_core::text::chunking::TokenBudget;
+use fish_speech_core::text::pause::PauseConfig;
 use fish_speech_core::text::{clean::preprocess_text, prompt::PromptEncoder};
 pub use futures_util::Stream;
 use server::handlers::{
+    dialogue::generate_dialogue,
     encode_speech::encode_speaker,
+    score::score_speech,
     speech::{generate_speech, server_lm_generate_blocking, vocode_semantic_tokens},
     supported_voices::get_supported_voices,
+    text::preview_text,
 };
-use server::state::AppState;
-use server::utils::load::{Args, load_codec, load_lm};
+use server::state::{AppState, StagePools};
+use server::utils::load::{Args, load_codec, load_lm, load_text_config};
 use std::sync::Arc;
 use std::time::Instant;
 use tokio::sync::Semaphore;
@@ -30,6 +35,7 @@ async fn run_warmup(state: Arc<AppState>) -> anyhow::Result<()> {
     info!("Starting warmup inference");
 
     let _permit = state.concurrency.clone().acquire_owned().await?;
+    state.lm.model.lock().await.set_lora_adapter(None)?;
 
     let prompt_encoder = PromptEncoder::new(
         &state.lm.tokenizer,
@@ -55,12 +61,14 @@ async fn run_warmup(state: Arc<AppState>) -> anyhow::Result<()> {
         anyhow::bail!("Warmup prompt generation produced no prompts");
     };
 
-    let (semantic_tokens, _) = server_lm_generate_blocking(
+    let (semantic_tokens, _, _) = server_lm_generate_blocking(
         state.clone(),
         first_prompt,
+        None,
         &state.lm.default_sampling_args,
         n_conditioning_tokens,
         false,
+        false,
     )
     .await?;
 
@@ -111,6 +119,21 @@ async fn main() -> anyhow::Result<()> {
     let dt = start_load.elapsed();
     info!("Models loaded in {:.2}s", dt.as_secs_f64());
 
+    let stage_pools = if device.is_cpu() {
+        Some(StagePools::new(args.vocoder_threads)?)
+    } else {
+        None
+    };
+    let text = load_text_config(&args)?;
+    let chunk_budget = TokenBudget::new(
+        args.max_chunk_tokens,
+        args.max_chunk_frames,
+        lm_state.model_type.frame_rate(),
+    );
+    // Overlapping the LM and the vocoder only pays off with CPU threads to spare
+    let pipeline_depth = args
+        .pipeline_depth
+        .unwrap_or(if device.is_cpu() { 2 } else { 0 });
     let state = Arc::new(AppState {
         lm: Arc::new(lm_state),
         codec: Arc::new(codec_state),
@@ -119,6 +142,25 @@ async fn main() -> anyhow::Result<()> {
         sample_rate,
         voice_dir: args.voice_dir.clone(),
         concurrency: Arc::new(Semaphore::new(1)),
+        pipeline_depth,
+        vocoder_context_frames: args.vocoder_context_frames,
+        crossfade_ms: args.crossfade_ms,
+        pauses: PauseConfig {
+            sentence_ms: args.sentence_pause_ms,
+            paragraph_ms: args.paragraph_pause_ms,
+            marker_ms: args.break_pause_ms,
+        },
+        alignment_layers: args.alignment_layers.clone(),
+        chunking: args.chunking,
+        chunk_budget,
+        text_pipeline: text.pipeline,
+        text_rules: text.rules,
+        voice_text_rules: text.voice_rules,
+        lexicon: text.lexicon,
+        voice_lexicons: text.voice_lexicons,
+        text_language: text.language,
+        segmenter: text.segmenter,
+        stage_pools,
     });
 
     if args.warmup {
@@ -128,7 +170,10 @@ async fn main() -> anyhow::Result<()> {
     // Create router
     let app = Router::new()
         .route("/v1/audio/speech", post(generate_speech))
+        .route("/v1/audio/dialogue", post(generate_dialogue))
         .route("/v1/audio/encoding", post(encode_speaker))
+        .route("/v1/audio/score", post(score_speech))
+        .route("/v1/text/preview", post(preview_text))
         .route("/v1/voices", get(get_supported_voices))
         .layer(DefaultBodyLimit::max(32 * 1024 * 1024))
         .layer(